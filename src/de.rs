@@ -1,13 +1,57 @@
-use std::{fmt, fs, io, path::Path};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt, io,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
 
-use serde::de;
+use serde::{de, Deserialize};
 
-/// A deserializer which automatically reads referenced files.
+use crate::{
+    expand::{self, Context, Expansion, Hint, Options, Scalar, Segment, State},
+    prefetch,
+    resolver::{is_valid_scheme, Resolvers},
+    Base64Alphabet, DependencyGraph, Error, Filesystem, Format, Listener, PathRedaction, Resolver,
+    SecretSource, Stats, SymlinkPolicy,
+};
+
+/// A deserializer which automatically reads referenced files and environment variables.
+///
+/// Files should be referenced like `${file:/path/to/file}` and environment variables like
+/// `${env:NAME}`. The `${` and `}` delimiters can be changed with
+/// [`Deserializer::with_delimiters`]. See the crate documentation for escaping rules.
+///
+/// The path of a file reference is everything between the first `:` and any parameters or default
+/// value, used verbatim without trimming whitespace. Windows paths like `${file:C:\secrets\token}`,
+/// `${file:C:/secrets/token}`, and `${file:\\server\share\token}` are therefore supported.
+///
+/// Values deserialized as bytes receive the raw contents of the file. All other values receive the
+/// contents decoded as UTF-8.
+///
+/// The contents of a reference are always passed to the target type as an owned string, even if
+/// the reference itself could have been borrowed from the input. Values without a reference are
+/// passed through unchanged, borrowed or not. As a result, a field of type `&'de str` fails to
+/// deserialize with an "invalid type" error if it contains a reference, while a
+/// `#[serde(borrow)] Cow<'de, str>` field receives `Cow::Owned` for a reference and `Cow::Borrowed`
+/// otherwise.
 ///
-/// Files should be referenced like `${file:/path/to/file}`.
+/// References are only resolved in values which are actually deserialized by the target type.
+/// Values which it ignores, such as those of unknown or skipped struct fields, are never resolved
+/// and are not reported to the listener.
+///
+/// In-place deserialization, which reuses the allocations of an existing value, is driven by the
+/// target type's `Deserialize::deserialize_in_place` through the same methods as normal
+/// deserialization, so references are resolved in the same way.
 pub struct Deserializer<'a, D, L> {
     de: D,
     listener: &'a mut L,
+    options: Arc<Options>,
+    resolvers: Resolvers,
+    prefetch: Vec<PathBuf>,
 }
 
 impl<'a, D, L> Deserializer<'a, D, L>
@@ -18,114 +62,1573 @@ where
     ///
     /// The listener will be called on every referenced file read along with the result of the read.
     pub fn new(de: D, listener: &'a mut L) -> Self {
-        Deserializer { de, listener }
+        Deserializer {
+            de,
+            listener,
+            options: Arc::default(),
+            resolvers: Resolvers::default(),
+            prefetch: vec![],
+        }
+    }
+
+    /// Creates a new deserializer which uses custom delimiters around references.
+    ///
+    /// Files will be referenced like `{open}file:/path/to/file{close}`. For example, with
+    /// delimiters of `@@(` and `)@@`, a file would be referenced like `@@(file:/path/to/file)@@`.
+    ///
+    /// Only the delimiters around the reference change. The scheme prefix, such as `file:`, and
+    /// the syntax of the rest of the reference are the same as with the default delimiters.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `open` is empty.
+    pub fn with_delimiters(de: D, listener: &'a mut L, open: &str, close: &str) -> Self {
+        Deserializer::new(de, listener).delimiters(open, close)
     }
 }
 
-macro_rules! forward_deserialize {
-    ($name:ident) => {forward_deserialize!($name, );};
+impl<'a, D, L> Deserializer<'a, D, L>
+where
+    L: Listener,
+{
+    /// Creates a new deserializer with shared options.
+    ///
+    /// This is equivalent to [`FileValueOptions::wrap`], and allows options shared between threads
+    /// to be used without copying them.
+    pub fn with_options(de: D, listener: &'a mut L, options: Arc<FileValueOptions>) -> Self {
+        options.wrap(de, listener)
+    }
+
+    /// Creates a new deserializer with a [`Listener`].
+    pub fn with_listener(de: D, listener: &'a mut L) -> Self {
+        Deserializer {
+            de,
+            listener,
+            options: Arc::default(),
+            resolvers: Resolvers::default(),
+            prefetch: vec![],
+        }
+    }
+}
+
+// The builder methods shared by `Deserializer` and `FileValueOptions`, which both store their
+// settings in an `options` field.
+macro_rules! option_builders {
+    () => {
+        fn options_mut(&mut self) -> &mut Options {
+            Arc::make_mut(&mut self.options)
+        }
+
+        /// Determines if references will be resolved at all.
+        ///
+        /// When disabled, every value is passed to the target type unchanged, including references
+        /// and the backslashes escaping them, so that the raw configuration can be inspected using
+        /// the same types and code path as normal deserialization. No files are read, the listener
+        /// is never called, and [`Deserializer::collect_references`] returns no references.
+        ///
+        /// Defaults to `true`.
+        pub fn enabled(mut self, enabled: bool) -> Self {
+            self.options_mut().enabled = enabled;
+            self
+        }
+
+        /// Sets the delimiters around references.
+        ///
+        /// See [`Deserializer::with_delimiters`] for details.
+        ///
+        /// # Panics
+        ///
+        /// Panics if `open` is empty.
+        pub fn delimiters(mut self, open: &str, close: &str) -> Self {
+            assert!(!open.is_empty(), "opening delimiter must not be empty");
+            self.options_mut().open = open.to_string();
+            self.options_mut().close = close.to_string();
+            self
+        }
+
+        /// Sets the delimiters around references to double braces, like `{{file:/path/to/file}}`.
+        ///
+        /// This is a shorthand for `delimiters("{{", "}}")`. A single `}` does not close a
+        /// reference, so it can appear within a reference's path or default value, or elsewhere in
+        /// an inline value.
+        pub fn double_braces(self) -> Self {
+            self.delimiters("{{", "}}")
+        }
+
+        /// Determines if file contents which are not valid UTF-8 will be decoded lossily rather
+        /// than producing an error.
+        ///
+        /// When enabled, invalid sequences are replaced with `U+FFFD REPLACEMENT CHARACTER`. The
+        /// listener still receives the unmodified bytes of the file, and values read as bytes are
+        /// unaffected.
+        ///
+        /// Defaults to `false`.
+        pub fn utf8_lossy(mut self, utf8_lossy: bool) -> Self {
+            self.options_mut().utf8_lossy = utf8_lossy;
+            self
+        }
+
+        /// Sets the handling of file paths in error messages.
+        ///
+        /// Paths can reveal details of the infrastructure a program runs on, so they can be hidden
+        /// or hashed in the messages of errors produced while resolving references, including
+        /// those of I/O errors produced by the access checks of options like
+        /// [`Deserializer::allowed_root`]. Since references contain paths, the text of references
+        /// included in error messages, like those of malformed references or reference cycles, is
+        /// redacted as well. The listener still receives the real path of each file, as does
+        /// [`Error::path`].
+        ///
+        /// Defaults to [`PathRedaction::Disabled`].
+        pub fn path_redaction(mut self, path_redaction: PathRedaction) -> Self {
+            self.options_mut().path_redaction = path_redaction;
+            self
+        }
+
+        /// Determines if a UTF-8 byte order mark at the start of a file will be removed.
+        ///
+        /// When enabled, a leading `U+FEFF BYTE ORDER MARK`, encoded as `EF BB BF`, is stripped
+        /// from file contents decoded as UTF-8, before any trimming. The listener still receives
+        /// the unmodified bytes of the file, and values read as bytes are unaffected.
+        ///
+        /// Defaults to `false`.
+        pub fn strip_bom(mut self, strip_bom: bool) -> Self {
+            self.options_mut().strip_bom = strip_bom;
+            self
+        }
+
+        /// Determines if a single trailing `\n` or `\r\n` will be stripped from file contents.
+        ///
+        /// Only contents decoded as text are trimmed. Values read as bytes, encoded values such as
+        /// `${file-base64:...}` and `${file-hex:...}`, and structured files always receive the
+        /// contents verbatim, since whitespace is significant in them. The listener still receives
+        /// the unmodified bytes of the file.
+        ///
+        /// Defaults to `false`.
+        pub fn trim_trailing_newline(mut self, trim_trailing_newline: bool) -> Self {
+            self.options_mut().trim_trailing_newline = trim_trailing_newline;
+            self
+        }
+
+        /// Determines if all leading and trailing whitespace will be stripped from file contents.
+        ///
+        /// Trimming is applied after the contents have been decoded as UTF-8, so it also applies to
+        /// contents decoded via [`Deserializer::utf8_lossy`]. It subsumes
+        /// [`Deserializer::trim_trailing_newline`], which has no additional effect when this is
+        /// enabled. Values read as bytes and encoded values such as `${file-base64:...}` are
+        /// unaffected, and the listener still receives the unmodified bytes of the file.
+        ///
+        /// Defaults to `false`.
+        pub fn trim(mut self, trim: bool) -> Self {
+            self.options_mut().trim = trim;
+            self
+        }
+
+        /// Determines if every `\n` and `\r` will be removed from file contents.
+        ///
+        /// This joins the lines of a file into a single value, which is useful for long tokens
+        /// that have been wrapped across multiple lines. Other whitespace is preserved. Lines are
+        /// joined after [`Deserializer::trim`] and [`Deserializer::trim_trailing_newline`] are
+        /// applied, so trimming still removes whitespace surrounding the entire value rather than
+        /// each line. Like trimming, only contents decoded as text are affected.
+        ///
+        /// Defaults to `false`.
+        pub fn join_lines(mut self, join_lines: bool) -> Self {
+            self.options_mut().join_lines = join_lines;
+            self
+        }
+
+        /// Determines if references embedded within larger strings will be resolved.
+        ///
+        /// When enabled, every reference in a string is replaced by its value, so `Bearer
+        /// ${file:/run/secrets/token}` resolves to `Bearer ` followed by the contents of the file.
+        /// Otherwise, a reference is only resolved if it makes up the entire string.
+        ///
+        /// Defaults to `false`.
+        pub fn inline(mut self, inline: bool) -> Self {
+            self.options_mut().inline = inline;
+            self
+        }
+
+        /// Determines if resolved values which are themselves references will be resolved as well.
+        ///
+        /// Reference cycles are detected and reported as errors.
+        ///
+        /// Defaults to `false`.
+        pub fn recursive(mut self, recursive: bool) -> Self {
+            self.options_mut().recursive = recursive;
+            self
+        }
+
+        /// Provides the contents of files in memory.
+        ///
+        /// References to these files will use the provided contents rather than reading from the
+        /// filesystem. All other files are read as normal. This can be combined with
+        /// [`Deserializer::collect_references`] to read files asynchronously before deserializing.
+        pub fn files(mut self, files: HashMap<PathBuf, Vec<u8>>) -> Self {
+            self.options_mut().files = files;
+            self
+        }
+
+        /// Sets the filesystem from which referenced files are read.
+        ///
+        /// See [`Filesystem`] for details.
+        ///
+        /// Defaults to the real filesystem.
+        pub fn filesystem<F>(mut self, filesystem: F) -> Self
+        where
+            F: Filesystem + 'static,
+        {
+            self.options_mut().filesystem = Some(Arc::new(filesystem));
+            self
+        }
+
+        /// Sets the maximum size of a referenced file in bytes.
+        ///
+        /// Files larger than this limit will produce an error rather than being read into memory.
+        /// The size of regular files is checked before reading, and reads of other files, such as
+        /// pipes or devices, are aborted once the limit is exceeded.
+        ///
+        /// Defaults to unlimited.
+        pub fn max_file_size(mut self, max_file_size: u64) -> Self {
+            self.options_mut().max_file_size = Some(max_file_size);
+            self
+        }
+
+        /// Sets the maximum total size in bytes of all files read during a single deserialization.
+        ///
+        /// A read which would cause the total to exceed this limit produces an error, and is
+        /// aborted once the limit is exceeded in the same way as with
+        /// [`Deserializer::max_file_size`]. Only files which are actually read count towards the
+        /// total, including files provided via [`Deserializer::files`]. References served from the
+        /// cache do not count.
+        ///
+        /// Defaults to unlimited.
+        pub fn max_total_bytes(mut self, max_total_bytes: u64) -> Self {
+            self.options_mut().max_total_bytes = Some(max_total_bytes);
+            self
+        }
+
+        /// Adds a directory which referenced files must be located within.
+        ///
+        /// Paths are canonicalized before being checked, so references cannot escape the directory
+        /// via `..` components or symlinks. This can be called multiple times to allow multiple
+        /// directories. A rejected reference is reported to the listener as a
+        /// [`PermissionDenied`](io::ErrorKind::PermissionDenied) error.
+        ///
+        /// Defaults to allowing all files.
+        pub fn allowed_root<P>(mut self, root: P) -> Self
+        where
+            P: AsRef<Path>,
+        {
+            self.options_mut()
+                .allowed_roots
+                .push(root.as_ref().to_path_buf());
+            self
+        }
+
+        /// Adds a source consulted by `${secret:NAME}` references.
+        ///
+        /// A secret reference tries each source in the order they were added and resolves to the
+        /// value provided by the first source which has one. A source which doesn't have the
+        /// secret, like an unset environment variable or a missing file, is skipped, but any other
+        /// error, like a file which cannot be read due to its permissions, is reported immediately.
+        /// If no source has the secret, the reference's default value is used if it has one, and an
+        /// error is reported otherwise. Secret names used with [`SecretSource::Dir`] cannot contain
+        /// path separators.
+        ///
+        /// Each source tried is reported to the listener: environment variables through
+        /// [`Listener::env_read`], and files through [`Listener::file_read`] as with any other
+        /// file, including the `NotFound` error of a missing file. Only the source providing the
+        /// value is reported through [`Listener::file_resolved`].
+        ///
+        /// The `secret` scheme is only recognized if at least one source has been added.
+        pub fn secret_source(mut self, source: SecretSource) -> Self {
+            self.options_mut().secret_sources.push(source);
+            self
+        }
+
+        /// Restricts referenced files to those with one of the specified extensions.
+        ///
+        /// Extensions are matched case-sensitively against the portion of the file name following
+        /// its last `.`, and may be specified with or without a leading `.`, like `"pem"` or
+        /// `".pem"`. Files without an extension are rejected unless an empty extension, `""`, is
+        /// included. A rejected reference is reported to the listener as a
+        /// [`PermissionDenied`](io::ErrorKind::PermissionDenied) error. Files provided via
+        /// [`Deserializer::files`] and descriptors read through [`Deserializer::fd_references`]
+        /// are not restricted.
+        ///
+        /// Defaults to allowing all extensions.
+        pub fn allowed_extensions(mut self, allowed_extensions: &[&str]) -> Self {
+            let allowed_extensions = allowed_extensions
+                .iter()
+                .map(|e| e.strip_prefix('.').unwrap_or(e).to_string())
+                .collect();
+            self.options_mut().allowed_extensions = Some(allowed_extensions);
+            self
+        }
+
+        /// Sets the handling of referenced files which are symbolic links.
+        ///
+        /// Only the referenced path itself is checked, before the file is opened; symbolic links in
+        /// the directories leading to it are always followed. With [`SymlinkPolicy::Deny`], a
+        /// reference to a symbolic link is rejected even if it is the start of a chain of links
+        /// which ends at a regular file. With [`SymlinkPolicy::WithinAllowedRoots`], the entire
+        /// chain is resolved and the final target must be within one of the directories added with
+        /// [`Deserializer::allowed_root`]. Note that the allowed roots are always checked against
+        /// the fully resolved path, regardless of this policy. A rejected reference is reported to
+        /// the listener as a [`PermissionDenied`](io::ErrorKind::PermissionDenied) error.
+        ///
+        /// This has no effect on files read from a custom [`Filesystem`] or on descriptors read
+        /// through [`Deserializer::fd_references`].
+        ///
+        /// Defaults to [`SymlinkPolicy::Follow`].
+        pub fn symlinks(mut self, symlinks: SymlinkPolicy) -> Self {
+            self.options_mut().symlinks = symlinks;
+            self
+        }
+
+        /// Sets the directory which relative file references are resolved against.
+        ///
+        /// Absolute references are unaffected. The listener is passed the resolved path. If
+        /// [`Deserializer::config_path`] is also set, a relative base directory is itself resolved
+        /// against the configuration file's directory, while an absolute one takes precedence.
+        ///
+        /// Defaults to the current working directory.
+        pub fn base_dir<P>(mut self, base_dir: P) -> Self
+        where
+            P: AsRef<Path>,
+        {
+            self.options_mut().base_dir = Some(base_dir.as_ref().to_path_buf());
+            self
+        }
+
+        /// Sets the path of the configuration file being deserialized.
+        ///
+        /// Relative file references are resolved against the directory containing the configuration
+        /// file rather than the current working directory, so a configuration file can be moved
+        /// along with the files it references. Absolute references are unaffected. See
+        /// [`Deserializer::base_dir`] for how the two interact.
+        ///
+        /// Defaults to none.
+        pub fn config_path<P>(mut self, config_path: P) -> Self
+        where
+            P: AsRef<Path>,
+        {
+            self.options_mut().config_dir = config_path.as_ref().parent().map(Path::to_path_buf);
+            self
+        }
+
+        /// Determines if errors resolving references will be prefixed with the location of the
+        /// value within the document, like `servers[0].password: error reading file ...`.
+        ///
+        /// This can be disabled when the location is reported by other means, for example when
+        /// composing with [`serde_path_to_error`](https://docs.rs/serde_path_to_error), which would
+        /// otherwise repeat it.
+        ///
+        /// Defaults to `true`.
+        pub fn error_locations(mut self, error_locations: bool) -> Self {
+            self.options_mut().error_locations = error_locations;
+            self
+        }
+
+        /// Enables fallback across multiple candidate paths in file references, separated by the
+        /// specified string.
+        ///
+        /// With a separator of `|`, a reference like `${file:/etc/app/token|/run/secrets/token}`
+        /// tries each path in order and uses the first file which can be read. Every candidate
+        /// tried is reported to the listener. If none of the candidates can be read, the
+        /// reference's default value is used if all of them are missing, and otherwise an error
+        /// listing the error of each candidate is produced. Errors processing a file which was
+        /// read, such as invalid UTF-8, are reported immediately rather than moving on to the next
+        /// candidate.
+        ///
+        /// Parameters and selections follow the last candidate and apply to all of them, like
+        /// `${file:/a|/b#L1}`. Candidates are supported by the `file`, `file-base64`, `file-hex`,
+        /// `file-base64-decode`, and `file-gz` schemes. All candidates are included in
+        /// [`Deserializer::collect_references`], so [`Deserializer::validate`] requires each of
+        /// them to be readable.
+        ///
+        /// Defaults to disabled.
+        ///
+        /// # Panics
+        ///
+        /// Panics if `separator` is empty.
+        pub fn candidate_separator(mut self, separator: &str) -> Self {
+            assert!(
+                !separator.is_empty(),
+                "candidate separator must not be empty"
+            );
+            self.options_mut().candidate_separator = Some(separator.to_string());
+            self
+        }
+
+        /// Determines if references to inherited file descriptors like `${fd:3}` will be resolved.
+        ///
+        /// This supports environments which pass secrets to a process as open file descriptors
+        /// rather than paths. The descriptor is read by opening its path, like `/dev/fd/3`, which
+        /// is also the path reported to the listener, so a descriptor which is not open produces
+        /// an error rather than being read. The descriptor itself is never closed. Whether the new
+        /// handle shares its position depends on the platform; on Linux, a regular file is read
+        /// from its start, while reading a pipe or socket consumes its contents on all platforms.
+        /// Parameters and selections are not supported, and since a descriptor can't be missing
+        /// like a file can, a default value is never used. Otherwise, the descriptor is read like
+        /// any other file, subject to the same restrictions and limits, such as
+        /// [`Deserializer::max_file_size`], [`Deserializer::read_timeout`], and
+        /// [`Deserializer::allowed_root`], which the descriptor's path must resolve within. The
+        /// exceptions are [`Deserializer::symlinks`] and [`Deserializer::allowed_extensions`],
+        /// since a descriptor's path is a symbolic link without an extension on platforms like
+        /// Linux; neither applies to a descriptor's path, whether it is referenced through the `fd`
+        /// scheme or as a file.
+        ///
+        /// A reference can name any descriptor of the process, including ones in use elsewhere in
+        /// the program, so this should only be enabled for trusted input. File descriptor
+        /// references are only supported on Unix, and produce an error on other platforms.
+        ///
+        /// Defaults to `false`.
+        pub fn fd_references(mut self, fd_references: bool) -> Self {
+            self.options_mut().fd_references = fd_references;
+            self
+        }
+
+        /// Determines if a file reference to `-`, like `${file:-}`, reads standard input.
+        ///
+        /// Following the Unix convention, this allows secrets to be piped into the process.
+        /// Standard input is read to its end and is reported to the listener with a pseudo-path of
+        /// `-`. It can only be referenced once per deserialization, and a second reference produces
+        /// an error since the input has already been consumed. Its contents are never cached, and
+        /// since standard input can't be missing like a file can, a default value is never used.
+        /// The `file`, `file-base64`, `file-hex`, `file-base64-decode`, and `file-gz` schemes
+        /// support standard input along with their parameters and selections. Structured, lines,
+        /// and dotenv file references always treat `-` as a path. [`Deserializer::max_file_size`]
+        /// and [`Deserializer::max_total_bytes`] apply, but other restrictions on files, such as
+        /// [`Deserializer::allowed_root`], do not. In particular, [`Deserializer::read_timeout`]
+        /// does not apply, since a blocked read of standard input can't be abandoned without
+        /// losing its contents. References to standard input are not included in
+        /// [`Deserializer::collect_references`].
+        ///
+        /// If standard input is an interactive terminal, reading it produces an error rather than
+        /// waiting for the user to type the contents and send an end-of-file.
+        ///
+        /// When disabled, `${file:-}` refers to a file named `-`.
+        ///
+        /// Defaults to `false`.
+        pub fn stdin_file(mut self, stdin_file: bool) -> Self {
+            self.options_mut().stdin_file = stdin_file;
+            self
+        }
+
+        /// Determines if reference schemes will be matched case-insensitively.
+        ///
+        /// When enabled, references like `${FILE:/path}` and `${File:/path}` are handled in the
+        /// same way as `${file:/path}`. This applies to the built-in schemes and those registered
+        /// with [`Deserializer::scheme`], but [`Resolver`]s receive the scheme as written. Only
+        /// ASCII letters are case-folded, and the remainder of the reference, such as a file path,
+        /// is unaffected.
+        ///
+        /// Defaults to `false`.
+        pub fn case_insensitive_schemes(mut self, case_insensitive_schemes: bool) -> Self {
+            self.options_mut().case_insensitive_schemes = case_insensitive_schemes;
+            self
+        }
+
+        /// Determines if the listener will be warned about files readable by their group or by
+        /// other users.
+        ///
+        /// Secret files are typically expected to be readable only by their owner. When enabled,
+        /// [`Listener::insecure_permissions`] is called for each file read from the filesystem
+        /// whose mode grants group or world read access. The file is still read and used as normal.
+        /// This has no effect on non-Unix platforms.
+        ///
+        /// Defaults to `false`.
+        pub fn warn_insecure_permissions(mut self, warn_insecure_permissions: bool) -> Self {
+            self.options_mut().warn_insecure_permissions = warn_insecure_permissions;
+            self
+        }
+
+        /// Sets a directory which referenced files are recommended to be located within.
+        ///
+        /// This is an advisory counterpart to [`Deserializer::allowed_root`], which can be used to
+        /// nudge configuration authors towards keeping secrets in a single place without breaking
+        /// existing configurations. [`Listener::outside_recommended_root`] is called for each file
+        /// read from the filesystem which is located outside of the directory, and the file is
+        /// still read and used as normal. Paths are canonicalized in the same way as for
+        /// [`Deserializer::allowed_root`] before being checked.
+        ///
+        /// Defaults to no recommended root.
+        pub fn recommended_root<P>(mut self, root: P) -> Self
+        where
+            P: AsRef<Path>,
+        {
+            self.options_mut().recommended_root = Some(root.as_ref().to_path_buf());
+            self
+        }
+
+        /// Determines if environment variables will be expanded in the paths of file references.
+        ///
+        /// When enabled, a path can contain `$VAR` or `${VAR}` references to environment variables,
+        /// like `${file:$HOME/.config/app/token}` or `${file:/run/secrets/${SERVICE}_token}`. A
+        /// `$VAR` reference extends over the longest run of ASCII alphanumerics and `_` following
+        /// the `$`, and the `${VAR}` form can be used to separate the name from following
+        /// characters. A `$` which isn't followed by a name is left unchanged, and `$$` produces a
+        /// literal `$`. Variables are expanded before the path is resolved against the base
+        /// directory, and an unset variable produces an error, even if the reference has a default
+        /// value.
+        ///
+        /// Since the `${VAR}` form contains the default `}` closing delimiter, it can't be used in
+        /// [inline](Deserializer::inline) references with the default delimiters.
+        ///
+        /// Defaults to `false`.
+        pub fn path_env_vars(mut self, path_env_vars: bool) -> Self {
+            self.options_mut().path_env_vars = path_env_vars;
+            self
+        }
+
+        /// Determines if the contents of files will be cached for the duration of the
+        /// deserialization.
+        ///
+        /// When enabled, a file referenced multiple times will only be read once, and later
+        /// references reuse the contents of the first successful read. Cache entries are keyed by
+        /// the resolved path of the file. The listener is only called for actual reads, not for
+        /// cache hits. Failed reads are not cached.
+        ///
+        /// Defaults to `false`.
+        pub fn cache(mut self, cache: bool) -> Self {
+            self.options_mut().cache = cache;
+            self
+        }
+
+        /// Determines if a referenced file which is empty will produce an error.
+        ///
+        /// A file is considered empty if its value is empty after any trimming, so with
+        /// [`Deserializer::trim`] enabled a file containing only whitespace is also rejected. This
+        /// catches secrets which have not been populated yet. The listener is still notified of the
+        /// successful read before the error is returned. Default values are not affected, so
+        /// `${file:/path:-}` still deserializes to an empty string if the file is missing.
+        ///
+        /// Defaults to `false`.
+        pub fn error_on_empty(mut self, error_on_empty: bool) -> Self {
+            self.options_mut().error_on_empty = error_on_empty;
+            self
+        }
+
+        /// Determines if references in map keys, struct field names, and enum variant names will be
+        /// resolved.
+        ///
+        /// Defaults to `false`.
+        pub fn expand_keys(mut self, expand_keys: bool) -> Self {
+            self.options_mut().expand_keys = expand_keys;
+            self
+        }
+
+        /// Sets the alphabet used to encode the contents of `${file-base64:...}` references and
+        /// decode the contents of `${file-base64-decode:...}` references.
+        ///
+        /// Defaults to [`Base64Alphabet::Standard`].
+        pub fn base64_alphabet(mut self, base64_alphabet: Base64Alphabet) -> Self {
+            self.options_mut().base64_alphabet = base64_alphabet;
+            self
+        }
+
+        /// Determines if the contents of `${file-hex:...}` references will be encoded with
+        /// uppercase rather than lowercase hex digits.
+        ///
+        /// Defaults to `false`.
+        pub fn hex_uppercase(mut self, hex_uppercase: bool) -> Self {
+            self.options_mut().hex_uppercase = hex_uppercase;
+            self
+        }
+
+        /// Determines if optional values referencing a file which does not exist will be
+        /// deserialized as `None` rather than producing an error.
+        ///
+        /// This only applies to values consisting of a single file reference without a default.
+        /// Since the value must be inspected before it is passed to the target type, this requires
+        /// the underlying format to support [`serde::Deserializer::deserialize_any`].
+        ///
+        /// Defaults to `false`.
+        pub fn missing_as_none(mut self, missing_as_none: bool) -> Self {
+            self.options_mut().missing_as_none = missing_as_none;
+            self
+        }
+
+        /// Determines if references in values deserialized as sequences will be passed to the
+        /// target type as sequences of bytes.
+        ///
+        /// Types like `Vec<u8>` and `[u8; N]` are deserialized as sequences rather than as bytes,
+        /// so by default a reference can't provide their contents. When this is enabled, such a
+        /// value receives the raw contents of the referenced file, one element per byte, without
+        /// UTF-8 decoding or trimming. This allows a `Vec<Vec<u8>>` to be populated from a list of
+        /// references like `["${file:a.der}", "${file:b.der}"]`.
+        ///
+        /// Since the input must be inspected before it is passed to sequence types, this requires
+        /// the underlying format to support [`deserialize_any`](de::Deserializer::deserialize_any).
+        ///
+        /// Defaults to `false`.
+        pub fn byte_sequences(mut self, byte_sequences: bool) -> Self {
+            self.options_mut().byte_sequences = byte_sequences;
+            self
+        }
+
+        /// Determines if file lines references like `${file-lines:/path}` will be resolved.
+        ///
+        /// A file lines reference expands to a sequence containing each line of the file as a
+        /// string, which allows a `Vec<String>`, or a tuple or array of strings, to be populated
+        /// from a file with one entry per line. Lines are separated by `\n`, and a `\r` preceding
+        /// it is removed. A single trailing newline does not produce an empty final line, but other
+        /// blank lines are preserved as empty strings, and an empty file produces an empty
+        /// sequence. If [`Deserializer::trim`] is enabled, each line is also trimmed. File lines
+        /// references must make up the entire value. A default value is split into lines in the
+        /// same way.
+        ///
+        /// Since the input must be inspected before it is passed to sequence types, this requires
+        /// the underlying format to support [`deserialize_any`](de::Deserializer::deserialize_any).
+        ///
+        /// Defaults to `false`.
+        pub fn file_lines(mut self, file_lines: bool) -> Self {
+            self.options_mut().file_lines = file_lines;
+            self
+        }
+
+        /// Determines if dotenv file references like `${file-dotenv:/path/.env}` will be resolved.
+        ///
+        /// A dotenv file reference expands to a map containing each `KEY=VALUE` entry of the file,
+        /// in order, which allows a `HashMap<String, String>` or a struct to be populated from an
+        /// environment file. Each line of the file is parsed as follows:
+        ///
+        /// * Surrounding whitespace is ignored, and blank lines and lines starting with `#` are
+        ///   skipped.
+        /// * An `export ` prefix is ignored.
+        /// * The key is the text before the first `=`, and the value is everything after it, so
+        ///   values may contain `=` without quoting. Whitespace around both is ignored.
+        /// * A value enclosed in single quotes is used literally, without the quotes.
+        /// * A value enclosed in double quotes has the escapes `\n`, `\r`, `\t`, `\"`, and `\\`
+        ///   processed, and other backslashes are kept.
+        /// * Other values are used as written, so a `#` or quote within them is part of the value.
+        ///
+        /// Quoted values can't span multiple lines or be followed by a comment, and a line without
+        /// a `=` or a key produces an error. Values aren't expanded further. Dotenv file
+        /// references must make up the entire value, and a default value is parsed in the same way
+        /// as the file.
+        ///
+        /// Since the input must be inspected before it is passed to map and struct types, this
+        /// requires the underlying format to support
+        /// [`deserialize_any`](de::Deserializer::deserialize_any).
+        ///
+        /// Defaults to `false`.
+        pub fn dotenv_files(mut self, dotenv_files: bool) -> Self {
+            self.options_mut().dotenv_files = dotenv_files;
+            self
+        }
+
+        /// Determines if references in values deserialized as primitives are parsed into the
+        /// requested type.
+        ///
+        /// When enabled, the expansion of a reference in a value deserialized as a `bool`, integer,
+        /// float, or `char` is parsed with the type's [`FromStr`](std::str::FromStr)
+        /// implementation, ignoring surrounding whitespace. This allows a `u16` field to be read
+        /// from a file containing `8080\n`, for example. An expansion which fails to parse produces
+        /// an error naming the requested type and the reason, but not the expansion itself since it
+        /// may be secret. Values without a reference, like the string `"8080"`, are passed to the
+        /// target type unchanged and are rejected as usual.
+        ///
+        /// Since the underlying format must pass a string to a primitive type, this requires the
+        /// format to support [`deserialize_any`](de::Deserializer::deserialize_any).
+        ///
+        /// Defaults to `false`.
+        pub fn typed_scalars(mut self, typed_scalars: bool) -> Self {
+            self.options_mut().typed_scalars = typed_scalars;
+            self
+        }
+
+        /// Determines if references in values deserialized as integers can have a unit suffix.
+        ///
+        /// When enabled along with [`Deserializer::typed_scalars`], the expansion of a reference in
+        /// a value deserialized as an integer can end with one of the following case-sensitive
+        /// suffixes, optionally separated from the number by whitespace, like `256MiB` or `30 s`:
+        ///
+        /// * `B` - bytes.
+        /// * `kB` or `KB`, `MB`, `GB`, `TB`, `PB`, and `EB` - decimal multiples of bytes, in powers
+        ///   of 1000.
+        /// * `KiB`, `MiB`, `GiB`, `TiB`, `PiB`, and `EiB` - binary multiples of bytes, in powers
+        ///   of 1024.
+        /// * `s`, `m`, `h`, `d`, and `w` - seconds, minutes, hours, days, and weeks, converted to
+        ///   seconds.
+        ///
+        /// The number must be an integer, so fractional values like `1.5GiB` are rejected. An
+        /// unknown suffix produces an error, as does a value which overflows the requested type
+        /// once multiplied, rather than being truncated or saturated. Values without a suffix are
+        /// parsed as usual.
+        ///
+        /// Defaults to `false`.
+        pub fn unit_suffixes(mut self, unit_suffixes: bool) -> Self {
+            self.options_mut().unit_suffixes = unit_suffixes;
+            self
+        }
+
+        /// Registers a format used to parse structured file references like `${file-json:/path}`.
+        ///
+        /// A reference with the scheme `file-` followed by `name` is parsed with the format and its
+        /// contents passed directly to the target type, which allows a file to provide a struct,
+        /// map, or sequence rather than just a string. Structured references must make up the
+        /// entire value. A default value is passed to the target type as a string. References
+        /// within the file's contents are not resolved.
+        ///
+        /// If the contents can't be parsed, or don't match the shape expected by the target type,
+        /// deserialization fails with an error naming the path of the file. Registering a format
+        /// with the same name as an existing one, ignoring case, replaces it.
+        ///
+        /// Since the input is inspected before it is passed to struct, map, sequence, and tuple
+        /// types once any format is registered, this requires the underlying format to support
+        /// [`serde::Deserializer::deserialize_any`]. The contents of the file are parsed into an
+        /// intermediate value, so the format must be self-describing as well.
+        pub fn format<F>(mut self, name: &str, format: F) -> Self
+        where
+            F: Format + Send + Sync + 'static,
+        {
+            let formats = &mut self.options_mut().formats;
+            formats.retain(|format, _| !format.eq_ignore_ascii_case(name));
+            formats.insert(name.to_string(), Arc::new(format));
+            self
+        }
+
+        /// Determines if references with an unknown scheme will produce an error rather than being
+        /// passed through unchanged.
+        ///
+        /// A value is considered to be a reference with an unknown scheme if it has the form
+        /// `${name:arg}` where `name` is a valid scheme name (see [`Deserializer::scheme`]) which
+        /// is not handled by the deserializer. With [`Deserializer::inline`] enabled, this applies
+        /// to each reference within a string.
+        ///
+        /// Defaults to `false`.
+        pub fn deny_unknown_schemes(mut self, deny_unknown_schemes: bool) -> Self {
+            self.options_mut().deny_unknown_schemes = deny_unknown_schemes;
+            self
+        }
+
+        /// Determines if the deserializer will run in dry-run mode.
+        ///
+        /// In dry-run mode, file references are reported to [`Listener::file_referenced`] and
+        /// replaced with a placeholder rather than being read. This allows the files referenced by
+        /// a configuration to be validated without reading their contents. Unlike
+        /// [`Deserializer::collect_references`], this works with any target type and format.
+        /// Environment variable references are resolved as normal.
+        ///
+        /// Defaults to `false`.
+        pub fn dry_run(mut self, dry_run: bool) -> Self {
+            self.options_mut().dry_run = dry_run;
+            self
+        }
+
+        /// Sets the placeholder which replaces file references in dry-run mode.
+        ///
+        /// Defaults to the text of the reference itself.
+        pub fn dry_run_placeholder(mut self, placeholder: &str) -> Self {
+            self.options_mut().dry_run_placeholder = Some(placeholder.to_string());
+            self
+        }
+
+        /// Sets the number of threads used to read files registered with
+        /// [`Deserializer::prefetch`].
+        ///
+        /// Defaults to the available parallelism reported by
+        /// [`std::thread::available_parallelism`].
+        pub fn prefetch_threads(mut self, prefetch_threads: usize) -> Self {
+            self.options_mut().prefetch_threads = Some(prefetch_threads);
+            self
+        }
+
+        /// Determines if malformed references will produce an error rather than being passed
+        /// through unchanged.
+        ///
+        /// See [`Listener::malformed_reference`] for the values considered to be malformed
+        /// references. The listener is notified of a malformed reference before the error is
+        /// returned.
+        ///
+        /// Defaults to `false`.
+        pub fn strict(mut self, strict: bool) -> Self {
+            self.options_mut().strict = strict;
+            self
+        }
+
+        /// Sets a timeout for each file read.
+        ///
+        /// A read which doesn't complete in time, like one of a FIFO with no writer or a file on an
+        /// unresponsive network filesystem, fails with an [`io::ErrorKind::TimedOut`] error which
+        /// is reported to the listener like any other read error.
+        ///
+        /// Each read is performed on a newly spawned helper thread, so configuring a timeout adds
+        /// the cost of spawning a thread to every file read, including reads of files registered
+        /// with [`Deserializer::prefetch`]. A helper thread whose read times out is not
+        /// interrupted; it continues in the background until the underlying read completes. Values
+        /// provided by [`Deserializer::files`] are not subject to the timeout.
+        ///
+        /// Defaults to no timeout.
+        pub fn read_timeout(mut self, read_timeout: Duration) -> Self {
+            self.options_mut().read_timeout = Some(read_timeout);
+            self
+        }
+
+        /// Sets the number of times a file read which fails with a transient error is retried.
+        ///
+        /// Secret files are often rotated by atomically renaming a new file into place, and a read
+        /// which races with the rotation can fail. The errors considered transient are those of
+        /// kind [`io::ErrorKind::NotFound`], [`io::ErrorKind::Interrupted`], and
+        /// [`io::ErrorKind::WouldBlock`]. All other errors, such as
+        /// [`io::ErrorKind::PermissionDenied`], fail immediately, and a transient error which
+        /// persists after the last retry is reported as normal. The listener is only notified of
+        /// the final result of the read.
+        ///
+        /// The first retry waits for [`Deserializer::read_retry_backoff`], and the wait doubles
+        /// before each subsequent one. A missing file is only retried when it would otherwise be
+        /// an error, so references with a default value (including `${file?:...}` references),
+        /// candidates other than the last, and optional values with
+        /// [`Deserializer::missing_as_none`] enabled fall back immediately. Values provided by
+        /// [`Deserializer::files`] are never retried.
+        ///
+        /// Defaults to 0.
+        pub fn read_retries(mut self, read_retries: u32) -> Self {
+            self.options_mut().read_retries = read_retries;
+            self
+        }
+
+        /// Sets the time waited before the first retry of a failed file read.
+        ///
+        /// See [`Deserializer::read_retries`] for details.
+        ///
+        /// Defaults to 10 milliseconds.
+        pub fn read_retry_backoff(mut self, read_retry_backoff: Duration) -> Self {
+            self.options_mut().read_retry_backoff = read_retry_backoff;
+            self
+        }
+
+        /// Sets the maximum number of nested references which will be resolved in recursive mode.
+        ///
+        /// Defaults to 8.
+        pub fn recursion_limit(mut self, recursion_limit: usize) -> Self {
+            self.options_mut().recursion_limit = recursion_limit;
+            self
+        }
+    };
+}
+
+impl<D, L> Deserializer<'_, D, L> {
+    option_builders!();
+
+    /// Sets a hook which transforms the value of every file reference decoded as text.
+    ///
+    /// The hook is called with the path of the file and its value after decoding and trimming, and
+    /// its output is passed to the target type as-is, without being trimmed again. This can be used
+    /// to decrypt secrets or strip surrounding markup. Values read as bytes are not passed to the
+    /// hook, while encoded values such as `${file-base64:...}` are passed in their encoded form.
+    /// Default values used in place of missing files are not passed to the hook.
+    ///
+    /// If the hook returns an error, deserialization fails with the provided message along with the
+    /// path of the file. [`Deserializer::error_on_empty`] applies to the output of the hook.
+    pub fn transform<F>(mut self, transform: F) -> Self
+    where
+        F: FnMut(&Path, String) -> Result<String, String> + 'static,
+    {
+        self.resolvers.transform = Some(Box::new(transform));
+        self
+    }
+
+    /// Sets a handler for file contents which are not valid UTF-8.
+    ///
+    /// The handler is called with the path of the file and its raw contents whenever they fail to
+    /// decode as UTF-8, and the string it returns is used in their place. This allows the contents
+    /// to be recovered on a per-file basis, for example by decoding them with a different
+    /// character set. The handler takes precedence over [`Deserializer::utf8_lossy`], which still
+    /// applies to values of other schemes.
+    ///
+    /// The handler runs before the contents are processed further, so its output is subject to
+    /// [`Deserializer::strip_bom`], the trimming options, and [`Deserializer::transform`] like any
+    /// other contents. Values read as bytes never need to be decoded and are not passed to the
+    /// handler.
+    ///
+    /// If the handler returns an error, deserialization fails with the provided message along with
+    /// the path of the file.
+    pub fn utf8_error_handler<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(&Path, Vec<u8>) -> Result<String, String> + 'static,
+    {
+        self.resolvers.utf8_error_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// Adds a resolver for custom reference schemes.
+    ///
+    /// This can be called multiple times to install a chain of resolvers, which are tried in the
+    /// order they were added. See [`Resolver`] for details.
+    pub fn resolver<R>(mut self, resolver: R) -> Self
+    where
+        R: Resolver + 'static,
+    {
+        self.resolvers.chain.push(Box::new(resolver));
+        self
+    }
+
+    /// Registers a handler for a reference scheme.
+    ///
+    /// References like `${name:arg}` are resolved by calling the handler with `arg`, and the
+    /// returned value is treated in the same way as one returned by a [`Resolver`]. Handlers take
+    /// precedence over the built-in schemes, so registering `file` or `env` replaces the built-in
+    /// handling, and the built-in schemes take precedence over a [`Resolver`]. Registering a scheme
+    /// a second time replaces its handler, as does registering a name which differs from an
+    /// existing one only in case.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is not a valid scheme name. Scheme names must be non-empty and consist of
+    /// ASCII letters, digits, `+`, `-`, `.`, and `_`.
+    pub fn scheme<F>(mut self, name: &str, handler: F) -> Self
+    where
+        F: FnMut(&str) -> io::Result<Vec<u8>> + 'static,
+    {
+        assert!(is_valid_scheme(name), "invalid scheme name `{name}`");
+        // a name may only be registered once regardless of case, so that lookups with
+        // `case_insensitive_schemes` are unambiguous
+        self.resolvers
+            .schemes
+            .retain(|scheme, _| !scheme.eq_ignore_ascii_case(name));
+        self.resolvers
+            .schemes
+            .insert(name.to_string(), Box::new(handler));
+        self
+    }
+
+    /// Registers files to be read in parallel before deserialization begins.
+    ///
+    /// Since deserialization is sequential, reading many referenced files one by one can be slow.
+    /// Prefetching reads the files on a pool of threads when deserialization begins, and the
+    /// results are used when the files are referenced. The paths are typically obtained by
+    /// running [`Deserializer::collect_references`] over the same input beforehand:
+    ///
+    /// ```no_run
+    /// use std::fs;
+    ///
+    /// use serde::Deserialize;
+    /// use serde_file_value::Deserializer;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Config {
+    ///     secret_value: String,
+    /// }
+    ///
+    /// let config = fs::read("conf/config.json").unwrap();
+    ///
+    /// let mut deserializer = serde_json::Deserializer::from_slice(&config);
+    /// let references = serde_file_value::collect_references(&mut deserializer).unwrap();
+    ///
+    /// let mut deserializer = serde_json::Deserializer::from_slice(&config);
+    /// let mut listener = |_: &_, _: &_| ();
+    /// let deserializer = Deserializer::new(&mut deserializer, &mut listener)
+    ///     .prefetch(references)
+    ///     .prefetch_threads(16);
+    /// let config = Config::deserialize(deserializer).unwrap();
+    /// ```
+    ///
+    /// Prefetched files are subject to the same restrictions as other reads. The listener's
+    /// [`Listener::before_file_read`] is consulted for each path before it is prefetched, and paths
+    /// it rejects are not read. It is consulted again when the file is referenced, and the listener
+    /// is notified of each read at that point rather than when it is prefetched. A
+    /// prefetched file is only used once, so later references to the same file read it again
+    /// unless [`Deserializer::cache`] is enabled. Prefetched files which are never referenced are
+    /// discarded.
+    pub fn prefetch<I>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = PathBuf>,
+    {
+        self.prefetch.extend(paths);
+        self
+    }
+}
+
+/// Reusable settings for [`Deserializer`]s.
+///
+/// `FileValueOptions` holds the settings of a deserializer, configured with the same methods as
+/// [`Deserializer`], independently of any underlying deserializer or listener. A single set of
+/// options can be configured once and then used to wrap any number of deserializers.
+///
+/// The settings are stored behind an [`Arc`] and are never modified once shared, so cloning a
+/// `FileValueOptions` and creating deserializers from it don't copy them. Configuring a clone
+/// copies the settings first, leaving the original unchanged. `FileValueOptions` is `Send` and
+/// `Sync`, so a single instance can be shared by threads deserializing concurrently, either by
+/// reference or with [`Deserializer::with_options`].
+///
+/// Handlers registered with [`Deserializer::transform`], [`Deserializer::utf8_error_handler`],
+/// [`Deserializer::resolver`], and [`Deserializer::scheme`], and files registered with
+/// [`Deserializer::prefetch`], are not part of the options and must be set on each deserializer.
+///
+/// ```
+/// use serde::Deserialize;
+/// use serde_file_value::FileValueOptions;
+/// # use std::{fs, path::Path, io};
+/// # use tempfile::NamedTempFile;
+///
+/// # if !cfg!(feature = "fs") { return; }
+/// # let file = NamedTempFile::new().unwrap();
+/// # fs::write(file.path(), "hunter2\n").unwrap();
+/// # let path = file.path().display().to_string();
+/// let options = FileValueOptions::new()
+///     .max_file_size(1024)
+///     .trim_trailing_newline(true);
+///
+/// let mut listener = |_: &Path, _: &io::Result<Vec<u8>>| {};
+/// for fragment in [format!(r#""${{file:{path}}}""#), r#""plain""#.to_string()] {
+///     let mut deserializer = serde_json::Deserializer::from_str(&fragment);
+///     let value = String::deserialize(options.wrap(&mut deserializer, &mut listener)).unwrap();
+///     # assert!(value == "hunter2" || value == "plain");
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct FileValueOptions {
+    options: Arc<Options>,
+}
+
+impl FileValueOptions {
+    /// Creates a new set of options with default settings.
+    pub fn new() -> Self {
+        FileValueOptions::default()
+    }
+
+    option_builders!();
+
+    /// Creates a deserializer with these options.
+    ///
+    /// The deserializer shares the settings rather than copying them.
+    pub fn wrap<'a, D, L>(&self, de: D, listener: &'a mut L) -> Deserializer<'a, D, L>
+    where
+        L: Listener,
+    {
+        Deserializer {
+            de,
+            listener,
+            options: self.options.clone(),
+            resolvers: Resolvers::default(),
+            prefetch: vec![],
+        }
+    }
+}
+
+impl<'de, D, L> Deserializer<'_, D, L>
+where
+    D: de::Deserializer<'de>,
+    L: Listener,
+{
+    /// Returns the paths of all files referenced by the input without reading them.
+    ///
+    /// The entire input is traversed via [`serde::Deserializer::deserialize_any`], so this only
+    /// works with self-describing formats. Environment variable references are resolved as normal.
+    /// References in map keys are only included if [`Deserializer::expand_keys`] is enabled.
+    ///
+    /// This makes it possible to read the referenced files in a separate step, for example
+    /// asynchronously, and then provide their contents to a second deserialization with
+    /// [`Deserializer::files`].
+    pub fn collect_references(self) -> Result<BTreeSet<PathBuf>, D::Error> {
+        let mut state = State {
+            references: Some(BTreeSet::new()),
+            ..State::default()
+        };
+        self.with_state(&mut state, |d| Walk::deserialize(d))?;
+
+        Ok(state.references.unwrap_or_default())
+    }
+
+    /// Returns the text of every reference in the input, in the order they appear, without
+    /// resolving them.
+    ///
+    /// Unlike [`Deserializer::collect_references`], references of every scheme are included, none
+    /// of them are resolved, and the reference is returned as written, like
+    /// `${file:/path:-default}` or `${env:NAME}`. A reference appearing multiple times is included
+    /// each time. References which are escaped, or whose scheme is not handled, are not included.
+    /// References within the contents of files are not included even if [`Deserializer::recursive`]
+    /// is enabled, since the files are not read.
+    ///
+    /// The entire input is traversed via [`serde::Deserializer::deserialize_any`], so this only
+    /// works with self-describing formats. As in normal deserialization, references in map keys are
+    /// only included if [`Deserializer::expand_keys`] is enabled, and are otherwise treated as
+    /// literal keys.
+    pub fn scan_references(self) -> Result<Vec<String>, D::Error> {
+        let mut state = State {
+            scanned: Some(vec![]),
+            ..State::default()
+        };
+        self.with_state(&mut state, |d| Walk::deserialize(d))?;
+
+        Ok(state.scanned.unwrap_or_default())
+    }
+
+    /// Checks that all files referenced by the input exist and can be read, returning their paths.
+    ///
+    /// The input is traversed in the same way as [`Deserializer::collect_references`], and each
+    /// referenced file is then opened and checked to not be a directory, without reading its
+    /// contents. This is a cheap way to detect missing or inaccessible files before deserializing
+    /// a configuration. Files provided via [`Deserializer::files`] are always considered readable,
+    /// and a custom [`Filesystem`] is checked with [`Filesystem::exists`]. The allowed roots are
+    /// enforced, but size limits are not.
+    ///
+    /// Environment variable references and references handled by a [`Resolver`] or a registered
+    /// scheme are resolved as normal, so a missing environment variable without a default value
+    /// produces an error. References with default values are still checked, since the default only
+    /// applies to files which are actually missing.
+    ///
+    /// If a file is not readable, the returned [`Error`] provides its path and the [`io::Error`]
+    /// produced when checking it. The files are checked in sorted order, and checking stops at the
+    /// first failure.
+    pub fn validate(self) -> Result<Vec<PathBuf>, Error<D::Error>> {
+        let options = self.options.clone();
+        let references = self.collect_references().map_err(|e| Error::new(e, None))?;
+
+        for path in &references {
+            if let Err(e) = expand::check_readable(&options, path) {
+                let error = de::Error::custom(format_args!(
+                    "error reading file {}: {e}",
+                    expand::redact_path(&options, path).display()
+                ));
+                return Err(Error::new(error, Some((path.clone(), e))));
+            }
+        }
+
+        Ok(references.into_iter().collect())
+    }
+
+    /// Deserializes a value, returning an error with structured information about failed file
+    /// reads.
+    ///
+    /// If deserialization fails after a file could not be read, the returned [`Error`] provides
+    /// the path of the file and the original [`io::Error`]. If multiple file reads failed, the
+    /// information refers to the most recent one.
+    pub fn deserialize_with_error<T>(self) -> Result<T, Error<D::Error>>
+    where
+        T: Deserialize<'de>,
+    {
+        let mut state = State::default();
+        self.with_state(&mut state, |d| T::deserialize(d))
+            .map_err(|e| Error::new(e, state.file_error.take()))
+    }
+
+    /// Deserializes a value, returning it along with statistics about the files read.
+    pub fn deserialize_with_stats<T>(self) -> Result<(T, Stats), D::Error>
+    where
+        T: Deserialize<'de>,
+    {
+        let mut state = State::default();
+        let value = self.with_state(&mut state, |d| T::deserialize(d))?;
+        Ok((value, state.stats))
+    }
+
+    /// Deserializes a value, returning it along with the graph of referenced files.
+    ///
+    /// The graph records the files referenced directly by the input, and, when
+    /// [`Deserializer::recursive`] is enabled, the files referenced by the contents of each file.
+    /// This can be used to watch every file a configuration transitively depends on.
+    pub fn deserialize_with_graph<T>(self) -> Result<(T, DependencyGraph), D::Error>
+    where
+        T: Deserialize<'de>,
+    {
+        let mut state = State {
+            graph: Some(DependencyGraph::default()),
+            ..State::default()
+        };
+        let value = self.with_state(&mut state, |d| T::deserialize(d))?;
+        Ok((value, state.graph.unwrap_or_default()))
+    }
+
+    fn with_state<F, R>(self, state: &mut State, f: F) -> R
+    where
+        F: FnOnce(ValueDeserializer<'_, D, L>) -> R,
+    {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("serde_file_value::deserialize").entered();
+
+        if self.options.enabled
+            && state.references.is_none()
+            && state.scanned.is_none()
+            && !self.prefetch.is_empty()
+        {
+            let threads = self
+                .options
+                .prefetch_threads
+                .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+            // rejected paths are left for the read of the reference itself to report
+            let paths = self
+                .prefetch
+                .iter()
+                .filter(|path| self.listener.before_file_read(path).is_ok())
+                .cloned()
+                .collect::<Vec<_>>();
+            state.prefetched = prefetch::prefetch(&self.options, &paths, threads);
+        }
+
+        let mut resolvers = self.resolvers;
+        let deserializer = ValueDeserializer {
+            de: self.de,
+            ctx: Context {
+                listener: self.listener,
+                options: &self.options,
+                resolvers: &mut resolvers,
+                state,
+            },
+            key: false,
+        };
+        f(deserializer)
+    }
+}
+
+macro_rules! delegate_deserialize {
+    ($name:ident) => {delegate_deserialize!($name, );};
     ($name:ident, $($arg:tt => $ty:ty),*) => {
         fn $name<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, D::Error>
             where V: de::Visitor<'de>
         {
+            self.with_state(&mut State::default(), |d| d.$name($($arg,)* visitor))
+        }
+    }
+}
+
+impl<'de, D, L> de::Deserializer<'de> for Deserializer<'_, D, L>
+where
+    D: de::Deserializer<'de>,
+    L: Listener,
+{
+    type Error = D::Error;
+
+    delegate_deserialize!(deserialize_any);
+    delegate_deserialize!(deserialize_bool);
+    delegate_deserialize!(deserialize_u8);
+    delegate_deserialize!(deserialize_u16);
+    delegate_deserialize!(deserialize_u32);
+    delegate_deserialize!(deserialize_u64);
+    delegate_deserialize!(deserialize_i8);
+    delegate_deserialize!(deserialize_i16);
+    delegate_deserialize!(deserialize_i32);
+    delegate_deserialize!(deserialize_i64);
+    delegate_deserialize!(deserialize_f32);
+    delegate_deserialize!(deserialize_f64);
+    delegate_deserialize!(deserialize_char);
+    delegate_deserialize!(deserialize_str);
+    delegate_deserialize!(deserialize_string);
+    delegate_deserialize!(deserialize_unit);
+    delegate_deserialize!(deserialize_option);
+    delegate_deserialize!(deserialize_seq);
+    delegate_deserialize!(deserialize_bytes);
+    delegate_deserialize!(deserialize_byte_buf);
+    delegate_deserialize!(deserialize_map);
+    delegate_deserialize!(deserialize_unit_struct, name => &'static str);
+    delegate_deserialize!(deserialize_newtype_struct, name => &'static str);
+    delegate_deserialize!(deserialize_tuple_struct, name => &'static str, len => usize);
+    delegate_deserialize!(deserialize_struct,
+                          name => &'static str,
+                          fields => &'static [&'static str]);
+    delegate_deserialize!(deserialize_identifier);
+    delegate_deserialize!(deserialize_tuple, len => usize);
+    delegate_deserialize!(deserialize_enum,
+                          name => &'static str,
+                          variants => &'static [&'static str]);
+    delegate_deserialize!(deserialize_ignored_any);
+}
+
+struct ValueDeserializer<'a, D, L> {
+    de: D,
+    ctx: Context<'a, L>,
+    /// Set when deserializing a map key or enum variant.
+    key: bool,
+}
+
+macro_rules! forward_deserialize {
+    ($name:ident $(, $arg:tt => $ty:ty)*) => {forward_deserialize!($name[Any] $(, $arg => $ty)*);};
+    ($name:ident[$hint:ident] $(, $arg:tt => $ty:ty)*) => {
+        fn $name<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, D::Error>
+            where V: de::Visitor<'de>
+        {
+            let visitor = Visitor {
+                visitor,
+                ctx: self.ctx,
+                hint: if self.key { Hint::Key } else { Hint::$hint },
+            };
+            self.de.$name($($arg,)* visitor)
+        }
+    }
+}
+
+/// Like `forward_deserialize!`, but forwards to `deserialize_any` when typed scalars are enabled so
+/// that a string containing a reference is passed to the visitor.
+macro_rules! forward_deserialize_scalar {
+    ($name:ident, $scalar:ident) => {
+        fn $name<V>(self, visitor: V) -> Result<V::Value, D::Error>
+        where
+            V: de::Visitor<'de>,
+        {
+            let typed_scalars = self.ctx.options.typed_scalars && !self.key;
+            let visitor = Visitor {
+                visitor,
+                ctx: self.ctx,
+                hint: match (self.key, typed_scalars) {
+                    (true, _) => Hint::Key,
+                    (false, true) => Hint::Scalar(Scalar::$scalar),
+                    (false, false) => Hint::Any,
+                },
+            };
+            if typed_scalars {
+                self.de.deserialize_any(visitor)
+            } else {
+                self.de.$name(visitor)
+            }
+        }
+    };
+}
+
+/// Like `forward_deserialize!`, but forwards to `deserialize_any` when structured file formats are
+/// registered or dotenv file references are enabled so that a string containing one is passed to
+/// the visitor.
+macro_rules! forward_deserialize_structured {
+    ($name:ident $(, $arg:tt => $ty:ty)*) => {
+        fn $name<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, D::Error>
+            where V: de::Visitor<'de>
+        {
+            let structured_files =
+                !self.ctx.options.formats.is_empty() || self.ctx.options.dotenv_files;
+            let visitor = Visitor {
+                visitor,
+                ctx: self.ctx,
+                hint: if self.key { Hint::Key } else { Hint::Any },
+            };
+            if structured_files {
+                self.de.deserialize_any(visitor)
+            } else {
+                self.de.$name($($arg,)* visitor)
+            }
+        }
+    }
+}
+
+/// Like `forward_deserialize!`, but forwards to `deserialize_any` when structured file formats are
+/// registered or byte sequences or file lines references are enabled so that a string containing a
+/// reference is passed to the visitor.
+macro_rules! forward_deserialize_seq {
+    ($name:ident $(, $arg:tt => $ty:ty)*) => {
+        fn $name<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, D::Error>
+            where V: de::Visitor<'de>
+        {
+            let options = self.ctx.options;
             let visitor = Visitor {
                 visitor,
-                listener: self.listener,
+                ctx: self.ctx,
+                hint: match (self.key, options.byte_sequences) {
+                    (true, _) => Hint::Key,
+                    (false, true) => Hint::Seq,
+                    (false, false) => Hint::Any,
+                },
             };
-            self.de.$name($($arg,)* visitor)
+            if !options.formats.is_empty() || options.byte_sequences || options.file_lines {
+                self.de.deserialize_any(visitor)
+            } else {
+                self.de.$name($($arg,)* visitor)
+            }
         }
     }
 }
 
-impl<'a, 'de, D, L> de::Deserializer<'de> for Deserializer<'a, D, L>
+impl<'de, D, L> de::Deserializer<'de> for ValueDeserializer<'_, D, L>
 where
     D: de::Deserializer<'de>,
-    L: FnMut(&Path, &io::Result<Vec<u8>>),
+    L: Listener,
 {
     type Error = D::Error;
 
     forward_deserialize!(deserialize_any);
-    forward_deserialize!(deserialize_bool);
-    forward_deserialize!(deserialize_u8);
-    forward_deserialize!(deserialize_u16);
-    forward_deserialize!(deserialize_u32);
-    forward_deserialize!(deserialize_u64);
-    forward_deserialize!(deserialize_i8);
-    forward_deserialize!(deserialize_i16);
-    forward_deserialize!(deserialize_i32);
-    forward_deserialize!(deserialize_i64);
-    forward_deserialize!(deserialize_f32);
-    forward_deserialize!(deserialize_f64);
-    forward_deserialize!(deserialize_char);
+    forward_deserialize_scalar!(deserialize_bool, Bool);
+    forward_deserialize_scalar!(deserialize_u8, U8);
+    forward_deserialize_scalar!(deserialize_u16, U16);
+    forward_deserialize_scalar!(deserialize_u32, U32);
+    forward_deserialize_scalar!(deserialize_u64, U64);
+    forward_deserialize_scalar!(deserialize_i8, I8);
+    forward_deserialize_scalar!(deserialize_i16, I16);
+    forward_deserialize_scalar!(deserialize_i32, I32);
+    forward_deserialize_scalar!(deserialize_i64, I64);
+    forward_deserialize_scalar!(deserialize_f32, F32);
+    forward_deserialize_scalar!(deserialize_f64, F64);
+    forward_deserialize_scalar!(deserialize_char, Char);
     forward_deserialize!(deserialize_str);
     forward_deserialize!(deserialize_string);
     forward_deserialize!(deserialize_unit);
     forward_deserialize!(deserialize_option);
-    forward_deserialize!(deserialize_seq);
-    forward_deserialize!(deserialize_bytes);
-    forward_deserialize!(deserialize_byte_buf);
-    forward_deserialize!(deserialize_map);
+    forward_deserialize!(deserialize_bytes[Bytes]);
+    forward_deserialize!(deserialize_byte_buf[Bytes]);
+    forward_deserialize_structured!(deserialize_map);
     forward_deserialize!(deserialize_unit_struct, name => &'static str);
     forward_deserialize!(deserialize_newtype_struct, name => &'static str);
-    forward_deserialize!(deserialize_tuple_struct, name => &'static str, len => usize);
-    forward_deserialize!(deserialize_struct,
-                         name => &'static str,
-                         fields => &'static [&'static str]);
-    forward_deserialize!(deserialize_identifier);
-    forward_deserialize!(deserialize_tuple, len => usize);
+    forward_deserialize_structured!(deserialize_struct,
+                                    name => &'static str,
+                                    fields => &'static [&'static str]);
+    forward_deserialize!(deserialize_identifier[Key]);
     forward_deserialize!(deserialize_enum,
                          name => &'static str,
                          variants => &'static [&'static str]);
-    forward_deserialize!(deserialize_ignored_any);
+
+    forward_deserialize_seq!(deserialize_seq);
+    // Arrays like `[u8; N]` are deserialized as tuples, so they receive byte sequences as well.
+    forward_deserialize_seq!(deserialize_tuple, len => usize);
+    forward_deserialize_seq!(deserialize_tuple_struct, name => &'static str, len => usize);
+
+    // Ignored values are passed directly to the visitor so that references within them are never
+    // resolved, even by formats which implement this by calling `deserialize_any`.
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, D::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.de.deserialize_ignored_any(visitor)
+    }
 }
 
 struct Visitor<'a, V, L> {
     visitor: V,
-    listener: &'a mut L,
+    ctx: Context<'a, L>,
+    hint: Hint,
 }
 
 macro_rules! forward_visit {
     ($name:ident, $ty:ty) => {
-        fn $name<E>(self, v: $ty) -> Result<V::Value, E>
+        fn $name<E>(mut self, v: $ty) -> Result<V::Value, E>
         where
             E: de::Error,
         {
+            self.record_key(&v);
             self.visitor.$name(v)
         }
     };
 }
 
-impl<V, L> Visitor<'_, V, L>
+impl<'de, V, L> Visitor<'_, V, L>
 where
-    L: FnMut(&Path, &io::Result<Vec<u8>>),
+    V: de::Visitor<'de>,
+    L: Listener,
 {
-    fn expand_str<E>(&mut self, s: &str) -> Result<Option<String>, E>
+    /// Records a visited map key as part of the location of the following value.
+    fn record_key(&mut self, key: &dyn fmt::Display) {
+        if self.hint == Hint::Key {
+            self.ctx.set_key(key);
+        }
+    }
+
+    fn visit_expansion<E>(self, expansion: Expansion) -> Result<V::Value, E>
     where
         E: de::Error,
     {
-        match s.strip_prefix("${file:").and_then(|s| s.strip_suffix('}')) {
-            Some(path) => {
-                let value = fs::read(path);
-                (self.listener)(path.as_ref(), &value);
-                match value {
-                    Ok(contents) => {
-                        let contents = String::from_utf8(contents).map_err(|e| {
-                            E::custom(format_args!("error parsing file {path}: {e}"))
-                        })?;
-                        Ok(Some(contents))
-                    }
-                    Err(e) => Err(E::custom(format_args!("error reading file {path}: {e}"))),
+        match expansion {
+            Expansion::String(s) => match self.hint {
+                Hint::Scalar(scalar) => {
+                    let unit_suffixes = self.ctx.options.unit_suffixes;
+                    visit_scalar(s.trim(), scalar, unit_suffixes, self.visitor)
                 }
-            }
-            None => Ok(None),
+                _ => self.visitor.visit_string(s),
+            },
+            Expansion::Bytes(b) if self.hint == Hint::Seq => self
+                .visitor
+                .visit_seq(de::value::SeqDeserializer::new(b.into_iter())),
+            Expansion::Bytes(b) => self.visitor.visit_byte_buf(b),
+        }
+    }
+}
+
+/// Parses the expansion of a reference into the requested primitive type.
+fn visit_scalar<'de, V, E>(
+    s: &str,
+    scalar: Scalar,
+    unit_suffixes: bool,
+    visitor: V,
+) -> Result<V::Value, E>
+where
+    V: de::Visitor<'de>,
+    E: de::Error,
+{
+    fn parse<T, E>(s: &str, scalar: Scalar) -> Result<T, E>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+        E: de::Error,
+    {
+        s.parse().map_err(|e| {
+            E::custom(format_args!(
+                "error parsing referenced value as {scalar}: {e}"
+            ))
+        })
+    }
+
+    fn parse_int<T, E>(s: &str, scalar: Scalar, unit_suffixes: bool) -> Result<T, E>
+    where
+        T: FromStr + TryFrom<i128>,
+        T::Err: fmt::Display,
+        E: de::Error,
+    {
+        let split = s
+            .find(|c: char| !c.is_ascii_digit() && c != '-' && c != '+')
+            .unwrap_or(s.len());
+        let (number, suffix) = s.split_at(split);
+        let suffix = suffix.trim_start();
+        if !unit_suffixes || suffix.is_empty() {
+            return parse(s, scalar);
         }
+
+        let error = |reason: &dyn fmt::Display| {
+            E::custom(format_args!(
+                "error parsing referenced value as {scalar}: {reason}"
+            ))
+        };
+        let multiplier = unit_multiplier(suffix).ok_or_else(|| error(&"unknown unit suffix"))?;
+        let number = number.parse::<i128>().map_err(|e| error(&e))?;
+        number
+            .checked_mul(multiplier)
+            .and_then(|value| T::try_from(value).ok())
+            .ok_or_else(|| error(&"value out of range"))
+    }
+
+    match scalar {
+        Scalar::Bool => visitor.visit_bool(parse(s, scalar)?),
+        Scalar::I8 => visitor.visit_i8(parse_int(s, scalar, unit_suffixes)?),
+        Scalar::I16 => visitor.visit_i16(parse_int(s, scalar, unit_suffixes)?),
+        Scalar::I32 => visitor.visit_i32(parse_int(s, scalar, unit_suffixes)?),
+        Scalar::I64 => visitor.visit_i64(parse_int(s, scalar, unit_suffixes)?),
+        Scalar::U8 => visitor.visit_u8(parse_int(s, scalar, unit_suffixes)?),
+        Scalar::U16 => visitor.visit_u16(parse_int(s, scalar, unit_suffixes)?),
+        Scalar::U32 => visitor.visit_u32(parse_int(s, scalar, unit_suffixes)?),
+        Scalar::U64 => visitor.visit_u64(parse_int(s, scalar, unit_suffixes)?),
+        Scalar::F32 => visitor.visit_f32(parse(s, scalar)?),
+        Scalar::F64 => visitor.visit_f64(parse(s, scalar)?),
+        Scalar::Char => visitor.visit_char(parse(s, scalar)?),
     }
 }
 
+/// Returns the multiplier of an integer unit suffix.
+fn unit_multiplier(suffix: &str) -> Option<i128> {
+    let multiplier = match suffix {
+        "B" => 1,
+        "kB" | "KB" => 1000,
+        "MB" => 1000_i128.pow(2),
+        "GB" => 1000_i128.pow(3),
+        "TB" => 1000_i128.pow(4),
+        "PB" => 1000_i128.pow(5),
+        "EB" => 1000_i128.pow(6),
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        "TiB" => 1 << 40,
+        "PiB" => 1 << 50,
+        "EiB" => 1 << 60,
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(multiplier)
+}
+
 impl<'de, V, L> de::Visitor<'de> for Visitor<'_, V, L>
 where
     V: de::Visitor<'de>,
-    L: FnMut(&Path, &io::Result<Vec<u8>>),
+    L: Listener,
 {
     type Value = V::Value;
 
@@ -145,15 +1648,18 @@ where
     forward_visit!(visit_f32, f32);
     forward_visit!(visit_f64, f64);
     forward_visit!(visit_char, char);
-    forward_visit!(visit_bytes, &[u8]);
-    forward_visit!(visit_byte_buf, Vec<u8>);
 
     fn visit_str<E>(mut self, v: &str) -> Result<Self::Value, E>
     where
         E: de::Error,
     {
-        match self.expand_str(v)? {
-            Some(s) => self.visitor.visit_string(s),
+        self.record_key(&v);
+        if self.ctx.is_structured(v, self.hint) {
+            return self.ctx.deserialize_structured(v, self.visitor);
+        }
+
+        match self.ctx.expand_str(v, self.hint)? {
+            Some(expansion) => self.visit_expansion(expansion),
             None => self.visitor.visit_str(v),
         }
     }
@@ -162,8 +1668,13 @@ where
     where
         E: de::Error,
     {
-        match self.expand_str(&v)? {
-            Some(s) => self.visitor.visit_string(s),
+        self.record_key(&v);
+        if self.ctx.is_structured(&v, self.hint) {
+            return self.ctx.deserialize_structured(&v, self.visitor);
+        }
+
+        match self.ctx.expand_str(&v, self.hint)? {
+            Some(expansion) => self.visit_expansion(expansion),
             None => self.visitor.visit_string(v),
         }
     }
@@ -172,12 +1683,47 @@ where
     where
         E: de::Error,
     {
-        match self.expand_str(v)? {
-            Some(s) => self.visitor.visit_string(s),
+        self.record_key(&v);
+        if self.ctx.is_structured(v, self.hint) {
+            return self.ctx.deserialize_structured(v, self.visitor);
+        }
+
+        match self.ctx.expand_str(v, self.hint)? {
+            Some(expansion) => self.visit_expansion(expansion),
             None => self.visitor.visit_borrowed_str(v),
         }
     }
 
+    fn visit_bytes<E>(mut self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        match self.ctx.expand_bytes(v, self.hint)? {
+            Some(expansion) => self.visit_expansion(expansion),
+            None => self.visitor.visit_bytes(v),
+        }
+    }
+
+    fn visit_byte_buf<E>(mut self, v: Vec<u8>) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        match self.ctx.expand_bytes(&v, self.hint)? {
+            Some(expansion) => self.visit_expansion(expansion),
+            None => self.visitor.visit_byte_buf(v),
+        }
+    }
+
+    fn visit_borrowed_bytes<E>(mut self, v: &'de [u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        match self.ctx.expand_bytes(v, self.hint)? {
+            Some(expansion) => self.visit_expansion(expansion),
+            None => self.visitor.visit_borrowed_bytes(v),
+        }
+    }
+
     fn visit_unit<E>(self) -> Result<V::Value, E>
     where
         E: de::Error,
@@ -196,9 +1742,18 @@ where
     where
         D: de::Deserializer<'de>,
     {
-        let deserializer = Deserializer {
+        if self.ctx.options.missing_as_none {
+            let visitor = OptionProbe {
+                visitor: self.visitor,
+                ctx: self.ctx,
+            };
+            return deserializer.deserialize_any(visitor);
+        }
+
+        let deserializer = ValueDeserializer {
             de: deserializer,
-            listener: self.listener,
+            ctx: self.ctx,
+            key: self.hint == Hint::Key,
         };
         self.visitor.visit_some(deserializer)
     }
@@ -207,51 +1762,64 @@ where
     where
         D: de::Deserializer<'de>,
     {
-        let deserializer = Deserializer {
+        let deserializer = ValueDeserializer {
             de: deserializer,
-            listener: self.listener,
+            ctx: self.ctx,
+            key: self.hint == Hint::Key,
         };
         self.visitor.visit_newtype_struct(deserializer)
     }
 
-    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    fn visit_seq<A>(mut self, seq: A) -> Result<Self::Value, A::Error>
     where
         A: de::SeqAccess<'de>,
     {
+        self.ctx.enter(Segment::Index(0));
         let visitor = Visitor {
             visitor: seq,
-            listener: self.listener,
+            ctx: self.ctx.reborrow(),
+            hint: Hint::Any,
         };
-        self.visitor.visit_seq(visitor)
+        let value = self.visitor.visit_seq(visitor);
+        self.ctx.leave();
+        value
     }
 
-    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    fn visit_map<A>(mut self, map: A) -> Result<Self::Value, A::Error>
     where
         A: de::MapAccess<'de>,
     {
+        self.ctx.enter(Segment::Key(None));
         let visitor = Visitor {
             visitor: map,
-            listener: self.listener,
+            ctx: self.ctx.reborrow(),
+            hint: Hint::Any,
         };
-        self.visitor.visit_map(visitor)
+        let value = self.visitor.visit_map(visitor);
+        self.ctx.leave();
+        value
     }
 
-    fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+    fn visit_enum<A>(mut self, data: A) -> Result<Self::Value, A::Error>
     where
         A: de::EnumAccess<'de>,
     {
+        self.ctx.enter(Segment::Key(None));
         let visitor = Visitor {
             visitor: data,
-            listener: self.listener,
+            ctx: self.ctx.reborrow(),
+            hint: Hint::Any,
         };
-        self.visitor.visit_enum(visitor)
+        let value = self.visitor.visit_enum(visitor);
+        self.ctx.leave();
+        value
     }
 }
 
 impl<'de, V, L> de::SeqAccess<'de> for Visitor<'_, V, L>
 where
     V: de::SeqAccess<'de>,
-    L: FnMut(&Path, &io::Result<Vec<u8>>),
+    L: Listener,
 {
     type Error = V::Error;
 
@@ -261,9 +1829,12 @@ where
     {
         let seed = DeserializeSeed {
             seed,
-            listener: self.listener,
+            ctx: self.ctx.reborrow(),
+            key: false,
         };
-        self.visitor.next_element_seed(seed)
+        let value = self.visitor.next_element_seed(seed);
+        self.ctx.next_index();
+        value
     }
 
     fn size_hint(&self) -> Option<usize> {
@@ -274,7 +1845,7 @@ where
 impl<'de, V, L> de::MapAccess<'de> for Visitor<'_, V, L>
 where
     V: de::MapAccess<'de>,
-    L: FnMut(&Path, &io::Result<Vec<u8>>),
+    L: Listener,
 {
     type Error = V::Error;
 
@@ -282,9 +1853,11 @@ where
     where
         K: de::DeserializeSeed<'de>,
     {
+        self.ctx.clear_key();
         let seed = DeserializeSeed {
             seed,
-            listener: self.listener,
+            ctx: self.ctx.reborrow(),
+            key: true,
         };
         self.visitor.next_key_seed(seed)
     }
@@ -295,7 +1868,8 @@ where
     {
         let seed = DeserializeSeed {
             seed,
-            listener: self.listener,
+            ctx: self.ctx.reborrow(),
+            key: false,
         };
         self.visitor.next_value_seed(seed)
     }
@@ -308,25 +1882,27 @@ where
 impl<'a, 'de, V, L> de::EnumAccess<'de> for Visitor<'a, V, L>
 where
     V: de::EnumAccess<'de>,
-    L: FnMut(&Path, &io::Result<Vec<u8>>),
+    L: Listener,
 {
     type Error = V::Error;
 
     type Variant = Visitor<'a, V::Variant, L>;
 
-    fn variant_seed<S>(self, seed: S) -> Result<(S::Value, Self::Variant), Self::Error>
+    fn variant_seed<S>(mut self, seed: S) -> Result<(S::Value, Self::Variant), Self::Error>
     where
         S: de::DeserializeSeed<'de>,
     {
         let seed = DeserializeSeed {
             seed,
-            listener: self.listener,
+            ctx: self.ctx.reborrow(),
+            key: true,
         };
         match self.visitor.variant_seed(seed) {
             Ok((value, variant)) => {
                 let variant = Visitor {
                     visitor: variant,
-                    listener: self.listener,
+                    ctx: self.ctx,
+                    hint: Hint::Any,
                 };
                 Ok((value, variant))
             }
@@ -338,7 +1914,7 @@ where
 impl<'de, V, L> de::VariantAccess<'de> for Visitor<'_, V, L>
 where
     V: de::VariantAccess<'de>,
-    L: FnMut(&Path, &io::Result<Vec<u8>>),
+    L: Listener,
 {
     type Error = V::Error;
 
@@ -352,7 +1928,8 @@ where
     {
         let seed = DeserializeSeed {
             seed,
-            listener: self.listener,
+            ctx: self.ctx,
+            key: false,
         };
         self.visitor.newtype_variant_seed(seed)
     }
@@ -363,7 +1940,8 @@ where
     {
         let visitor = Visitor {
             visitor,
-            listener: self.listener,
+            ctx: self.ctx,
+            hint: Hint::Any,
         };
         self.visitor.tuple_variant(len, visitor)
     }
@@ -378,7 +1956,8 @@ where
     {
         let visitor = Visitor {
             visitor,
-            listener: self.listener,
+            ctx: self.ctx,
+            hint: Hint::Any,
         };
         self.visitor.struct_variant(fields, visitor)
     }
@@ -386,13 +1965,14 @@ where
 
 struct DeserializeSeed<'a, S, L> {
     seed: S,
-    listener: &'a mut L,
+    ctx: Context<'a, L>,
+    key: bool,
 }
 
 impl<'de, S, L> de::DeserializeSeed<'de> for DeserializeSeed<'_, S, L>
 where
     S: de::DeserializeSeed<'de>,
-    L: FnMut(&Path, &io::Result<Vec<u8>>),
+    L: Listener,
 {
     type Value = S::Value;
 
@@ -400,10 +1980,288 @@ where
     where
         D: de::Deserializer<'de>,
     {
-        let deserializer = Deserializer {
+        let deserializer = ValueDeserializer {
             de: deserializer,
-            listener: self.listener,
+            ctx: self.ctx,
+            key: self.key,
         };
         self.seed.deserialize(deserializer)
     }
 }
+
+/// A visitor which inspects the contents of an optional value before passing it to the target type,
+/// which sees `None` if the value references a missing file.
+struct OptionProbe<'a, V, L> {
+    visitor: V,
+    ctx: Context<'a, L>,
+}
+
+impl<'de, V, L> OptionProbe<'_, V, L>
+where
+    V: de::Visitor<'de>,
+    L: Listener,
+{
+    fn visit_some<D>(self, deserializer: D) -> Result<V::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let deserializer = ValueDeserializer {
+            de: deserializer,
+            ctx: self.ctx,
+            key: false,
+        };
+        self.visitor.visit_some(deserializer)
+    }
+}
+
+macro_rules! probe_visit {
+    ($name:ident, $ty:ty) => {
+        fn $name<E>(self, v: $ty) -> Result<V::Value, E>
+        where
+            E: de::Error,
+        {
+            self.visit_some(de::IntoDeserializer::<E>::into_deserializer(v))
+        }
+    };
+}
+
+impl<'de, V, L> de::Visitor<'de> for OptionProbe<'_, V, L>
+where
+    V: de::Visitor<'de>,
+    L: Listener,
+{
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.visitor.expecting(formatter)
+    }
+
+    probe_visit!(visit_bool, bool);
+    probe_visit!(visit_i8, i8);
+    probe_visit!(visit_i16, i16);
+    probe_visit!(visit_i32, i32);
+    probe_visit!(visit_i64, i64);
+    probe_visit!(visit_i128, i128);
+    probe_visit!(visit_u8, u8);
+    probe_visit!(visit_u16, u16);
+    probe_visit!(visit_u32, u32);
+    probe_visit!(visit_u64, u64);
+    probe_visit!(visit_u128, u128);
+    probe_visit!(visit_f32, f32);
+    probe_visit!(visit_f64, f64);
+    probe_visit!(visit_char, char);
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.visit_string(v.to_string())
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        if self.ctx.is_missing_file(&v) {
+            return self.visitor.visit_none();
+        }
+
+        self.visit_some(de::value::StringDeserializer::new(v))
+    }
+
+    fn visit_borrowed_str<E>(self, v: &'de str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        if self.ctx.is_missing_file(v) {
+            return self.visitor.visit_none();
+        }
+
+        self.visit_some(de::value::BorrowedStrDeserializer::new(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.visit_byte_buf(v.to_vec())
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.visit_some(ByteBufDeserializer(v, PhantomData))
+    }
+
+    fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.visit_some(de::value::BorrowedBytesDeserializer::new(v))
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.visit_some(de::value::UnitDeserializer::new())
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.visitor.visit_none()
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        OptionProbe::visit_some(self, deserializer)
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        OptionProbe::visit_some(self, deserializer)
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        self.visit_some(de::value::SeqAccessDeserializer::new(seq))
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        self.visit_some(de::value::MapAccessDeserializer::new(map))
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::EnumAccess<'de>,
+    {
+        self.visit_some(de::value::EnumAccessDeserializer::new(data))
+    }
+}
+
+/// A deserializer yielding an owned byte buffer.
+struct ByteBufDeserializer<E>(Vec<u8>, PhantomData<E>);
+
+impl<'de, E> de::Deserializer<'de> for ByteBufDeserializer<E>
+where
+    E: de::Error,
+{
+    type Error = E;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, E>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_byte_buf(self.0)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// A type which visits every value in the input.
+struct Walk;
+
+impl<'de> Deserialize<'de> for Walk {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(Walk)
+    }
+}
+
+macro_rules! walk_visit {
+    ($name:ident, $ty:ty) => {
+        fn $name<E>(self, _: $ty) -> Result<Walk, E>
+        where
+            E: de::Error,
+        {
+            Ok(Walk)
+        }
+    };
+}
+
+impl<'de> de::Visitor<'de> for Walk {
+    type Value = Walk;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any value")
+    }
+
+    walk_visit!(visit_bool, bool);
+    walk_visit!(visit_i64, i64);
+    walk_visit!(visit_i128, i128);
+    walk_visit!(visit_u64, u64);
+    walk_visit!(visit_u128, u128);
+    walk_visit!(visit_f64, f64);
+    walk_visit!(visit_str, &str);
+    walk_visit!(visit_bytes, &[u8]);
+
+    fn visit_unit<E>(self) -> Result<Walk, E>
+    where
+        E: de::Error,
+    {
+        Ok(Walk)
+    }
+
+    fn visit_none<E>(self) -> Result<Walk, E>
+    where
+        E: de::Error,
+    {
+        Ok(Walk)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Walk, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        Walk::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Walk, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        Walk::deserialize(deserializer)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Walk, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        while seq.next_element::<Walk>()?.is_some() {}
+        Ok(Walk)
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Walk, A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        while map.next_entry::<Walk, Walk>()?.is_some() {}
+        Ok(Walk)
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<Walk, A::Error>
+    where
+        A: de::EnumAccess<'de>,
+    {
+        let (Walk, variant) = data.variant::<Walk>()?;
+        de::VariantAccess::newtype_variant::<Walk>(variant)
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    error, fmt, io,
+    path::{Path, PathBuf},
+};
+
+/// An error returned by
+/// [`Deserializer::deserialize_with_error`](crate::Deserializer::deserialize_with_error).
+///
+/// In addition to the error produced by the underlying deserializer, it provides structured
+/// information about the failed file read, if any.
+///
+/// The IO error of a failed file read is exposed as the [`source`](error::Error::source) of the
+/// error, so error reporting tools which walk the source chain will display the original cause.
+/// Otherwise, the source is that of the underlying deserializer's error.
+#[derive(Debug)]
+pub struct Error<E> {
+    error: E,
+    file: Option<(PathBuf, io::Error)>,
+}
+
+impl<E> Error<E> {
+    pub(crate) fn new(error: E, file: Option<(PathBuf, io::Error)>) -> Self {
+        Error { error, file }
+    }
+
+    /// Returns the error produced by the underlying deserializer.
+    pub fn inner(&self) -> &E {
+        &self.error
+    }
+
+    /// Consumes the error, returning the error produced by the underlying deserializer.
+    pub fn into_inner(self) -> E {
+        self.error
+    }
+
+    /// Returns the path of the file which failed to be read, if the error was caused by a file
+    /// read.
+    pub fn path(&self) -> Option<&Path> {
+        self.file.as_ref().map(|(path, _)| &**path)
+    }
+
+    /// Returns the IO error produced by the failed file read, if the error was caused by a file
+    /// read.
+    pub fn io_error(&self) -> Option<&io::Error> {
+        self.file.as_ref().map(|(_, e)| e)
+    }
+}
+
+impl<E> fmt::Display for Error<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, fmt)
+    }
+}
+
+impl<E> error::Error for Error<E>
+where
+    E: error::Error,
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match &self.file {
+            Some((_, e)) => Some(e),
+            None => self.error.source(),
+        }
+    }
+}
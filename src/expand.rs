@@ -0,0 +1,2751 @@
+use std::{
+    borrow::Cow,
+    collections::{hash_map::DefaultHasher, BTreeSet, HashMap},
+    env,
+    fmt::{self, Write},
+    fs::Metadata,
+    hash::{Hash, Hasher},
+    io, mem,
+    path::{Path, PathBuf},
+    str,
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+#[cfg(feature = "fs")]
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, Read},
+};
+
+use base64::Engine;
+use serde::de;
+
+use crate::{
+    file_value,
+    format::DynFormat,
+    prefetch::Prefetched,
+    resolver::{is_valid_scheme, Resolvers},
+    Access, DependencyGraph, Filesystem, Listener, Resolved, Stats,
+};
+
+#[derive(Clone)]
+pub(crate) struct Options {
+    pub(crate) enabled: bool,
+    pub(crate) open: String,
+    pub(crate) close: String,
+    pub(crate) trim_trailing_newline: bool,
+    pub(crate) utf8_lossy: bool,
+    pub(crate) strip_bom: bool,
+    pub(crate) trim: bool,
+    pub(crate) join_lines: bool,
+    pub(crate) inline: bool,
+    pub(crate) recursive: bool,
+    pub(crate) recursion_limit: usize,
+    pub(crate) files: HashMap<PathBuf, Vec<u8>>,
+    pub(crate) max_file_size: Option<u64>,
+    pub(crate) max_total_bytes: Option<u64>,
+    pub(crate) allowed_roots: Vec<PathBuf>,
+    pub(crate) base_dir: Option<PathBuf>,
+    pub(crate) config_dir: Option<PathBuf>,
+    pub(crate) cache: bool,
+    pub(crate) expand_keys: bool,
+    pub(crate) base64_alphabet: Base64Alphabet,
+    pub(crate) hex_uppercase: bool,
+    pub(crate) missing_as_none: bool,
+    pub(crate) formats: HashMap<String, Arc<dyn DynFormat>>,
+    pub(crate) dry_run: bool,
+    pub(crate) dry_run_placeholder: Option<String>,
+    pub(crate) prefetch_threads: Option<usize>,
+    pub(crate) deny_unknown_schemes: bool,
+    pub(crate) read_timeout: Option<Duration>,
+    pub(crate) read_retries: u32,
+    pub(crate) read_retry_backoff: Duration,
+    pub(crate) strict: bool,
+    pub(crate) byte_sequences: bool,
+    pub(crate) file_lines: bool,
+    pub(crate) dotenv_files: bool,
+    pub(crate) typed_scalars: bool,
+    pub(crate) unit_suffixes: bool,
+    pub(crate) error_on_empty: bool,
+    pub(crate) error_locations: bool,
+    pub(crate) case_insensitive_schemes: bool,
+    pub(crate) fd_references: bool,
+    pub(crate) stdin_file: bool,
+    pub(crate) candidate_separator: Option<String>,
+    pub(crate) path_env_vars: bool,
+    pub(crate) warn_insecure_permissions: bool,
+    pub(crate) recommended_root: Option<PathBuf>,
+    pub(crate) symlinks: SymlinkPolicy,
+    pub(crate) path_redaction: PathRedaction,
+    pub(crate) allowed_extensions: Option<Vec<String>>,
+    pub(crate) secret_sources: Vec<SecretSource>,
+    pub(crate) filesystem: Option<Arc<dyn Filesystem>>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            enabled: true,
+            open: "${".to_string(),
+            close: "}".to_string(),
+            trim_trailing_newline: false,
+            utf8_lossy: false,
+            strip_bom: false,
+            trim: false,
+            join_lines: false,
+            inline: false,
+            recursive: false,
+            recursion_limit: 8,
+            files: HashMap::new(),
+            max_file_size: None,
+            max_total_bytes: None,
+            allowed_roots: vec![],
+            base_dir: None,
+            config_dir: None,
+            cache: false,
+            expand_keys: false,
+            base64_alphabet: Base64Alphabet::Standard,
+            hex_uppercase: false,
+            missing_as_none: false,
+            formats: HashMap::new(),
+            dry_run: false,
+            dry_run_placeholder: None,
+            prefetch_threads: None,
+            deny_unknown_schemes: false,
+            read_timeout: None,
+            read_retries: 0,
+            read_retry_backoff: Duration::from_millis(10),
+            strict: false,
+            byte_sequences: false,
+            file_lines: false,
+            dotenv_files: false,
+            typed_scalars: false,
+            unit_suffixes: false,
+            error_on_empty: false,
+            error_locations: true,
+            case_insensitive_schemes: false,
+            fd_references: false,
+            stdin_file: false,
+            candidate_separator: None,
+            path_env_vars: false,
+            warn_insecure_permissions: false,
+            recommended_root: None,
+            symlinks: SymlinkPolicy::Follow,
+            path_redaction: PathRedaction::Disabled,
+            allowed_extensions: None,
+            secret_sources: vec![],
+            filesystem: None,
+        }
+    }
+}
+
+/// The handling of referenced files which are symbolic links.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SymlinkPolicy {
+    /// Symbolic links are followed.
+    Follow,
+    /// Symbolic links are rejected.
+    Deny,
+    /// Symbolic links are followed only if their final target is located within one of the allowed
+    /// roots, and are rejected if no allowed roots are configured.
+    WithinAllowedRoots,
+}
+
+/// The handling of file paths in error messages.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PathRedaction {
+    /// Paths are included in error messages.
+    Disabled,
+    /// Paths and the text of references are replaced with `<redacted>`.
+    Hidden,
+    /// Paths are replaced with a hash of the path, like `<path:1f0e3dad99908345>`, and the text of
+    /// references with a hash of the text, like `<reference:1f0e3dad99908345>`.
+    ///
+    /// The hash allows errors involving the same file to be correlated without revealing the path.
+    /// It is not cryptographically secure, and may change between releases of this crate or of
+    /// Rust.
+    Hashed,
+}
+
+/// A source consulted by `${secret:NAME}` references.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SecretSource {
+    /// The environment variable `NAME`.
+    Env,
+    /// The file `NAME` within a directory, like the directories Docker and Kubernetes mount secrets
+    /// in.
+    ///
+    /// A relative directory is resolved in the same way as a relative file reference.
+    Dir(PathBuf),
+}
+
+/// The base64 alphabet used for `${file-base64:...}` references.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Base64Alphabet {
+    /// The standard alphabet, using `+` and `/`.
+    Standard,
+    /// The URL-safe alphabet, using `-` and `_`.
+    UrlSafe,
+}
+
+/// The kind of value the target type requested from the deserializer.
+#[derive(Copy, Clone, PartialEq)]
+pub(crate) enum Hint {
+    Any,
+    Bytes,
+    /// A map key, struct field name, or enum variant name.
+    Key,
+    /// A sequence, when byte sequences are enabled.
+    Seq,
+    /// A primitive, when typed scalars are enabled.
+    Scalar(Scalar),
+}
+
+/// A primitive type requested by the target type.
+#[derive(Copy, Clone, PartialEq)]
+pub(crate) enum Scalar {
+    Bool,
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+    Char,
+}
+
+impl fmt::Display for Scalar {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Scalar::Bool => "bool",
+            Scalar::I8 => "i8",
+            Scalar::I16 => "i16",
+            Scalar::I32 => "i32",
+            Scalar::I64 => "i64",
+            Scalar::U8 => "u8",
+            Scalar::U16 => "u16",
+            Scalar::U32 => "u32",
+            Scalar::U64 => "u64",
+            Scalar::F32 => "f32",
+            Scalar::F64 => "f64",
+            Scalar::Char => "char",
+        };
+        fmt.write_str(name)
+    }
+}
+
+/// The expanded form of a reference.
+pub(crate) enum Expansion {
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+impl Expansion {
+    fn from_string(value: String, hint: Hint) -> Self {
+        match hint {
+            Hint::Bytes | Hint::Seq => Expansion::Bytes(value.into_bytes()),
+            Hint::Any | Hint::Key | Hint::Scalar(_) => Expansion::String(value),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Expansion::String(value) => value.is_empty(),
+            Expansion::Bytes(value) => value.is_empty(),
+        }
+    }
+
+    fn as_resolved(&self) -> Resolved<'_> {
+        match self {
+            Expansion::String(value) => Resolved::String(value),
+            Expansion::Bytes(value) => Resolved::Bytes(value),
+        }
+    }
+
+    fn push_str(&mut self, s: &str) {
+        match self {
+            Expansion::String(value) => value.push_str(s),
+            Expansion::Bytes(value) => value.extend_from_slice(s.as_bytes()),
+        }
+    }
+
+    fn push(&mut self, other: Expansion) {
+        match (&mut *self, other) {
+            (Expansion::String(value), Expansion::String(other)) => value.push_str(&other),
+            (Expansion::String(value), Expansion::Bytes(other)) => {
+                let mut value = mem::take(value).into_bytes();
+                value.extend_from_slice(&other);
+                *self = Expansion::Bytes(value);
+            }
+            (Expansion::Bytes(value), Expansion::String(other)) => {
+                value.extend_from_slice(other.as_bytes())
+            }
+            (Expansion::Bytes(value), Expansion::Bytes(other)) => value.extend_from_slice(&other),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Scheme<'a> {
+    File(Encoding),
+    /// A file deserialized by the listener in the specified format.
+    Structured(&'a str),
+    /// A file split into a sequence of its lines.
+    Lines,
+    /// A dotenv file parsed into a map of its entries.
+    Dotenv,
+    Env,
+    /// A secret looked up in the configured secret sources.
+    Secret,
+    /// An inherited file descriptor.
+    Fd,
+    /// A scheme handled by a registered handler or the custom resolver.
+    Custom(&'a str),
+}
+
+/// The encoding applied to the contents of a file.
+#[derive(Copy, Clone)]
+enum Encoding {
+    None,
+    Base64,
+    Hex,
+    /// The file contains base64 data, and is decoded as it is read.
+    Base64Decode,
+    /// The file is gzip-compressed, and is decompressed as it is read.
+    #[cfg(feature = "gzip")]
+    Gzip,
+}
+
+/// The character set used to decode file contents as text.
+#[derive(Copy, Clone)]
+enum Charset {
+    Utf8,
+    Latin1,
+}
+
+impl Charset {
+    /// Transcodes contents in this character set to UTF-8.
+    fn to_utf8(self, contents: Vec<u8>) -> Vec<u8> {
+        match self {
+            Charset::Utf8 => contents,
+            Charset::Latin1 => latin1_decode(&contents).into_bytes(),
+        }
+    }
+}
+
+/// A parsed reference.
+struct Reference<'a> {
+    scheme: Scheme<'a>,
+    arg: &'a str,
+    /// The `;`-separated parameters following a file path, if any.
+    params: &'a str,
+    /// The portion of the file selected by a `#` fragment, if any.
+    selection: Option<Selection>,
+    default: Option<&'a str>,
+}
+
+/// A portion of a file selected by a `#` fragment.
+enum Selection {
+    /// A 1-based line number, from `#L<n>`.
+    Line(usize),
+    /// A 0-based, end-exclusive byte range, from `#<start>-<end>`.
+    Bytes(usize, usize),
+    /// A JSON pointer to a value within a JSON file, from `#/<pointer>`.
+    #[cfg(feature = "json")]
+    Pointer(String),
+}
+
+impl Selection {
+    fn parse(s: &str) -> Option<Self> {
+        #[cfg(feature = "json")]
+        if s.starts_with('/') {
+            return Some(Selection::Pointer(s.to_string()));
+        }
+
+        if let Some(line) = s.strip_prefix('L') {
+            return parse_number(line).map(Selection::Line);
+        }
+
+        let (start, end) = s.split_once('-')?;
+        Some(Selection::Bytes(parse_number(start)?, parse_number(end)?))
+    }
+
+    /// Extracts the selected portion of a file's contents.
+    fn apply<E>(&self, path: &Path, mut contents: Vec<u8>) -> Result<Vec<u8>, E>
+    where
+        E: de::Error,
+    {
+        match *self {
+            Selection::Line(0) => Err(E::custom(format_args!(
+                "invalid line 0 in reference to file {}: lines are numbered from 1",
+                path.display()
+            ))),
+            Selection::Line(line) => {
+                let body = contents.strip_suffix(b"\n").unwrap_or(&contents);
+                let lines = match body {
+                    [] => vec![],
+                    body => body.split(|&b| b == b'\n').collect::<Vec<_>>(),
+                };
+                match lines.get(line - 1) {
+                    Some(selected) => Ok(selected.strip_suffix(b"\r").unwrap_or(selected).to_vec()),
+                    None => Err(E::custom(format_args!(
+                        "line {line} is out of range for file {} with {} lines",
+                        path.display(),
+                        lines.len()
+                    ))),
+                }
+            }
+            Selection::Bytes(start, end) if start > end => Err(E::custom(format_args!(
+                "invalid byte range {start}-{end} in reference to file {}",
+                path.display()
+            ))),
+            Selection::Bytes(start, end) if end > contents.len() => Err(E::custom(format_args!(
+                "byte range {start}-{end} is out of range for file {} of {} bytes",
+                path.display(),
+                contents.len()
+            ))),
+            Selection::Bytes(start, end) => {
+                contents.truncate(end);
+                contents.drain(..start);
+                Ok(contents)
+            }
+            #[cfg(feature = "json")]
+            Selection::Pointer(ref pointer) => select_pointer(path, pointer, &contents),
+        }
+    }
+}
+
+/// Extracts the scalar value at a JSON pointer within the contents of a JSON file.
+#[cfg(feature = "json")]
+fn select_pointer<E>(path: &Path, pointer: &str, contents: &[u8]) -> Result<Vec<u8>, E>
+where
+    E: de::Error,
+{
+    let mut value = serde_json::from_slice::<serde_json::Value>(contents).map_err(|e| {
+        E::custom(format_args!(
+            "error parsing file {} as JSON: {e}",
+            path.display()
+        ))
+    })?;
+    match value.pointer_mut(pointer) {
+        Some(serde_json::Value::String(s)) => Ok(mem::take(s).into_bytes()),
+        Some(value @ (serde_json::Value::Number(_) | serde_json::Value::Bool(_))) => {
+            Ok(value.to_string().into_bytes())
+        }
+        Some(_) => Err(E::custom(format_args!(
+            "JSON pointer {pointer} in file {} does not refer to a string, number, or boolean",
+            path.display()
+        ))),
+        None => Err(E::custom(format_args!(
+            "JSON pointer {pointer} not found in file {}",
+            path.display()
+        ))),
+    }
+}
+
+fn parse_number(s: &str) -> Option<usize> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// Mutable state shared across a single deserialization.
+#[derive(Default)]
+pub(crate) struct State {
+    /// If set, file references are recorded here rather than read.
+    pub(crate) references: Option<BTreeSet<PathBuf>>,
+    /// If set, the text of every reference is recorded here rather than resolved.
+    pub(crate) scanned: Option<Vec<String>>,
+    /// The contents of files which have already been read, if caching is enabled.
+    pub(crate) cache: HashMap<PathBuf, Vec<u8>>,
+    /// The failed file read of the current expansion, if any.
+    pub(crate) file_error: Option<(PathBuf, io::Error)>,
+    pub(crate) stats: Stats,
+    /// Set once standard input has been read by a `${file:-}` reference.
+    pub(crate) stdin_read: bool,
+    /// The results of reads performed ahead of time by `Deserializer::prefetch`.
+    pub(crate) prefetched: Prefetched,
+    /// The location of the value currently being deserialized.
+    pub(crate) path: Vec<Segment>,
+    /// If set, the references between files are recorded here.
+    pub(crate) graph: Option<DependencyGraph>,
+    /// The files whose contents are currently being expanded, innermost last.
+    pub(crate) parents: Vec<PathBuf>,
+}
+
+/// A component of the location of a value within the document.
+pub(crate) enum Segment {
+    /// An element of a sequence.
+    Index(usize),
+    /// A value in a map or struct, or the contents of an enum variant, if the key is known.
+    Key(Option<String>),
+}
+
+/// The location of a value within the document, like `servers[0].password`.
+struct Location<'a>(&'a [Segment]);
+
+impl fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Index(index) => write!(f, "[{index}]")?,
+                Segment::Key(key) => {
+                    if i > 0 {
+                        f.write_str(".")?;
+                    }
+                    f.write_str(key.as_deref().unwrap_or("?"))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) struct Context<'a, L> {
+    pub(crate) listener: &'a mut L,
+    pub(crate) options: &'a Arc<Options>,
+    pub(crate) resolvers: &'a mut Resolvers,
+    pub(crate) state: &'a mut State,
+}
+
+impl<L> Context<'_, L>
+where
+    L: Listener,
+{
+    pub(crate) fn reborrow(&mut self) -> Context<'_, L> {
+        Context {
+            listener: self.listener,
+            options: self.options,
+            resolvers: self.resolvers,
+            state: self.state,
+        }
+    }
+
+    /// Starts deserializing a nested sequence, map, or enum.
+    pub(crate) fn enter(&mut self, segment: Segment) {
+        self.state.path.push(segment);
+    }
+
+    /// Finishes deserializing the innermost nested value.
+    pub(crate) fn leave(&mut self) {
+        self.state.path.pop();
+    }
+
+    /// Advances to the next element of the innermost sequence.
+    pub(crate) fn next_index(&mut self) {
+        if let Some(Segment::Index(index)) = self.state.path.last_mut() {
+            *index += 1;
+        }
+    }
+
+    /// Records the key of the innermost map.
+    pub(crate) fn set_key(&mut self, key: &dyn fmt::Display) {
+        if let Some(Segment::Key(slot)) = self.state.path.last_mut() {
+            let slot = slot.get_or_insert_with(String::new);
+            slot.clear();
+            let _ = write!(slot, "{key}");
+        }
+    }
+
+    /// Forgets the key of the innermost map.
+    pub(crate) fn clear_key(&mut self) {
+        if let Some(Segment::Key(slot)) = self.state.path.last_mut() {
+            *slot = None;
+        }
+    }
+
+    /// Adds the location of the current value to an error.
+    fn locate<E>(&self, e: E) -> E
+    where
+        E: de::Error,
+    {
+        if !self.options.error_locations || self.state.path.is_empty() {
+            return e;
+        }
+
+        E::custom(format_args!("{}: {e}", Location(&self.state.path)))
+    }
+
+    pub(crate) fn expand_str<E>(&mut self, s: &str, hint: Hint) -> Result<Option<Expansion>, E>
+    where
+        E: de::Error,
+    {
+        if !self.options.enabled || hint == Hint::Key && !self.options.expand_keys {
+            return Ok(None);
+        }
+
+        // a failed read from an earlier value may have been handled by the target type
+        self.state.file_error = None;
+        self.expand(s, hint, &mut vec![])
+            .map_err(|e| self.locate(e))
+    }
+
+    pub(crate) fn expand_bytes<E>(&mut self, v: &[u8], hint: Hint) -> Result<Option<Expansion>, E>
+    where
+        E: de::Error,
+    {
+        if hint != Hint::Bytes {
+            return Ok(None);
+        }
+
+        match str::from_utf8(v) {
+            Ok(s) => self.expand_str(s, hint),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Determines if the value consists of a single file reference without a default which refers
+    /// to a file that does not exist.
+    pub(crate) fn is_missing_file(&self, s: &str) -> bool {
+        if !self.options.enabled || self.state.references.is_some() || self.state.scanned.is_some()
+        {
+            return false;
+        }
+
+        let Some(Reference {
+            scheme: Scheme::File(_),
+            arg,
+            default: None,
+            ..
+        }) = self.parse_reference(s)
+        else {
+            return false;
+        };
+
+        if self.is_stdin(arg) {
+            return false;
+        }
+        let Ok(paths) = self.candidate_paths::<de::value::Error>(arg) else {
+            return false;
+        };
+        paths.iter().all(|path| {
+            if self.options.files.contains_key(path) {
+                return false;
+            }
+
+            match &self.options.filesystem {
+                Some(filesystem) => matches!(filesystem.exists(path), Ok(false)),
+                None => std_is_missing(path),
+            }
+        })
+    }
+
+    fn expand<E>(
+        &mut self,
+        s: &str,
+        hint: Hint,
+        chain: &mut Vec<String>,
+    ) -> Result<Option<Expansion>, E>
+    where
+        E: de::Error,
+    {
+        if self.options.inline {
+            return self.expand_inline(s, hint, chain);
+        }
+
+        let rest = s.trim_start_matches('\\');
+        let escapes = s.len() - rest.len();
+        let Some(reference) = self.parse_reference(rest) else {
+            if escapes % 2 != 1 {
+                self.check_unparsed(rest)?;
+            }
+            return Ok(None);
+        };
+
+        if escapes == 0 {
+            return self.resolve(rest, reference, hint, chain).map(Some);
+        }
+
+        let mut expansion = Expansion::String("\\".repeat(escapes / 2));
+        if escapes % 2 == 1 {
+            expansion.push_str(rest);
+        } else {
+            expansion.push(self.resolve(rest, reference, hint, chain)?);
+        }
+        Ok(Some(expansion))
+    }
+
+    fn expand_inline<E>(
+        &mut self,
+        s: &str,
+        hint: Hint,
+        chain: &mut Vec<String>,
+    ) -> Result<Option<Expansion>, E>
+    where
+        E: de::Error,
+    {
+        let options = self.options;
+
+        let mut expansion = Expansion::String(String::new());
+        let mut expanded = false;
+        let mut remaining = s;
+        while let Some(start) = remaining.find(&*options.open) {
+            let literal = &remaining[..start];
+            let unescaped = literal.trim_end_matches('\\');
+            let escapes = literal.len() - unescaped.len();
+
+            let body_start = start + options.open.len();
+            let Some(end) = remaining[body_start..].find(&*options.close) else {
+                if escapes % 2 != 1 {
+                    self.check_unparsed(&remaining[start..])?;
+                }
+                break;
+            };
+            let raw = &remaining[start..body_start + end + options.close.len()];
+
+            let Some(reference) = self.parse_reference(raw) else {
+                if escapes % 2 != 1 {
+                    self.check_unparsed(raw)?;
+                }
+                expansion.push_str(&remaining[..body_start]);
+                remaining = &remaining[body_start..];
+                continue;
+            };
+
+            expansion.push_str(unescaped);
+            expansion.push_str(&"\\".repeat(escapes / 2));
+            if escapes % 2 == 1 {
+                expansion.push_str(raw);
+            } else {
+                expansion.push(self.resolve(raw, reference, hint, chain)?);
+            }
+
+            expanded = true;
+            remaining = &remaining[start + raw.len()..];
+        }
+
+        if !expanded {
+            return Ok(None);
+        }
+
+        expansion.push_str(remaining);
+        Ok(Some(expansion))
+    }
+
+    /// Handles a string which could not be parsed as a reference.
+    ///
+    /// Malformed references are reported to the listener and produce an error in strict mode, and
+    /// an error is returned if unknown schemes are denied and the string is a reference with an
+    /// unknown scheme.
+    fn check_unparsed<E>(&mut self, s: &str) -> Result<(), E>
+    where
+        E: de::Error,
+    {
+        let options = self.options;
+        let Some(body) = s.strip_prefix(&*options.open) else {
+            return Ok(());
+        };
+
+        if let Some(reason) = self.malformed_reason(body) {
+            self.listener.malformed_reference(s, &reason);
+            if options.strict {
+                return Err(E::custom(format_args!(
+                    "malformed reference {}: {reason}",
+                    self.error_reference(s)
+                )));
+            }
+        }
+
+        if !options.deny_unknown_schemes {
+            return Ok(());
+        }
+
+        let scheme = body
+            .strip_suffix(&*options.close)
+            .and_then(|s| s.split_once(':'))
+            .map(|(scheme, _)| scheme.strip_suffix('?').unwrap_or(scheme));
+        match scheme {
+            Some(scheme) if is_valid_scheme(scheme) => Err(E::custom(format_args!(
+                "unknown reference scheme `{scheme}`"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Determines why the body of a string starting with the opening delimiter looks like a
+    /// malformed reference, if it does.
+    ///
+    /// Only strings starting with the full name of a supported scheme are considered, so that
+    /// unrelated uses of the delimiters, like `${filename}`, aren't reported.
+    fn malformed_reason(&self, body: &str) -> Option<String> {
+        let end = body
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.' | '_')))
+            .unwrap_or(body.len());
+        let name = &body[..end];
+        let scheme = ["file", "env"]
+            .into_iter()
+            .chain(self.resolvers.schemes.keys().map(|s| &**s))
+            .find(|scheme| {
+                if self.options.case_insensitive_schemes {
+                    name.eq_ignore_ascii_case(scheme)
+                } else {
+                    name == *scheme
+                }
+            })?;
+
+        let close = &*self.options.close;
+        let Some(body) = body.strip_suffix(close) else {
+            return Some(format!("missing closing `{close}`"));
+        };
+
+        let rest = &body[end.min(body.len())..];
+        let rest = rest.strip_prefix('?').unwrap_or(rest);
+        if !rest.starts_with(':') {
+            return Some(format!("missing `:` after the `{scheme}` scheme"));
+        }
+        None
+    }
+
+    fn parse_reference<'s>(&self, s: &'s str) -> Option<Reference<'s>> {
+        let options = self.options;
+        // The scheme ends at the first `:`, and scheme names never contain one, so a Windows drive
+        // letter like the one in `${file:C:\path}` is always part of the argument.
+        let (scheme, arg) = s
+            .strip_prefix(&*options.open)
+            .and_then(|s| s.strip_suffix(&*options.close))
+            .and_then(|s| s.split_once(':'))?;
+        // a `?` after the scheme marks the reference as optional, like an empty default
+        let (scheme, optional) = match scheme.strip_suffix('?') {
+            Some(scheme) => (scheme, true),
+            None => (scheme, false),
+        };
+
+        let ignore_case = options.case_insensitive_schemes;
+        let lowercase;
+        let name = if ignore_case {
+            lowercase = scheme.to_ascii_lowercase();
+            &*lowercase
+        } else {
+            scheme
+        };
+
+        let scheme = match name {
+            _ if self.resolvers.is_registered(scheme, ignore_case) => Scheme::Custom(scheme),
+            "file" => Scheme::File(Encoding::None),
+            "file-base64" => Scheme::File(Encoding::Base64),
+            "file-hex" => Scheme::File(Encoding::Hex),
+            "file-base64-decode" => Scheme::File(Encoding::Base64Decode),
+            #[cfg(feature = "gzip")]
+            "file-gz" => Scheme::File(Encoding::Gzip),
+            "file-lines" if options.file_lines => Scheme::Lines,
+            "file-dotenv" if options.dotenv_files => Scheme::Dotenv,
+            "env" => Scheme::Env,
+            "secret" if !options.secret_sources.is_empty() => Scheme::Secret,
+            "fd" if options.fd_references => Scheme::Fd,
+            _ if name.starts_with("file-") && self.format(&scheme["file-".len()..]).is_some() => {
+                Scheme::Structured(&scheme["file-".len()..])
+            }
+            _ if self.resolvers.resolver_supports(scheme) => Scheme::Custom(scheme),
+            _ => return None,
+        };
+
+        let (arg, default) = match arg.split_once(":-") {
+            Some((arg, default)) => (arg, Some(default)),
+            None if optional => (arg, Some("")),
+            None => (arg, None),
+        };
+
+        let (arg, params, selection) = match scheme {
+            Scheme::File(_) | Scheme::Structured(_) | Scheme::Lines | Scheme::Dotenv => {
+                let (arg, params) = split_params(arg);
+                let (arg, selection) = split_selection(arg);
+                (arg, params, selection)
+            }
+            Scheme::Env | Scheme::Secret | Scheme::Fd | Scheme::Custom(_) => (arg, "", None),
+        };
+
+        Some(Reference {
+            scheme,
+            arg,
+            params,
+            selection,
+            default,
+        })
+    }
+
+    fn resolve<E>(
+        &mut self,
+        raw: &str,
+        reference: Reference<'_>,
+        hint: Hint,
+        chain: &mut Vec<String>,
+    ) -> Result<Expansion, E>
+    where
+        E: de::Error,
+    {
+        if let Some(scanned) = &mut self.state.scanned {
+            scanned.push(raw.to_string());
+            return Ok(Expansion::from_string(String::new(), hint));
+        }
+
+        let options = self.options;
+        if options.recursive {
+            let describe_chain = || {
+                chain
+                    .iter()
+                    .map(|r| self.error_reference(r))
+                    .chain([self.error_reference(raw)])
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            };
+            if chain.iter().any(|r| r == raw) {
+                return Err(E::custom(format_args!(
+                    "reference cycle detected: {}",
+                    describe_chain(),
+                )));
+            }
+            if chain.len() >= options.recursion_limit {
+                return Err(E::custom(format_args!(
+                    "recursion limit of {} exceeded: {}",
+                    options.recursion_limit,
+                    describe_chain(),
+                )));
+            }
+        }
+
+        if let (Scheme::File(_), true) = (reference.scheme, self.is_dry_run()) {
+            let placeholder = self.dry_run_file(raw, &reference)?;
+            return Ok(Expansion::from_string(placeholder, hint));
+        }
+
+        let mut path = None;
+        let expansion = match reference.scheme {
+            Scheme::File(encoding) => {
+                let (expansion, read_path) = self.read_file(raw, &reference, encoding, hint)?;
+                path = Some(read_path);
+                expansion
+            }
+            Scheme::Structured(_) => {
+                return Err(E::custom(format_args!(
+                    "structured file reference {} must make up the entire value",
+                    self.error_reference(raw)
+                )))
+            }
+            Scheme::Lines => {
+                return Err(E::custom(format_args!(
+                    "file lines reference {} must make up the entire value",
+                    self.error_reference(raw)
+                )))
+            }
+            Scheme::Dotenv => {
+                return Err(E::custom(format_args!(
+                    "dotenv file reference {} must make up the entire value",
+                    self.error_reference(raw)
+                )))
+            }
+            Scheme::Env => self.read_env(&reference, hint)?,
+            Scheme::Secret => self.read_secret(raw, &reference, hint)?,
+            Scheme::Fd => self.read_fd(raw, &reference, hint)?,
+            Scheme::Custom(scheme) => self.read_custom(scheme, &reference, hint)?,
+        };
+
+        if !options.recursive {
+            return Ok(expansion);
+        }
+
+        let value = match &expansion {
+            Expansion::String(value) => value.as_str(),
+            Expansion::Bytes(value) => match str::from_utf8(value) {
+                Ok(value) => value,
+                Err(_) => return Ok(expansion),
+            },
+        };
+
+        let parent = path.filter(|_| self.state.graph.is_some());
+        let has_parent = parent.is_some();
+        self.state.parents.extend(parent);
+        chain.push(raw.to_string());
+        let nested = self.expand(value, hint, chain);
+        chain.pop();
+        if has_parent {
+            self.state.parents.pop();
+        }
+        let nested = nested?;
+
+        Ok(nested.unwrap_or(expansion))
+    }
+
+    fn is_dry_run(&self) -> bool {
+        self.options.dry_run && self.state.references.is_none()
+    }
+
+    /// Reports a file reference to the listener in dry-run mode, returning its placeholder value.
+    fn dry_run_file<E>(&mut self, raw: &str, reference: &Reference<'_>) -> Result<String, E>
+    where
+        E: de::Error,
+    {
+        let paths = self.candidate_paths(reference.arg)?;
+        parse_params(&self.error_path(&paths[0]), reference.params)?;
+        for path in &paths {
+            self.listener.path_resolved(raw, path);
+            self.listener.file_referenced(path);
+        }
+
+        let placeholder = self.options.dry_run_placeholder.as_deref().unwrap_or(raw);
+        Ok(placeholder.to_string())
+    }
+
+    /// Reads a file reference, returning its value along with the path of the file it was read
+    /// from, or of the last candidate if none could be read.
+    fn read_file<E>(
+        &mut self,
+        raw: &str,
+        reference: &Reference<'_>,
+        encoding: Encoding,
+        hint: Hint,
+    ) -> Result<(Expansion, PathBuf), E>
+    where
+        E: de::Error,
+    {
+        let stdin = self.is_stdin(reference.arg);
+        let paths = self.candidate_paths(reference.arg)?;
+        let params = parse_params(&self.error_path(&paths[0]), reference.params)?;
+        if let Some(references) = &mut self.state.references {
+            if !stdin {
+                references.extend(paths);
+            }
+            return Ok((Expansion::from_string(String::new(), hint), PathBuf::new()));
+        }
+
+        // the line limit applies to the decompressed or decoded contents of the file, so they must
+        // be read in full
+        let max_lines = match encoding {
+            Encoding::None | Encoding::Base64 | Encoding::Hex => params.max_lines(),
+            _ => None,
+        };
+        // a missing file is only worth waiting for if it would otherwise be an error
+        let last = paths.len() - 1;
+        let mut errors = vec![];
+        for (i, path) in paths.into_iter().enumerate() {
+            let retry_missing = i == last && reference.default.is_none();
+            let contents = if stdin {
+                self.listener.path_resolved(raw, &path);
+                self.read_stdin(&path)?
+            } else {
+                self.record_dependency(&path);
+                self.listener.path_resolved(raw, &path);
+                self.read_contents(&path, max_lines, retry_missing)?
+            };
+            let contents = match contents {
+                Ok(contents) => contents,
+                Err(e) => {
+                    errors.push((path, e));
+                    continue;
+                }
+            };
+            if let Some(digest) = &params.digest {
+                digest.verify(&self.error_path(&path), &contents)?;
+            }
+            #[cfg(feature = "gzip")]
+            let contents = match encoding {
+                Encoding::Gzip => self.gunzip(&path, &contents)?,
+                _ => contents,
+            };
+            let contents = match encoding {
+                Encoding::Base64Decode => self.base64_decode(&path, contents)?,
+                _ => contents,
+            };
+            let contents = match params.lines {
+                Some(lines) => first_lines(contents, lines),
+                None => contents,
+            };
+            let contents = match &reference.selection {
+                Some(selection) => selection.apply(&self.error_path(&path), contents)?,
+                None => contents,
+            };
+            let expansion = self.file_value(&path, contents, encoding, params.charset, hint)?;
+            return Ok((expansion, path));
+        }
+
+        let missing = errors
+            .iter()
+            .all(|(_, e)| e.kind() == io::ErrorKind::NotFound);
+        let (path, e) = errors.pop().expect("no candidate paths");
+        match reference.default {
+            Some(default) if missing => {
+                let expansion = Expansion::from_string(default.to_string(), hint);
+                self.listener.file_resolved(&path, expansion.as_resolved());
+                Ok((expansion, path))
+            }
+            _ if errors.is_empty() => Err(self.read_error(path, e)),
+            _ => {
+                let mut message = String::new();
+                for (path, e) in &errors {
+                    let _ = write!(message, "{}: {e}; ", self.error_path(path).display());
+                }
+                let _ = write!(message, "{}: {e}", self.error_path(&path).display());
+                let err = E::custom(format_args!("error reading files {message}"));
+                self.state.file_error = Some((path, e));
+                Err(err)
+            }
+        }
+    }
+
+    /// Determines the candidate paths of a file reference's argument.
+    ///
+    /// A reference to standard input has the single pseudo-path `-`.
+    fn candidate_paths<E>(&self, arg: &str) -> Result<Vec<PathBuf>, E>
+    where
+        E: de::Error,
+    {
+        if self.is_stdin(arg) {
+            return Ok(vec![PathBuf::from("-")]);
+        }
+
+        match &self.options.candidate_separator {
+            Some(separator) => arg
+                .split(&**separator)
+                .map(|arg| self.reference_path(arg))
+                .collect(),
+            None => Ok(vec![self.reference_path(arg)?]),
+        }
+    }
+
+    /// Determines if a file reference's argument refers to standard input.
+    fn is_stdin(&self, arg: &str) -> bool {
+        self.options.stdin_file && arg == "-"
+    }
+
+    /// Reads the contents of standard input for a `${file:-}` reference.
+    ///
+    /// Standard input is reported to the listener with a pseudo-path of `-`. It can only be read
+    /// once per deserialization, and its contents are never cached.
+    fn read_stdin<E>(&mut self, path: &Path) -> Result<io::Result<Vec<u8>>, E>
+    where
+        E: de::Error,
+    {
+        if mem::replace(&mut self.state.stdin_read, true) {
+            return Err(E::custom(
+                "standard input can only be referenced once per deserialization",
+            ));
+        }
+        if let Err(e) = self.listener.before_file_read(path) {
+            return Err(E::custom(format_args!("error reading standard input: {e}")));
+        }
+
+        let max_total_bytes = self.options.max_total_bytes;
+        let remaining = max_total_bytes.map(|max| max.saturating_sub(self.state.stats.total_bytes));
+        let result = std_read_stdin(self.options.max_file_size, remaining, &|| {
+            total_too_large(max_total_bytes.unwrap_or(0))
+        });
+        self.listener.file_read(path, &result);
+        self.file_accessed(path, Access::Read, &result);
+        if let Ok(contents) = &result {
+            self.state.stats.files_read += 1;
+            self.state.stats.total_bytes += contents.len() as u64;
+        }
+        Ok(result)
+    }
+
+    /// Decodes and transforms the contents of a file read by a reference.
+    fn file_value<E>(
+        &mut self,
+        path: &Path,
+        contents: Vec<u8>,
+        encoding: Encoding,
+        charset: Charset,
+        hint: Hint,
+    ) -> Result<Expansion, E>
+    where
+        E: de::Error,
+    {
+        let error_path = self.error_path(path);
+        let expansion = self.decode(
+            &format_args!("file {}", error_path.display()),
+            Some(path),
+            contents,
+            encoding,
+            charset,
+            hint,
+        )?;
+        let expansion = match (expansion, &mut self.resolvers.transform) {
+            (Expansion::String(value), Some(transform)) => {
+                Expansion::String(transform(path, value).map_err(|e| {
+                    E::custom(format_args!(
+                        "error transforming file {}: {e}",
+                        error_path.display()
+                    ))
+                })?)
+            }
+            (expansion, _) => expansion,
+        };
+        if self.options.error_on_empty && expansion.is_empty() {
+            return Err(empty_file(&error_path));
+        }
+
+        self.listener.file_resolved(path, expansion.as_resolved());
+        file_value::record(path);
+        Ok(expansion)
+    }
+
+    /// Resolves a secret from the first configured source which provides it.
+    ///
+    /// Secrets are not looked up while collecting references or in dry-run mode, since they may be
+    /// read from files.
+    fn read_secret<E>(
+        &mut self,
+        raw: &str,
+        reference: &Reference<'_>,
+        hint: Hint,
+    ) -> Result<Expansion, E>
+    where
+        E: de::Error,
+    {
+        let name = reference.arg;
+        if self.state.references.is_some() {
+            return Ok(Expansion::from_string(String::new(), hint));
+        }
+        if self.is_dry_run() {
+            let placeholder = self.options.dry_run_placeholder.as_deref().unwrap_or(raw);
+            return Ok(Expansion::from_string(placeholder.to_string(), hint));
+        }
+
+        let options = self.options;
+        for source in &options.secret_sources {
+            match source {
+                SecretSource::Env => {
+                    let value = env::var(name);
+                    self.listener.env_read(name, &value);
+                    match value {
+                        Ok(value) => return Ok(Expansion::from_string(value, hint)),
+                        Err(env::VarError::NotPresent) => {}
+                        Err(e) => {
+                            return Err(E::custom(format_args!(
+                                "error reading environment variable {name}: {e}"
+                            )))
+                        }
+                    }
+                }
+                SecretSource::Dir(dir) => {
+                    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\'])
+                    {
+                        return Err(E::custom(format_args!(
+                            "invalid secret name `{name}`: names cannot contain path separators"
+                        )));
+                    }
+
+                    let path = self.resolve_path(&dir.join(name).to_string_lossy());
+                    self.record_dependency(&path);
+                    match self.read_contents(&path, None, false)? {
+                        Ok(contents) => {
+                            return self.file_value(
+                                &path,
+                                contents,
+                                Encoding::None,
+                                Charset::Utf8,
+                                hint,
+                            )
+                        }
+                        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                        Err(e) => return Err(self.read_error(path, e)),
+                    }
+                }
+            }
+        }
+
+        match reference.default {
+            Some(default) => Ok(Expansion::from_string(default.to_string(), hint)),
+            None => Err(E::custom(format_args!(
+                "error reading secret {name}: not found in any source"
+            ))),
+        }
+    }
+
+    /// Looks up the format registered for structured file references with the given name.
+    fn format(&self, name: &str) -> Option<&dyn DynFormat> {
+        let formats = &self.options.formats;
+        let format = match formats.get(name) {
+            Some(format) => format,
+            None if self.options.case_insensitive_schemes => {
+                formats
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(name))?
+                    .1
+            }
+            None => return None,
+        };
+        Some(&**format)
+    }
+
+    /// Determines if the value consists of a single structured, file lines, or dotenv file
+    /// reference.
+    pub(crate) fn is_structured(&self, s: &str, hint: Hint) -> bool {
+        if !self.options.enabled || hint == Hint::Key && !self.options.expand_keys {
+            return false;
+        }
+
+        matches!(
+            self.parse_reference(s),
+            Some(Reference {
+                scheme: Scheme::Structured(_) | Scheme::Lines | Scheme::Dotenv,
+                ..
+            })
+        )
+    }
+
+    /// Reads a structured file reference and passes its parsed contents to the visitor,
+    /// or reads a file lines reference and passes its lines to the visitor as a sequence.
+    ///
+    /// The value must have been checked with `is_structured`.
+    pub(crate) fn deserialize_structured<'de, V, E>(
+        &mut self,
+        s: &str,
+        visitor: V,
+    ) -> Result<V::Value, E>
+    where
+        V: de::Visitor<'de>,
+        E: de::Error,
+    {
+        if let Some(scanned) = &mut self.state.scanned {
+            scanned.push(s.to_string());
+            return visitor.visit_str("");
+        }
+
+        self.state.file_error = None;
+        self.deserialize_structured_inner(s, visitor)
+            .map_err(|e| self.locate(e))
+    }
+
+    fn deserialize_structured_inner<'de, V, E>(
+        &mut self,
+        s: &str,
+        visitor: V,
+    ) -> Result<V::Value, E>
+    where
+        V: de::Visitor<'de>,
+        E: de::Error,
+    {
+        let Some(reference) = self.parse_reference(s) else {
+            unreachable!("not a structured reference");
+        };
+        if self.is_dry_run() {
+            let placeholder = self.dry_run_file(s, &reference)?;
+            return visitor.visit_string(placeholder);
+        }
+
+        let Reference {
+            scheme,
+            arg,
+            params,
+            selection,
+            default,
+        } = reference;
+
+        let path = self.reference_path(arg)?;
+        let params = parse_params(&self.error_path(&path), params)?;
+        if let Some(references) = &mut self.state.references {
+            references.insert(path);
+            return visitor.visit_str("");
+        }
+        self.record_dependency(&path);
+        self.listener.path_resolved(s, &path);
+
+        let contents = self.read_contents(&path, params.max_lines(), default.is_none())?;
+        if let (Ok(contents), Some(digest)) = (&contents, &params.digest) {
+            digest.verify(&self.error_path(&path), contents)?;
+        }
+        let contents = match (contents, params.lines) {
+            (Ok(contents), Some(lines)) => Ok(first_lines(contents, lines)),
+            (contents, _) => contents,
+        };
+        let charset = params.charset;
+        let contents = match (contents, &selection) {
+            (Ok(contents), Some(selection)) => {
+                Ok(selection.apply(&self.error_path(&path), contents)?)
+            }
+            (contents, _) => contents,
+        };
+        let format = match scheme {
+            Scheme::Structured(format) => format,
+            Scheme::Lines => return self.visit_lines(path, contents, charset, default, visitor),
+            Scheme::Dotenv => return self.visit_dotenv(path, contents, charset, default, visitor),
+            _ => unreachable!("not a structured reference"),
+        };
+        match contents {
+            Ok(contents) if self.options.error_on_empty && contents.is_empty() => {
+                Err(empty_file(&self.error_path(&path)))
+            }
+            Ok(contents) => {
+                let format = self.format(format).expect("format not registered");
+                format
+                    .parse(&charset.to_utf8(contents))
+                    .and_then(|content| {
+                        let deserializer =
+                            de::IntoDeserializer::<de::value::Error>::into_deserializer(content);
+                        de::Deserializer::deserialize_any(deserializer, visitor)
+                            .map_err(|e| e.to_string())
+                    })
+                    .map_err(|e| {
+                        E::custom(format_args!(
+                            "error deserializing file {}: {e}",
+                            self.error_path(&path).display()
+                        ))
+                    })
+            }
+            Err(e) => match default {
+                Some(default) if e.kind() == io::ErrorKind::NotFound => visitor.visit_str(default),
+                _ => Err(self.read_error(path, e)),
+            },
+        }
+    }
+
+    /// Splits the contents of a file lines reference into lines and passes them to the visitor.
+    fn visit_lines<'de, V, E>(
+        &mut self,
+        path: PathBuf,
+        contents: io::Result<Vec<u8>>,
+        charset: Charset,
+        default: Option<&str>,
+        visitor: V,
+    ) -> Result<V::Value, E>
+    where
+        V: de::Visitor<'de>,
+        E: de::Error,
+    {
+        let contents = self.file_text(path, contents, charset, default)?;
+        let lines = split_lines(&contents)
+            .into_iter()
+            .map(|line| {
+                let mut line = line.to_string();
+                if self.options.trim {
+                    trim(&mut line);
+                }
+                line
+            })
+            .collect::<Vec<_>>();
+        visitor.visit_seq(de::value::SeqDeserializer::new(lines.into_iter()))
+    }
+
+    /// Parses the contents of a dotenv file reference and passes its entries to the visitor as a
+    /// map.
+    fn visit_dotenv<'de, V, E>(
+        &mut self,
+        path: PathBuf,
+        contents: io::Result<Vec<u8>>,
+        charset: Charset,
+        default: Option<&str>,
+        visitor: V,
+    ) -> Result<V::Value, E>
+    where
+        V: de::Visitor<'de>,
+        E: de::Error,
+    {
+        let error_path = self.error_path(&path).into_owned();
+        let contents = self.file_text(path, contents, charset, default)?;
+        let entries = parse_dotenv(&contents).map_err(|e| {
+            E::custom(format_args!(
+                "error parsing dotenv file {}: {e}",
+                error_path.display()
+            ))
+        })?;
+        visitor.visit_map(de::value::MapDeserializer::new(entries.into_iter()))
+    }
+
+    /// Decodes and transforms the contents of a file lines or dotenv file reference, or returns
+    /// the default value if the file is missing.
+    fn file_text<E>(
+        &mut self,
+        path: PathBuf,
+        contents: io::Result<Vec<u8>>,
+        charset: Charset,
+        default: Option<&str>,
+    ) -> Result<String, E>
+    where
+        E: de::Error,
+    {
+        match contents {
+            Ok(contents) => {
+                let error_path = self.error_path(&path);
+                let source = format_args!("file {}", error_path.display());
+                let Expansion::String(contents) = self.decode(
+                    &source,
+                    Some(&path),
+                    contents,
+                    Encoding::None,
+                    charset,
+                    Hint::Any,
+                )?
+                else {
+                    unreachable!("decoded as bytes");
+                };
+                let contents = match &mut self.resolvers.transform {
+                    Some(transform) => transform(&path, contents).map_err(|e| {
+                        E::custom(format_args!(
+                            "error transforming file {}: {e}",
+                            error_path.display()
+                        ))
+                    })?,
+                    None => contents,
+                };
+                if self.options.error_on_empty && contents.is_empty() {
+                    return Err(empty_file(&error_path));
+                }
+                self.listener
+                    .file_resolved(&path, Resolved::String(&contents));
+                Ok(contents)
+            }
+            Err(e) => match default {
+                Some(default) if e.kind() == io::ErrorKind::NotFound => Ok(default.to_string()),
+                _ => Err(self.read_error(path, e)),
+            },
+        }
+    }
+
+    /// Records a reference to a file in the dependency graph, if one is being built.
+    fn record_dependency(&mut self, path: &Path) {
+        let state = &mut *self.state;
+        if let Some(graph) = &mut state.graph {
+            graph.add(state.parents.last().map(|p| &**p), path);
+        }
+    }
+
+    /// Reads the contents of an inherited file descriptor.
+    ///
+    /// The descriptor is reported to the listener with a pseudo-path of `/dev/fd/N`. Its contents
+    /// are never cached, since reading consumes them.
+    fn read_fd<E>(
+        &mut self,
+        raw: &str,
+        reference: &Reference<'_>,
+        hint: Hint,
+    ) -> Result<Expansion, E>
+    where
+        E: de::Error,
+    {
+        let fd = reference
+            .arg
+            .parse::<i32>()
+            .ok()
+            .filter(|&fd| fd >= 0)
+            .ok_or_else(|| {
+                E::custom(format_args!(
+                    "invalid file descriptor `{}` in reference {}",
+                    self.error_reference(reference.arg),
+                    self.error_reference(raw)
+                ))
+            })?;
+        let path = PathBuf::from(format!("/dev/fd/{fd}"));
+        if self.state.references.is_some() {
+            return Ok(Expansion::from_string(String::new(), hint));
+        }
+        self.listener.path_resolved(raw, &path);
+        if self.is_dry_run() {
+            self.listener.file_referenced(&path);
+            let placeholder = self.options.dry_run_placeholder.as_deref().unwrap_or(raw);
+            return Ok(Expansion::from_string(placeholder.to_string(), hint));
+        }
+
+        if !cfg!(unix) {
+            return Err(E::custom(
+                "file descriptor references are only supported on Unix",
+            ));
+        }
+
+        // the descriptor is opened through its path rather than used directly, since it is not
+        // owned by the deserializer and may not even be open
+        match self.read_contents(&path, None, true)? {
+            Ok(contents) => self.file_value(&path, contents, Encoding::None, Charset::Utf8, hint),
+            Err(e) => Err(self.read_error(path, e)),
+        }
+    }
+
+    /// Reads a file, consulting the listener and the cache.
+    ///
+    /// If `max_lines` is set, reading may stop once that many lines have been read. The contents
+    /// of such reads are not cached since they may be incomplete. If `retry_missing` is not set, a
+    /// missing file is reported immediately rather than retried.
+    fn read_contents<E>(
+        &mut self,
+        path: &Path,
+        max_lines: Option<usize>,
+        retry_missing: bool,
+    ) -> Result<io::Result<Vec<u8>>, E>
+    where
+        E: de::Error,
+    {
+        if let Err(e) = self.listener.before_file_read(path) {
+            return Err(E::custom(format_args!(
+                "error reading file {}: {e}",
+                self.error_path(path).display()
+            )));
+        }
+
+        let value = match self.state.cache.get(path) {
+            Some(contents) => {
+                self.state.stats.cache_hits += 1;
+                let value = Ok(contents.clone());
+                self.file_accessed(path, Access::CacheHit, &value);
+                value
+            }
+            None => {
+                let (value, metadata) = match self.load(path, max_lines, retry_missing) {
+                    Ok((contents, metadata)) => (Ok(contents), metadata),
+                    Err(e) => (Err(e), None),
+                };
+                self.listener.file_read(path, &value);
+                self.file_accessed(path, Access::Read, &value);
+                if let Some(metadata) = &metadata {
+                    self.listener.file_metadata(path, metadata);
+                    if self.options.warn_insecure_permissions {
+                        self.check_permissions(path, metadata);
+                    }
+                    if let Some(root) = &self.options.recommended_root {
+                        self.check_recommended_root(path, root);
+                    }
+                }
+                if let Ok(contents) = &value {
+                    self.state.stats.files_read += 1;
+                    self.state.stats.total_bytes += contents.len() as u64;
+                }
+                if let (true, None, Ok(contents)) = (self.options.cache, max_lines, &value) {
+                    self.state
+                        .cache
+                        .insert(path.to_path_buf(), contents.clone());
+                }
+                value
+            }
+        };
+        Ok(value)
+    }
+
+    /// Notifies the listener if a file is readable by its group or by other users.
+    #[cfg(unix)]
+    fn check_permissions(&mut self, path: &Path, metadata: &Metadata) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = metadata.permissions().mode();
+        if mode & 0o044 != 0 {
+            self.listener.insecure_permissions(path, mode);
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn check_permissions(&mut self, _: &Path, _: &Metadata) {}
+
+    /// Reports an access to a file, successful or not.
+    fn file_accessed(&mut self, path: &Path, access: Access, result: &io::Result<Vec<u8>>) {
+        self.state.stats.any_reads = true;
+        self.listener.file_accessed(path, access, result);
+        #[cfg(feature = "tracing")]
+        trace_read(path, access, result);
+    }
+
+    fn check_recommended_root(&mut self, path: &Path, root: &Path) {
+        // the file was just read, so a failure to canonicalize it is not worth reporting
+        let Ok(canonical) = canonicalize(self.options, path) else {
+            return;
+        };
+        let canonical_root = canonicalize(self.options, root);
+        if !canonical.starts_with(canonical_root.as_deref().unwrap_or(root)) {
+            self.listener.outside_recommended_root(path, root);
+        }
+    }
+
+    /// Returns the form of a path included in error messages.
+    fn error_path<'p>(&self, path: &'p Path) -> Cow<'p, Path> {
+        redact_path(self.options, path)
+    }
+
+    fn error_reference<'r>(&self, reference: &'r str) -> Cow<'r, str> {
+        redact_reference(self.options, reference)
+    }
+
+    fn read_error<E>(&mut self, path: PathBuf, e: io::Error) -> E
+    where
+        E: de::Error,
+    {
+        let err = E::custom(format_args!(
+            "error reading file {}: {e}",
+            self.error_path(&path).display()
+        ));
+        self.state.file_error = Some((path, e));
+        err
+    }
+
+    fn decode<E>(
+        &mut self,
+        source: &dyn fmt::Display,
+        path: Option<&Path>,
+        contents: Vec<u8>,
+        encoding: Encoding,
+        charset: Charset,
+        hint: Hint,
+    ) -> Result<Expansion, E>
+    where
+        E: de::Error,
+    {
+        match encoding {
+            Encoding::None | Encoding::Base64Decode => {}
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => {}
+            Encoding::Base64 => {
+                let encoded = match self.options.base64_alphabet {
+                    Base64Alphabet::Standard => base64::engine::general_purpose::STANDARD,
+                    Base64Alphabet::UrlSafe => base64::engine::general_purpose::URL_SAFE,
+                }
+                .encode(contents);
+                return Ok(Expansion::from_string(encoded, hint));
+            }
+            Encoding::Hex => {
+                let encoded = hex_encode(&contents, self.options.hex_uppercase);
+                return Ok(Expansion::from_string(encoded, hint));
+            }
+        }
+
+        if let Hint::Bytes | Hint::Seq = hint {
+            return Ok(Expansion::Bytes(contents));
+        }
+
+        let contents = match charset {
+            Charset::Utf8 => String::from_utf8(contents),
+            Charset::Latin1 => Ok(latin1_decode(&contents)),
+        };
+        let mut contents = match (contents, path, &mut self.resolvers.utf8_error_handler) {
+            (Ok(contents), _, _) => contents,
+            (Err(e), Some(path), Some(handler)) => handler(path, e.into_bytes())
+                .map_err(|e| E::custom(format_args!("error decoding {source}: {e}")))?,
+            (Err(e), _, _) if self.options.utf8_lossy => {
+                String::from_utf8_lossy(e.as_bytes()).into_owned()
+            }
+            (Err(e), _, _) => return Err(E::custom(format_args!("error parsing {source}: {e}"))),
+        };
+        if self.options.strip_bom && contents.starts_with('\u{feff}') {
+            contents.drain(..'\u{feff}'.len_utf8());
+        }
+        if self.options.trim {
+            trim(&mut contents);
+        } else if self.options.trim_trailing_newline {
+            trim_trailing_newline(&mut contents);
+        }
+        if self.options.join_lines {
+            contents.retain(|c| c != '\n' && c != '\r');
+        }
+        Ok(Expansion::String(contents))
+    }
+
+    /// Decodes the contents of a base64 file, ignoring any ASCII whitespace.
+    fn base64_decode<E>(&self, path: &Path, mut contents: Vec<u8>) -> Result<Vec<u8>, E>
+    where
+        E: de::Error,
+    {
+        contents.retain(|b| !b.is_ascii_whitespace());
+        match self.options.base64_alphabet {
+            Base64Alphabet::Standard => base64::engine::general_purpose::STANDARD,
+            Base64Alphabet::UrlSafe => base64::engine::general_purpose::URL_SAFE,
+        }
+        .decode(contents)
+        .map_err(|e| {
+            E::custom(format_args!(
+                "error decoding base64 file {}: {e}",
+                self.error_path(path).display()
+            ))
+        })
+    }
+
+    /// Decompresses the contents of a gzip-compressed file, subject to the file size limit.
+    #[cfg(feature = "gzip")]
+    fn gunzip<E>(&self, path: &Path, contents: &[u8]) -> Result<Vec<u8>, E>
+    where
+        E: de::Error,
+    {
+        use std::io::Read;
+
+        use flate2::read::GzDecoder;
+
+        let error = |e: &dyn fmt::Display| {
+            E::custom(format_args!(
+                "error decompressing file {}: {e}",
+                self.error_path(path).display()
+            ))
+        };
+
+        // the decompressed contents count towards the total size limit in place of the compressed
+        // contents, which have already been counted if they were read rather than cached
+        let max_total_bytes = self.options.max_total_bytes;
+        let counted = self
+            .state
+            .stats
+            .total_bytes
+            .saturating_sub(contents.len() as u64);
+        let remaining = max_total_bytes.map(|max| max.saturating_sub(counted));
+        let (limit, too_large) = match (remaining, self.options.max_file_size) {
+            (Some(remaining), Some(max_file_size)) if max_file_size <= remaining => {
+                (Some(max_file_size), file_too_large(max_file_size))
+            }
+            (Some(remaining), _) => (
+                Some(remaining),
+                total_too_large(max_total_bytes.unwrap_or(0)),
+            ),
+            (None, max_file_size) => (max_file_size, file_too_large(max_file_size.unwrap_or(0))),
+        };
+
+        let mut decoder = GzDecoder::new(contents);
+        let mut buf = vec![];
+        let result = match limit {
+            // decompression stops just past the limit, so a small file can't expand without bound
+            Some(limit) => (&mut decoder)
+                .take(limit.saturating_add(1))
+                .read_to_end(&mut buf),
+            None => decoder.read_to_end(&mut buf),
+        };
+        if let Err(e) = result {
+            return Err(error(&e));
+        }
+        if limit.is_some_and(|limit| buf.len() as u64 > limit) {
+            return Err(error(&too_large));
+        }
+
+        Ok(buf)
+    }
+
+    /// Determines the path of the file referenced by a reference's argument.
+    fn reference_path<E>(&self, arg: &str) -> Result<PathBuf, E>
+    where
+        E: de::Error,
+    {
+        if !self.options.path_env_vars {
+            return Ok(self.resolve_path(arg));
+        }
+
+        let arg = expand_env_vars(arg).map_err(|e| {
+            E::custom(format_args!(
+                "error expanding variables in path {}: {e}",
+                self.error_path(Path::new(arg)).display()
+            ))
+        })?;
+        Ok(self.resolve_path(&arg))
+    }
+
+    fn resolve_path(&self, path: &str) -> PathBuf {
+        // Joining an absolute path replaces the existing one, so an absolute base directory takes
+        // precedence over the config file's directory, and an absolute reference over both.
+        let mut resolved = PathBuf::new();
+        if let Some(config_dir) = &self.options.config_dir {
+            resolved.push(config_dir);
+        }
+        if let Some(base_dir) = &self.options.base_dir {
+            resolved.push(base_dir);
+        }
+        resolved.push(path);
+        resolved
+    }
+
+    /// Loads a file's contents along with its metadata, if it was read from the filesystem.
+    fn load(
+        &mut self,
+        path: &Path,
+        max_lines: Option<usize>,
+        retry_missing: bool,
+    ) -> io::Result<(Vec<u8>, Option<Metadata>)> {
+        let max_total_bytes = self.options.max_total_bytes;
+        let remaining = max_total_bytes.map(|max| max.saturating_sub(self.state.stats.total_bytes));
+
+        let (contents, metadata) = match self.options.files.get(path) {
+            Some(contents) => (contents.clone(), None),
+            None => match self.state.prefetched.remove(path) {
+                // prefetching never waits for missing files, so retry them here if needed
+                Some(Err(e)) if retry_missing && e.kind() == io::ErrorKind::NotFound => {
+                    return read_checked(self.options, path, remaining, max_lines, true)
+                }
+                Some(result) => result?,
+                None => {
+                    return read_checked(self.options, path, remaining, max_lines, retry_missing)
+                }
+            },
+        };
+
+        if remaining.is_some_and(|remaining| contents.len() as u64 > remaining) {
+            return Err(total_too_large(max_total_bytes.unwrap_or(0)));
+        }
+        Ok((contents, metadata))
+    }
+
+    fn read_custom<E>(
+        &mut self,
+        scheme: &str,
+        reference: &Reference<'_>,
+        hint: Hint,
+    ) -> Result<Expansion, E>
+    where
+        E: de::Error,
+    {
+        let ignore_case = self.options.case_insensitive_schemes;
+        match self.resolvers.resolve(scheme, reference.arg, ignore_case) {
+            Ok(value) => self.decode(
+                &format_args!("{scheme} reference {}", reference.arg),
+                None,
+                value,
+                Encoding::None,
+                Charset::Utf8,
+                hint,
+            ),
+            Err(e) => match reference.default {
+                Some(default) if e.kind() == io::ErrorKind::NotFound => {
+                    Ok(Expansion::from_string(default.to_string(), hint))
+                }
+                _ => Err(E::custom(format_args!(
+                    "error resolving {scheme} reference {}: {e}",
+                    reference.arg
+                ))),
+            },
+        }
+    }
+
+    fn read_env<E>(&mut self, reference: &Reference<'_>, hint: Hint) -> Result<Expansion, E>
+    where
+        E: de::Error,
+    {
+        let name = reference.arg;
+        let value = env::var(name);
+        self.listener.env_read(name, &value);
+        match value {
+            Ok(value) => Ok(Expansion::from_string(value, hint)),
+            Err(env::VarError::NotPresent) => match reference.default {
+                Some(default) => Ok(Expansion::from_string(default.to_string(), hint)),
+                None => Err(E::custom(format_args!(
+                    "error reading environment variable {name}: not set"
+                ))),
+            },
+            Err(e) => Err(E::custom(format_args!(
+                "error reading environment variable {name}: {e}"
+            ))),
+        }
+    }
+}
+
+/// Splits a `#` fragment selecting part of the file from the end of a file path.
+///
+/// The fragment starts at the last `#`, and is only split off if it is a well-formed selection, so
+/// a path containing `#` can still be referenced.
+fn split_selection(arg: &str) -> (&str, Option<Selection>) {
+    if let Some((path, fragment)) = arg.rsplit_once('#') {
+        if let Some(selection) = Selection::parse(fragment) {
+            return (path, Some(selection));
+        }
+    }
+
+    (arg, None)
+}
+
+/// Splits the `;`-separated `name=value` parameters from the end of a file reference.
+///
+/// The parameters start at the first `;` which is only followed by well-formed parameters, so a
+/// path containing `;` can still be referenced.
+fn split_params(arg: &str) -> (&str, &str) {
+    for (i, _) in arg.match_indices(';') {
+        let params = &arg[i + 1..];
+        let valid = params.split(';').all(|param| {
+            param.split_once('=').is_some_and(|(name, _)| {
+                !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+            })
+        });
+        if valid {
+            return (&arg[..i], params);
+        }
+    }
+
+    (arg, "")
+}
+
+/// The parameters of a file reference.
+struct Params {
+    charset: Charset,
+    /// The expected digest of the file's contents, if it is pinned.
+    digest: Option<Digest>,
+    /// The maximum number of lines to read, if limited.
+    lines: Option<usize>,
+}
+
+impl Params {
+    /// Returns the number of lines after which a read can stop, if any.
+    ///
+    /// Reads can't stop early if the contents are pinned to a digest, since it covers the entire
+    /// file.
+    fn max_lines(&self) -> Option<usize> {
+        match self.digest {
+            Some(_) => None,
+            None => self.lines,
+        }
+    }
+}
+
+/// An expected digest of a file's contents.
+enum Digest {
+    #[cfg(feature = "sha256")]
+    Sha256([u8; 32]),
+}
+
+impl Digest {
+    /// Checks that the contents of a file match the digest.
+    #[cfg_attr(not(feature = "sha256"), allow(unused_variables))]
+    fn verify<E>(&self, path: &Path, contents: &[u8]) -> Result<(), E>
+    where
+        E: de::Error,
+    {
+        match *self {
+            #[cfg(feature = "sha256")]
+            Digest::Sha256(ref expected) => {
+                use sha2::Digest as _;
+
+                let actual = sha2::Sha256::digest(contents);
+                if actual[..] != expected[..] {
+                    return Err(E::custom(format_args!(
+                        "sha256 digest mismatch for file {}: expected {}, found {}",
+                        path.display(),
+                        hex_encode(expected, false),
+                        hex_encode(&actual, false),
+                    )));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn parse_params<E>(path: &Path, params: &str) -> Result<Params, E>
+where
+    E: de::Error,
+{
+    let mut charset = Charset::Utf8;
+    #[cfg_attr(not(feature = "sha256"), allow(unused_mut))]
+    let mut digest = None;
+    let mut lines = None;
+
+    for param in params.split(';').filter(|p| !p.is_empty()) {
+        let (name, value) = param.split_once('=').unwrap();
+        match name {
+            "encoding" => {
+                charset = match &*value.to_ascii_lowercase() {
+                    "utf-8" | "utf8" => Charset::Utf8,
+                    "latin1" | "latin-1" | "iso-8859-1" => Charset::Latin1,
+                    _ => {
+                        return Err(E::custom(format_args!(
+                            "unknown encoding `{value}` in reference to file {}",
+                            path.display()
+                        )))
+                    }
+                }
+            }
+            "lines" => {
+                lines = Some(parse_number(value).ok_or_else(|| {
+                    E::custom(format_args!(
+                        "invalid line count `{value}` in reference to file {}",
+                        path.display()
+                    ))
+                })?);
+            }
+            #[cfg(feature = "sha256")]
+            "sha256" => {
+                let expected = parse_hex(value)
+                    .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                    .ok_or_else(|| {
+                        E::custom(format_args!(
+                            "invalid sha256 digest `{value}` in reference to file {}",
+                            path.display()
+                        ))
+                    })?;
+                digest = Some(Digest::Sha256(expected));
+            }
+            #[cfg(not(feature = "sha256"))]
+            "sha256" => {
+                return Err(E::custom(format_args!(
+                    "the `sha256` parameter in reference to file {} requires the `sha256` feature",
+                    path.display()
+                )))
+            }
+            _ => {
+                return Err(E::custom(format_args!(
+                    "unknown parameter `{name}` in reference to file {}",
+                    path.display()
+                )))
+            }
+        }
+    }
+
+    Ok(Params {
+        charset,
+        digest,
+        lines,
+    })
+}
+
+/// Splits text into lines, ignoring a single trailing newline and stripping a `\r` preceding each
+/// newline.
+fn split_lines(s: &str) -> Vec<&str> {
+    match s.strip_suffix('\n').unwrap_or(s) {
+        "" => vec![],
+        body => body
+            .split('\n')
+            .map(|line| line.strip_suffix('\r').unwrap_or(line))
+            .collect(),
+    }
+}
+
+/// Parses the `KEY=VALUE` entries of a dotenv file, in order.
+fn parse_dotenv(s: &str) -> Result<Vec<(String, String)>, String> {
+    let mut entries = vec![];
+    for (i, line) in split_lines(s).into_iter().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("line {}: expected `KEY=VALUE`", i + 1));
+        };
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(format!("line {}: missing key", i + 1));
+        }
+
+        let value = value.trim();
+        let value = if let Some(value) = value.strip_prefix('\'') {
+            value
+                .strip_suffix('\'')
+                .ok_or_else(|| format!("line {}: unterminated single-quoted value", i + 1))?
+                .to_string()
+        } else if let Some(value) = value.strip_prefix('"') {
+            unescape_dotenv(value)
+                .ok_or_else(|| format!("line {}: unterminated double-quoted value", i + 1))?
+        } else {
+            value.to_string()
+        };
+        entries.push((key.to_string(), value));
+    }
+
+    Ok(entries)
+}
+
+/// Processes the escapes of a double-quoted dotenv value following its opening quote, returning
+/// `None` if the closing quote is missing or is not at the end of the value.
+fn unescape_dotenv(s: &str) -> Option<String> {
+    let mut value = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return chars.as_str().is_empty().then_some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                c @ ('"' | '\\') => value.push(c),
+                c => {
+                    value.push('\\');
+                    value.push(c);
+                }
+            },
+            c => value.push(c),
+        }
+    }
+
+    None
+}
+
+/// Expands `$VAR` and `${VAR}` environment variable references in a path, along with `$$` escapes.
+fn expand_env_vars(path: &str) -> Result<String, String> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    let mut expanded = String::new();
+    let mut remaining = path;
+    while let Some(start) = remaining.find('$') {
+        expanded.push_str(&remaining[..start]);
+        let rest = &remaining[start + 1..];
+
+        let (name, rest) = if let Some(rest) = rest.strip_prefix('$') {
+            expanded.push('$');
+            remaining = rest;
+            continue;
+        } else if let Some(rest) = rest.strip_prefix('{') {
+            let end = rest
+                .find('}')
+                .ok_or_else(|| "missing closing `}` after `${`".to_string())?;
+            let name = &rest[..end];
+            if name.is_empty() || !name.chars().all(is_name_char) {
+                return Err(format!("invalid variable name `{name}`"));
+            }
+            (name, &rest[end + 1..])
+        } else {
+            let end = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+
+        if name.is_empty() {
+            expanded.push('$');
+        } else {
+            match env::var(name) {
+                Ok(value) => expanded.push_str(&value),
+                Err(env::VarError::NotPresent) => {
+                    return Err(format!("environment variable {name} is not set"))
+                }
+                Err(e) => return Err(format!("environment variable {name}: {e}")),
+            }
+        }
+        remaining = rest;
+    }
+    expanded.push_str(remaining);
+
+    Ok(expanded)
+}
+
+/// Truncates contents to their first `lines` lines, including the newline terminating the last.
+fn first_lines(mut contents: Vec<u8>, lines: usize) -> Vec<u8> {
+    let end = match lines {
+        0 => 0,
+        lines => contents
+            .iter()
+            .enumerate()
+            .filter(|&(_, &b)| b == b'\n')
+            .nth(lines - 1)
+            .map_or(contents.len(), |(i, _)| i + 1),
+    };
+    contents.truncate(end);
+    contents
+}
+
+fn empty_file<E>(path: &Path) -> E
+where
+    E: de::Error,
+{
+    E::custom(format_args!("file {} is empty", path.display()))
+}
+
+fn latin1_decode(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| char::from(b)).collect()
+}
+
+fn trim_trailing_newline(s: &mut String) {
+    if s.ends_with('\n') {
+        s.pop();
+        if s.ends_with('\r') {
+            s.pop();
+        }
+    }
+}
+
+fn trim(s: &mut String) {
+    s.truncate(s.trim_end().len());
+    let start = s.len() - s.trim_start().len();
+    s.drain(..start);
+}
+
+fn hex_encode(bytes: &[u8], uppercase: bool) -> String {
+    let digits = if uppercase {
+        b"0123456789ABCDEF"
+    } else {
+        b"0123456789abcdef"
+    };
+
+    let mut encoded = String::with_capacity(bytes.len() * 2);
+    for &b in bytes {
+        encoded.push(digits[usize::from(b >> 4)] as char);
+        encoded.push(digits[usize::from(b & 0xf)] as char);
+    }
+    encoded
+}
+
+#[cfg(feature = "sha256")]
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 == 1 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Reads a file, subject to the allowed roots and size limits, retrying transient failures.
+///
+/// `remaining` is the number of bytes left before the total size limit is reached, if any. Missing
+/// files are only considered transient if `retry_missing` is set.
+pub(crate) fn read_checked(
+    options: &Arc<Options>,
+    path: &Path,
+    remaining: Option<u64>,
+    max_lines: Option<usize>,
+    retry_missing: bool,
+) -> io::Result<(Vec<u8>, Option<Metadata>)> {
+    let mut retries = options.read_retries;
+    let mut backoff = options.read_retry_backoff;
+    loop {
+        match read_checked_once(options, path, remaining, max_lines) {
+            Err(e) if retries > 0 && is_transient(&e, retry_missing) => {
+                thread::sleep(backoff);
+                retries -= 1;
+                backoff = backoff.saturating_mul(2);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Determines if a read error may be caused by a file being replaced concurrently.
+fn is_transient(e: &io::Error, retry_missing: bool) -> bool {
+    match e.kind() {
+        io::ErrorKind::NotFound => retry_missing,
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock => true,
+        _ => false,
+    }
+}
+
+fn read_checked_once(
+    options: &Arc<Options>,
+    path: &Path,
+    remaining: Option<u64>,
+    max_lines: Option<usize>,
+) -> io::Result<(Vec<u8>, Option<Metadata>)> {
+    match options.read_timeout {
+        Some(timeout) => read_with_timeout(options, path, remaining, max_lines, timeout),
+        None => read_unbounded(options, path, remaining, max_lines),
+    }
+}
+
+/// Reads a file on the current thread, without a timeout.
+fn read_unbounded(
+    options: &Options,
+    path: &Path,
+    remaining: Option<u64>,
+    max_lines: Option<usize>,
+) -> io::Result<(Vec<u8>, Option<Metadata>)> {
+    check_access(options, path)?;
+
+    let max_file_size = options.max_file_size;
+    let total_too_large = || total_too_large(options.max_total_bytes.unwrap_or(0));
+
+    if let Some(filesystem) = &options.filesystem {
+        let contents = filesystem.read(path)?;
+        let len = contents.len() as u64;
+        if let Some(max_file_size) = max_file_size.filter(|&max| len > max) {
+            return Err(file_too_large(max_file_size));
+        }
+        if remaining.is_some_and(|remaining| len > remaining) {
+            return Err(total_too_large());
+        }
+        return Ok((contents, None));
+    }
+
+    std_read(path, max_file_size, remaining, max_lines, &total_too_large)
+}
+
+/// Checks that a file exists and can be opened, subject to the allowed roots, without reading it.
+pub(crate) fn check_readable(options: &Options, path: &Path) -> io::Result<()> {
+    if options.files.contains_key(path) {
+        return Ok(());
+    }
+
+    check_access(options, path)?;
+
+    match &options.filesystem {
+        Some(filesystem) => {
+            if filesystem.exists(path)? {
+                Ok(())
+            } else {
+                Err(io::Error::new(io::ErrorKind::NotFound, "file not found"))
+            }
+        }
+        None => std_check_readable(path),
+    }
+}
+
+/// Reads a file on a helper thread, giving up if it doesn't complete within the timeout.
+///
+/// The helper thread is detached rather than joined if the read times out, and exits once the read
+/// completes.
+fn read_with_timeout(
+    options: &Arc<Options>,
+    path: &Path,
+    remaining: Option<u64>,
+    max_lines: Option<usize>,
+    timeout: Duration,
+) -> io::Result<(Vec<u8>, Option<Metadata>)> {
+    let options = options.clone();
+    let path = path.to_path_buf();
+
+    let (tx, rx) = mpsc::channel();
+    thread::Builder::new()
+        .name("serde-file-value-read".to_string())
+        .spawn(move || {
+            let _ = tx.send(read_unbounded(&options, &path, remaining, max_lines));
+        })?;
+
+    match rx.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("read timed out after {timeout:?}"),
+        )),
+    }
+}
+
+/// Returns the form of a path included in error messages according to the path redaction policy.
+pub(crate) fn redact_path<'p>(options: &Options, path: &'p Path) -> Cow<'p, Path> {
+    match options.path_redaction {
+        PathRedaction::Disabled => Cow::Borrowed(path),
+        PathRedaction::Hidden => Cow::Owned(PathBuf::from("<redacted>")),
+        PathRedaction::Hashed => {
+            let mut hasher = DefaultHasher::new();
+            path.hash(&mut hasher);
+            Cow::Owned(PathBuf::from(format!("<path:{:016x}>", hasher.finish())))
+        }
+    }
+}
+
+/// Returns the form of a reference's text included in error messages according to the path
+/// redaction policy.
+pub(crate) fn redact_reference<'r>(options: &Options, reference: &'r str) -> Cow<'r, str> {
+    match options.path_redaction {
+        PathRedaction::Disabled => Cow::Borrowed(reference),
+        PathRedaction::Hidden => Cow::Borrowed("<redacted>"),
+        PathRedaction::Hashed => {
+            let mut hasher = DefaultHasher::new();
+            reference.hash(&mut hasher);
+            Cow::Owned(format!("<reference:{:016x}>", hasher.finish()))
+        }
+    }
+}
+
+/// Checks that a file may be accessed according to the allowed extensions, symlink policy, and
+/// allowed roots.
+///
+/// The path of a file descriptor is always a symbolic link without an extension, so descriptors are
+/// only subject to the allowed roots.
+fn check_access(options: &Options, path: &Path) -> io::Result<()> {
+    let descriptor = is_descriptor_path(options, path);
+
+    if let (Some(allowed_extensions), false) = (&options.allowed_extensions, descriptor) {
+        let extension = path.extension().map_or(Some(""), |e| e.to_str());
+        if !extension.is_some_and(|extension| allowed_extensions.iter().any(|e| e == extension)) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "{} does not have an allowed extension",
+                    redact_path(options, path).display()
+                ),
+            ));
+        }
+    }
+
+    // Custom filesystems have no concept of symbolic links.
+    if !descriptor && options.filesystem.is_none() && std_is_symlink(path) {
+        match options.symlinks {
+            SymlinkPolicy::Follow => {}
+            SymlinkPolicy::Deny => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!(
+                        "{} is a symbolic link",
+                        redact_path(options, path).display()
+                    ),
+                ))
+            }
+            SymlinkPolicy::WithinAllowedRoots if options.allowed_roots.is_empty() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!(
+                        "{} is a symbolic link and no allowed roots are configured",
+                        redact_path(options, path).display()
+                    ),
+                ))
+            }
+            // The allowed roots are checked against the fully resolved target below.
+            SymlinkPolicy::WithinAllowedRoots => {}
+        }
+    }
+
+    if !options.allowed_roots.is_empty() {
+        check_allowed_roots(options, path)?;
+    }
+
+    Ok(())
+}
+
+/// Determines if a path is that of a file descriptor, like the `/dev/fd/3` of `${fd:3}`, when file
+/// descriptor references are enabled.
+fn is_descriptor_path(options: &Options, path: &Path) -> bool {
+    options.fd_references
+        && path
+            .strip_prefix("/dev/fd")
+            .ok()
+            .and_then(|fd| fd.to_str())
+            .is_some_and(|fd| !fd.is_empty() && fd.bytes().all(|b| b.is_ascii_digit()))
+}
+
+fn canonicalize(options: &Options, path: &Path) -> io::Result<PathBuf> {
+    match &options.filesystem {
+        Some(filesystem) => filesystem.canonicalize(path),
+        None => std_canonicalize(path),
+    }
+}
+
+fn check_allowed_roots(options: &Options, path: &Path) -> io::Result<()> {
+    let path = canonicalize(options, path)?;
+
+    for root in &options.allowed_roots {
+        if let Ok(root) = canonicalize(options, root) {
+            if path.starts_with(root) {
+                return Ok(());
+            }
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!(
+            "{} is outside of the allowed roots",
+            redact_path(options, &path).display()
+        ),
+    ))
+}
+
+#[cfg(feature = "fs")]
+fn std_check_readable(path: &Path) -> io::Result<()> {
+    let metadata = File::open(path)?.metadata()?;
+    if metadata.is_dir() {
+        return Err(is_directory());
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "fs"))]
+fn std_check_readable(_: &Path) -> io::Result<()> {
+    Err(fs_disabled())
+}
+
+#[cfg(feature = "fs")]
+fn std_is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink())
+}
+
+#[cfg(not(feature = "fs"))]
+fn std_is_symlink(_: &Path) -> bool {
+    false
+}
+
+#[cfg(feature = "fs")]
+fn std_is_missing(path: &Path) -> bool {
+    matches!(fs::metadata(path), Err(e) if e.kind() == io::ErrorKind::NotFound)
+}
+
+#[cfg(not(feature = "fs"))]
+fn std_is_missing(_: &Path) -> bool {
+    false
+}
+
+#[cfg(feature = "fs")]
+fn std_canonicalize(path: &Path) -> io::Result<PathBuf> {
+    path.canonicalize()
+}
+
+#[cfg(not(feature = "fs"))]
+fn std_canonicalize(_: &Path) -> io::Result<PathBuf> {
+    Err(fs_disabled())
+}
+
+/// Reads a file from the real filesystem, subject to the size limits.
+#[cfg(feature = "fs")]
+fn std_read(
+    path: &Path,
+    max_file_size: Option<u64>,
+    remaining: Option<u64>,
+    max_lines: Option<usize>,
+    total_too_large: &dyn Fn() -> io::Error,
+) -> io::Result<(Vec<u8>, Option<Metadata>)> {
+    let (contents, metadata) = match (remaining, max_file_size) {
+        (Some(remaining), Some(max_file_size)) if max_file_size <= remaining => {
+            read(path, Some(max_file_size), max_lines, &|| {
+                file_too_large(max_file_size)
+            })?
+        }
+        (Some(remaining), _) => read(path, Some(remaining), max_lines, total_too_large)?,
+        (None, max_file_size) => read(path, max_file_size, max_lines, &|| {
+            file_too_large(max_file_size.unwrap_or(0))
+        })?,
+    };
+    Ok((contents, Some(metadata)))
+}
+
+#[cfg(not(feature = "fs"))]
+fn std_read(
+    _: &Path,
+    _: Option<u64>,
+    _: Option<u64>,
+    _: Option<usize>,
+    _: &dyn Fn() -> io::Error,
+) -> io::Result<(Vec<u8>, Option<Metadata>)> {
+    Err(fs_disabled())
+}
+
+#[cfg(not(feature = "fs"))]
+fn fs_disabled() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "file scheme disabled")
+}
+
+/// Reads a file along with metadata taken from the same handle.
+///
+/// If `max_lines` is set, reading stops once that many lines have been read.
+#[cfg(feature = "fs")]
+fn read(
+    path: &Path,
+    max_size: Option<u64>,
+    max_lines: Option<usize>,
+    too_large: &dyn Fn() -> io::Error,
+) -> io::Result<(Vec<u8>, Metadata)> {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    // reading a directory fails with a platform-specific error, or not at all on some platforms
+    if metadata.is_dir() {
+        return Err(is_directory());
+    }
+
+    if let Some(max_lines) = max_lines {
+        // the size of the file doesn't matter if only its first lines are needed
+        let limit = max_size.map_or(u64::MAX, |max_size| max_size.saturating_add(1));
+        let mut reader = BufReader::new(file.take(limit));
+        let mut buf = vec![];
+        for _ in 0..max_lines {
+            if reader.read_until(b'\n', &mut buf)? == 0 {
+                break;
+            }
+        }
+        if max_size.is_some_and(|max_size| buf.len() as u64 > max_size) {
+            return Err(too_large());
+        }
+        return Ok((buf, metadata));
+    }
+
+    let Some(max_size) = max_size else {
+        let mut buf = Vec::with_capacity(metadata.len().try_into().unwrap_or(0));
+        file.read_to_end(&mut buf)?;
+        return Ok((buf, metadata));
+    };
+
+    if metadata.is_file() && metadata.len() > max_size {
+        return Err(too_large());
+    }
+
+    let mut buf = vec![];
+    file.take(max_size.saturating_add(1))
+        .read_to_end(&mut buf)?;
+    if buf.len() as u64 > max_size {
+        return Err(too_large());
+    }
+
+    Ok((buf, metadata))
+}
+
+/// Reads the contents of standard input, unless it is a terminal.
+#[cfg(feature = "fs")]
+fn std_read_stdin(
+    max_file_size: Option<u64>,
+    remaining: Option<u64>,
+    total_too_large: &dyn Fn() -> io::Error,
+) -> io::Result<Vec<u8>> {
+    use std::io::IsTerminal;
+
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        return Err(io::Error::other("standard input is a terminal"));
+    }
+    let stdin = stdin.lock();
+    match (remaining, max_file_size) {
+        (Some(remaining), Some(max_file_size)) if max_file_size <= remaining => {
+            read_limited(stdin, Some(max_file_size), &|| {
+                file_too_large(max_file_size)
+            })
+        }
+        (Some(remaining), _) => read_limited(stdin, Some(remaining), total_too_large),
+        (None, max_file_size) => read_limited(stdin, max_file_size, &|| {
+            file_too_large(max_file_size.unwrap_or(0))
+        }),
+    }
+}
+
+#[cfg(not(feature = "fs"))]
+fn std_read_stdin(
+    _: Option<u64>,
+    _: Option<u64>,
+    _: &dyn Fn() -> io::Error,
+) -> io::Result<Vec<u8>> {
+    Err(fs_disabled())
+}
+
+/// Reads a stream to its end, subject to a size limit.
+#[cfg(feature = "fs")]
+fn read_limited<R>(
+    mut reader: R,
+    max_size: Option<u64>,
+    too_large: &dyn Fn() -> io::Error,
+) -> io::Result<Vec<u8>>
+where
+    R: Read,
+{
+    let mut buf = vec![];
+    match max_size {
+        Some(max_size) => {
+            reader
+                .by_ref()
+                .take(max_size.saturating_add(1))
+                .read_to_end(&mut buf)?;
+            if buf.len() as u64 > max_size {
+                return Err(too_large());
+            }
+        }
+        None => {
+            reader.read_to_end(&mut buf)?;
+        }
+    }
+    Ok(buf)
+}
+
+#[cfg(feature = "fs")]
+fn is_directory() -> io::Error {
+    io::Error::other("expected a file but found a directory")
+}
+
+fn total_too_large(max_total_bytes: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("total size of referenced files exceeds limit of {max_total_bytes} bytes"),
+    )
+}
+
+fn file_too_large(max_size: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("file exceeds size limit of {max_size} bytes"),
+    )
+}
+
+/// Emits a `tracing` event for an access to a referenced file.
+///
+/// Only the path, size, and error are recorded, never the contents of the file.
+#[cfg(feature = "tracing")]
+fn trace_read(path: &Path, access: Access, result: &io::Result<Vec<u8>>) {
+    let cached = access == Access::CacheHit;
+    match result {
+        Ok(contents) => tracing::debug!(
+            path = %path.display(),
+            bytes = contents.len(),
+            cached,
+            "read referenced file",
+        ),
+        Err(e) => tracing::debug!(
+            path = %path.display(),
+            error = %e,
+            cached,
+            "error reading referenced file",
+        ),
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    cell::RefCell,
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Deserializer};
+
+thread_local! {
+    // the innermost `FileValue` being deserialized on this thread, if any, along with the file
+    // recorded for it so far
+    static CAPTURE: RefCell<Option<Option<PathBuf>>> = const { RefCell::new(None) };
+}
+
+/// Records the file a value being deserialized was read from.
+///
+/// Only the first file is kept if a value references more than one.
+pub(crate) fn record(path: &Path) {
+    CAPTURE.with(|capture| {
+        if let Some(slot @ None) = &mut *capture.borrow_mut() {
+            *slot = Some(path.to_path_buf());
+        }
+    });
+}
+
+// restores the enclosing capture even if deserialization panics
+struct Guard(Option<Option<PathBuf>>);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        CAPTURE.with(|capture| *capture.borrow_mut() = self.0.take());
+    }
+}
+
+/// A value which remembers the file it was read from.
+///
+/// `FileValue<T>` deserializes exactly like `T`, but when deserialized by this crate's
+/// [`Deserializer`](crate::Deserializer) it also records the path of the file referenced by the
+/// value, if any. This allows, for example, reloading only the fields of a configuration which came
+/// from a file that changed.
+///
+/// ```
+/// use serde::Deserialize;
+/// use serde_file_value::FileValue;
+/// # use std::fs;
+/// # use tempfile::NamedTempFile;
+///
+/// #[derive(Deserialize)]
+/// struct Config {
+///     username: FileValue<String>,
+///     password: FileValue<String>,
+/// }
+///
+/// # if !cfg!(feature = "fs") { return; }
+/// # let file = NamedTempFile::new().unwrap();
+/// # fs::write(file.path(), "hunter2").unwrap();
+/// # let path = file.path().display().to_string();
+/// let config = format!(r#"{{"username": "admin", "password": "${{file:{path}}}"}}"#);
+/// let mut deserializer = serde_json::Deserializer::from_str(&config);
+/// let config: Config = serde_file_value::deserialize(&mut deserializer, |_, _| {}).unwrap();
+///
+/// assert_eq!(*config.username, "admin");
+/// assert_eq!(config.username.path(), None);
+/// assert_eq!(*config.password, "hunter2");
+/// assert_eq!(config.password.path(), Some(file.path()));
+/// ```
+///
+/// # Provenance
+///
+/// Serde's `Deserialize` trait gives a type no way to ask a generic deserializer where its data
+/// came from, so provenance is passed out of band. While a `FileValue` is deserializing its inner
+/// value, it registers itself in a thread-local slot, and the first file successfully read by a
+/// reference within that value is recorded there. A `FileValue` nested within another reports its
+/// file to the enclosing one as well.
+///
+/// As a consequence, the path is only recorded when the file is read while the `FileValue` itself
+/// is being deserialized. Serde buffers the input of `#[serde(flatten)]` fields and of untagged and
+/// internally tagged enums before the inner types see it, so files referenced by those values are
+/// read too early and no path is recorded. Likewise, no path is recorded when a different
+/// deserializer is used, when references are being collected, or in dry-run mode, and values
+/// replaced by the default of a missing file are not associated with that file.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct FileValue<T> {
+    value: T,
+    path: Option<PathBuf>,
+}
+
+impl<T> FileValue<T> {
+    /// Creates a new `FileValue` which was not read from a file.
+    pub fn new(value: T) -> Self {
+        FileValue { value, path: None }
+    }
+
+    /// Returns the path of the file the value was read from, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Returns a reference to the inner value.
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Consumes the `FileValue`, returning the inner value.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Deref for FileValue<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for FileValue<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<'de, T> Deserialize<'de> for FileValue<T>
+where
+    T: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let guard = Guard(CAPTURE.with(|capture| capture.replace(Some(None))));
+        let value = T::deserialize(deserializer)?;
+        let path = CAPTURE.with(|capture| capture.take()).flatten();
+        drop(guard);
+        if let Some(path) = &path {
+            record(path);
+        }
+
+        Ok(FileValue { value, path })
+    }
+}
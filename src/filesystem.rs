@@ -0,0 +1,62 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+/// A filesystem from which referenced files are read.
+///
+/// By default, files are read from the real filesystem. If the default `fs` Cargo feature is
+/// disabled, no real filesystem access is compiled in, and reads of files which have not been
+/// provided via [`Deserializer::files`](crate::Deserializer::files) fail unless a custom filesystem
+/// is installed. A custom filesystem can be installed with
+/// [`Deserializer::filesystem`](crate::Deserializer::filesystem), for example to serve files from
+/// memory in tests. It is implemented for `HashMap<PathBuf, Vec<u8>>`.
+///
+/// Size limits are enforced after a file has been read from a custom filesystem, and
+/// [`Listener::file_metadata`](crate::Listener::file_metadata) is not called for its files.
+pub trait Filesystem: Send + Sync {
+    /// Reads the contents of a file.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Determines if a file exists.
+    ///
+    /// The default implementation attempts to read the file.
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        match self.read(path) {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the canonical form of a path, which is used to check it against the allowed roots.
+    ///
+    /// The default implementation lexically removes `.` and `..` components.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let mut canonical = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    canonical.pop();
+                }
+                component => canonical.push(component),
+            }
+        }
+        Ok(canonical)
+    }
+}
+
+impl Filesystem for HashMap<PathBuf, Vec<u8>> {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        match self.get(path) {
+            Some(contents) => Ok(contents.clone()),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "file not found")),
+        }
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        Ok(self.contains_key(path))
+    }
+}
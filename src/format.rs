@@ -0,0 +1,107 @@
+use std::marker::PhantomData;
+
+use serde::de;
+
+use crate::content::Content;
+
+/// A data format which can be used with
+/// [`deserialize_from_reader`](crate::deserialize_from_reader), or to parse structured file
+/// references registered with [`Deserializer::format`](crate::Deserializer::format).
+///
+/// # Examples
+///
+/// ```
+/// use serde::de::DeserializeSeed;
+///
+/// struct Json;
+///
+/// impl serde_file_value::Format for Json {
+///     type Error = serde_json::Error;
+///
+///     fn deserialize<'de, S>(&self, input: &'de [u8], seed: S) -> Result<S::Value, Self::Error>
+///     where
+///         S: DeserializeSeed<'de>,
+///     {
+///         let mut deserializer = serde_json::Deserializer::from_slice(input);
+///         let value = seed.deserialize(&mut deserializer)?;
+///         deserializer.end()?;
+///         Ok(value)
+///     }
+/// }
+/// ```
+pub trait Format {
+    /// The error type produced by the format.
+    type Error: de::Error;
+
+    /// Creates a deserializer for the input and passes it to the seed.
+    fn deserialize<'de, S>(&self, input: &'de [u8], seed: S) -> Result<S::Value, Self::Error>
+    where
+        S: de::DeserializeSeed<'de>;
+}
+
+/// JSON, used by [`deserialize_path`](crate::deserialize_path).
+#[cfg(all(feature = "fs", feature = "json"))]
+pub(crate) struct Json;
+
+#[cfg(all(feature = "fs", feature = "json"))]
+impl Format for Json {
+    type Error = serde_json::Error;
+
+    fn deserialize<'de, S>(&self, input: &'de [u8], seed: S) -> Result<S::Value, Self::Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        let mut deserializer = serde_json::Deserializer::from_slice(input);
+        let value = seed.deserialize(&mut deserializer)?;
+        deserializer.end()?;
+        Ok(value)
+    }
+}
+
+/// YAML, used by [`deserialize_path`](crate::deserialize_path).
+#[cfg(all(feature = "fs", feature = "yaml"))]
+pub(crate) struct Yaml;
+
+#[cfg(all(feature = "fs", feature = "yaml"))]
+impl Format for Yaml {
+    type Error = serde_norway::Error;
+
+    fn deserialize<'de, S>(&self, input: &'de [u8], seed: S) -> Result<S::Value, Self::Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(serde_norway::Deserializer::from_slice(input))
+    }
+}
+
+/// TOML, used by [`deserialize_path`](crate::deserialize_path).
+#[cfg(all(feature = "fs", feature = "toml"))]
+pub(crate) struct Toml;
+
+#[cfg(all(feature = "fs", feature = "toml"))]
+impl Format for Toml {
+    type Error = toml::de::Error;
+
+    fn deserialize<'de, S>(&self, input: &'de [u8], seed: S) -> Result<S::Value, Self::Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        let input = std::str::from_utf8(input).map_err(de::Error::custom)?;
+        seed.deserialize(toml::Deserializer::new(input))
+    }
+}
+
+/// An object-safe form of [`Format`] which parses input into an owned value.
+pub(crate) trait DynFormat: Send + Sync {
+    fn parse(&self, input: &[u8]) -> Result<Content, String>;
+}
+
+impl<F> DynFormat for F
+where
+    F: Format + Send + Sync,
+{
+    fn parse(&self, input: &[u8]) -> Result<Content, String> {
+        self.deserialize(input, PhantomData::<Content>)
+            .map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+/// The files referenced during deserialization, and the references between them.
+///
+/// Returned by
+/// [`Deserializer::deserialize_with_graph`](crate::Deserializer::deserialize_with_graph).
+///
+/// Files are only referenced by other files when recursive expansion is enabled. Every file whose
+/// reference was resolved is included, even if it could not be read, so that a watcher can be set
+/// up for a file which does not exist yet.
+///
+/// The graph serializes as a struct with a `roots` sequence and an `edges` map from each file to
+/// the files it references.
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph {
+    roots: BTreeSet<PathBuf>,
+    edges: BTreeMap<PathBuf, BTreeSet<PathBuf>>,
+    files: BTreeSet<PathBuf>,
+}
+
+impl DependencyGraph {
+    /// Records a reference to a file, made by another file or directly by the input.
+    pub(crate) fn add(&mut self, parent: Option<&Path>, path: &Path) {
+        match parent {
+            Some(parent) => {
+                self.edges
+                    .entry(parent.to_path_buf())
+                    .or_default()
+                    .insert(path.to_path_buf());
+            }
+            None => {
+                self.roots.insert(path.to_path_buf());
+            }
+        }
+        self.files.insert(path.to_path_buf());
+    }
+
+    /// Returns the files referenced directly by the input, in sorted order.
+    pub fn roots(&self) -> impl Iterator<Item = &Path> + '_ {
+        self.roots.iter().map(|p| &**p)
+    }
+
+    /// Returns the files referenced directly by the specified file, in sorted order.
+    pub fn dependencies(&self, path: &Path) -> impl Iterator<Item = &Path> + '_ {
+        self.edges.get(path).into_iter().flatten().map(|p| &**p)
+    }
+
+    /// Returns every file referenced, directly or transitively, in sorted order.
+    pub fn files(&self) -> impl Iterator<Item = &Path> + '_ {
+        self.files.iter().map(|p| &**p)
+    }
+}
+
+impl Serialize for DependencyGraph {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_struct("DependencyGraph", 2)?;
+        s.serialize_field("roots", &self.roots)?;
+        s.serialize_field("edges", &self.edges)?;
+        s.end()
+    }
+}
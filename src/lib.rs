@@ -32,14 +32,189 @@
 //!
 //! assert_eq!(config.secret_value, "hunter2");
 //! ```
+//!
+//! Environment variables can be referenced in the same way, like `${env:MY_SECRET}`.
+//! Secrets which may be provided by any of several sources, like an environment variable or a file
+//! in a secrets directory, can be referenced like `${secret:MY_SECRET}` once the sources have been
+//! configured with [`Deserializer::secret_source`].
+//!
+//! Files containing binary data can be referenced as `${file-base64:/path}`, in which case the
+//! value is the base64 encoding of the file's contents. The encoding uses the standard alphabet by
+//! default, which can be changed with [`Deserializer::base64_alphabet`]. Conversely, files
+//! containing base64 data can be referenced as `${file-base64-decode:/path}`, in which case the
+//! file's contents are decoded from base64 with the same alphabet before being processed in the
+//! same way as a `${file:/path}` reference. ASCII whitespace in the file is ignored, so base64
+//! which has been wrapped across multiple lines, like the body of a PEM file, can be decoded.
+//!
+//! With the `gzip` Cargo feature enabled, gzip-compressed files can be referenced as
+//! `${file-gz:/path}`, in which case the file is decompressed before being decoded in the same way
+//! as a `${file:/path}` reference. The listener receives the compressed contents of the file.
+//! [`Deserializer::max_file_size`] also limits the size of the decompressed contents, which count
+//! towards [`Deserializer::max_total_bytes`] in place of the compressed contents.
+//!
+//! Additional schemes can be supported by registering a handler for each with
+//! [`Deserializer::scheme`], or by providing a [`Resolver`]. Registered handlers take precedence
+//! over the built-in schemes, which take precedence over the [`Resolver`]. References with a scheme
+//! which isn't handled are passed through unchanged unless [`Deserializer::deny_unknown_schemes`]
+//! is enabled.
+//!
+//! With the `tracing` Cargo feature enabled, a debug-level event is emitted for each access to a
+//! referenced file, recording its path, its size in bytes or the error reading it, and whether it
+//! was served from the cache. The contents of files are never recorded. Each deserialization runs
+//! within a `serde_file_value::deserialize` span.
+//!
+//! # Defaults
+//!
+//! A reference can provide a default value which is used if the file does not exist or the
+//! environment variable is not set, like `${file:/mnt/secrets/my_secret:-hunter2}`. Other errors,
+//! such as a file which cannot be read due to its permissions, are still reported. The reference is
+//! split at the first `:-`, so everything after it, including any further `:-`, is part of the
+//! default value. As a result, a file whose path contains `:-` cannot be referenced.
+//!
+//! A `?` following the scheme marks a reference as optional, like
+//! `${file?:/mnt/secrets/my_secret}`, which is shorthand for an empty default value. This is useful
+//! for values which must always be present but whose source is best-effort. An explicit default
+//! value takes precedence over the marker.
+//!
+//! # Missing files
+//!
+//! A file which does not exist can be handled in several ways:
+//!
+//! * An empty default value, like `${file:/mnt/secrets/my_secret:-}`, replaces it with an empty
+//!   string, as does marking the reference optional, like `${file?:/mnt/secrets/my_secret}`.
+//! * [`Deserializer::missing_as_none`] deserializes an optional value referencing it as `None`.
+//! * The listener receives the original [`io::Error`], whose [`io::ErrorKind::NotFound`] kind can
+//!   be checked.
+//! * [`deserialize_with_error`] and [`Deserializer::deserialize_with_error`] return an [`Error`]
+//!   which exposes the original [`io::Error`] of a failed read alongside the deserializer's error.
+//!
+//! # Errors
+//!
+//! An error resolving a reference is prefixed with the location of the value within the document,
+//! like `servers[0].password: error reading file /mnt/secrets/password: ...`. Map keys which aren't
+//! strings or numbers are shown as `?`.
+//!
+//! The deserializer composes with [`serde_path_to_error`](https://docs.rs/serde_path_to_error) in
+//! either order, and the path it reports for a failed reference is the location of the reference.
+//! Since that path is also included in the error message, the prefix can be disabled with
+//! [`Deserializer::error_locations`]:
+//!
+//! ```
+//! use serde::Deserialize;
+//! use serde_file_value::Deserializer;
+//!
+//! #[derive(Deserialize, Debug)]
+//! struct Config {
+//!     password: String,
+//! }
+//!
+//! let mut deserializer = serde_json::Deserializer::from_str(r#"{"password": "${env:MISSING_PASSWORD}"}"#);
+//! let mut listener = |_: &_, _: &_| ();
+//! let deserializer = Deserializer::new(&mut deserializer, &mut listener).error_locations(false);
+//! let error = serde_path_to_error::deserialize::<_, Config>(deserializer).unwrap_err();
+//!
+//! assert_eq!(error.path().to_string(), "password");
+//! assert!(error.to_string().starts_with("password: error reading environment variable MISSING_PASSWORD"));
+//! ```
+//!
+//! # Parameters
+//!
+//! A file reference can be followed by `;`-separated `name=value` parameters, like
+//! `${file:/mnt/secrets/my_secret;encoding=latin1}`. The following parameters are supported:
+//!
+//! * `encoding` - The character set used to decode the file's contents as text. Either `utf-8` (the
+//!   default) or `latin1`. Values deserialized as bytes still receive the raw contents of the file.
+//! * `lines` - The maximum number of lines to read, like `${file:/var/log/app.log;lines=10}`.
+//!   Reading stops once that many lines have been read, so only the start of a large file is
+//!   loaded, and a file with fewer lines is read in full. The listener may receive only the portion
+//!   of the file which was read, and it is not cached. The lines keep their terminators, so the
+//!   value ends with a newline unless it is trimmed. For gzip-compressed and base64-decoded files,
+//!   the limit applies to the decompressed or decoded contents, and files pinned to a digest are
+//!   always read in full. A selection is applied to the limited contents, so `#L<n>` must refer to
+//!   one of the first lines and a byte range must lie within them.
+//! * `sha256` - The expected SHA-256 digest of the file's contents, in hex. If the contents, as
+//!   read from the file before any decompression or decoding, have a different digest, an error is
+//!   produced. This requires the `sha256` Cargo feature, and SHA-256 is currently the only
+//!   supported algorithm.
+//!
+//! Unknown parameters and encodings produce an error. Parameters precede any default value, like
+//! `${file:/path;encoding=latin1:-hunter2}`.
+//!
+//! # Selections
+//!
+//! A file path can be followed by a `#` fragment selecting part of the file's contents, like
+//! `${file:/mnt/secrets/combined#L2}`. The following selections are supported:
+//!
+//! * `#L<n>` - Line `n`, numbered from 1, without its line terminator.
+//! * `#<start>-<end>` - The bytes from offset `start` up to but not including offset `end`, counted
+//!   from 0.
+//! * `#/<pointer>` - The string, number, or boolean at a
+//!   [JSON Pointer](https://www.rfc-editor.org/rfc/rfc6901) within a JSON file, like
+//!   `${file:/mnt/secrets/bundle.json#/db/password}`. Each `/` starts a reference token naming an
+//!   object member or a 0-based array index, and `~1` and `~0` stand for a literal `/` and `~`
+//!   within a token. A string is selected without its quotes, and a pointer which does not exist or
+//!   which refers to an object, array, or null produces an error. Pointers containing `#` or `:-`
+//!   are not supported. This requires the `json` Cargo feature.
+//!
+//! The selection is applied to the raw contents of the file before any other decoding, and a
+//! selection outside of the file produces an error. The fragment precedes any parameters, like
+//! `${file:/path#L2;encoding=latin1}`. A path containing `#` can still be referenced as long as the
+//! text after its last `#` is not a valid selection.
+//!
+//! # Escaping
+//!
+//! A reference can be preceded by backslashes to prevent it from being expanded. The backslashes
+//! are processed in pairs: each `\\` becomes a single literal `\`, and a remaining unpaired `\`
+//! causes the reference to be passed through literally rather than read:
+//!
+//! * `${file:/path}` is replaced with the contents of `/path`.
+//! * `\${file:/path}` becomes the literal string `${file:/path}`.
+//! * `\\${file:/path}` becomes a `\` followed by the contents of `/path`.
+//!
+//! Backslashes are only processed when they are immediately followed by a reference that makes up
+//! the rest of the value. All other strings, including one consisting only of backslashes, are
+//! passed through unchanged. When [`Deserializer::inline`] is enabled, the same rules apply to the
+//! backslashes immediately preceding each reference within a string.
 #![warn(missing_docs)]
 
-use std::{io, path::Path};
+use std::{
+    collections::BTreeSet,
+    io::{self, Read},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
 
-pub use de::Deserializer;
-use serde::Deserialize;
+pub use de::{Deserializer, FileValueOptions};
+pub use error::Error;
+pub use expand::{Base64Alphabet, PathRedaction, SecretSource, SymlinkPolicy};
+pub use file_value::FileValue;
+pub use filesystem::Filesystem;
+pub use format::Format;
+pub use graph::DependencyGraph;
+pub use listener::{Access, Listener, Resolved};
+pub use resolver::Resolver;
+use serde::{
+    de::{DeserializeOwned, DeserializeSeed},
+    Deserialize,
+};
+pub use stats::Stats;
+#[cfg(feature = "test-util")]
+pub use test_util::MockFilesystem;
 
+mod content;
 mod de;
+mod error;
+mod expand;
+mod file_value;
+mod filesystem;
+mod format;
+mod graph;
+mod listener;
+mod prefetch;
+mod resolver;
+mod stats;
+#[cfg(feature = "test-util")]
+mod test_util;
 
 /// Entry point.
 ///
@@ -55,27 +230,364 @@ where
     T::deserialize(Deserializer::new(deserializer, &mut listener))
 }
 
+/// Like [`deserialize`], but returns an error with structured information about failed file reads.
+///
+/// See [`Deserializer::deserialize_with_error`] for details.
+pub fn deserialize_with_error<'de, D, F, T>(
+    deserializer: D,
+    mut listener: F,
+) -> Result<T, Error<D::Error>>
+where
+    D: serde::Deserializer<'de>,
+    F: FnMut(&Path, &io::Result<Vec<u8>>),
+    T: Deserialize<'de>,
+{
+    Deserializer::new(deserializer, &mut listener).deserialize_with_error()
+}
+
+/// Like [`deserialize`], but also returns statistics about the files read.
+///
+/// See [`Deserializer::deserialize_with_stats`] for details.
+pub fn deserialize_with_stats<'de, D, F, T>(
+    deserializer: D,
+    mut listener: F,
+) -> Result<(T, Stats), D::Error>
+where
+    D: serde::Deserializer<'de>,
+    F: FnMut(&Path, &io::Result<Vec<u8>>),
+    T: Deserialize<'de>,
+{
+    Deserializer::new(deserializer, &mut listener).deserialize_with_stats()
+}
+
+/// Like [`deserialize`], but also returns the graph of referenced files.
+///
+/// See [`Deserializer::deserialize_with_graph`] for details.
+pub fn deserialize_with_graph<'de, D, F, T>(
+    deserializer: D,
+    mut listener: F,
+) -> Result<(T, DependencyGraph), D::Error>
+where
+    D: serde::Deserializer<'de>,
+    F: FnMut(&Path, &io::Result<Vec<u8>>),
+    T: Deserialize<'de>,
+{
+    Deserializer::new(deserializer, &mut listener).deserialize_with_graph()
+}
+
+/// Like [`deserialize`], but returns the paths of the files read rather than notifying a listener.
+///
+/// The paths are returned in the order in which the files were read, which matches the order in
+/// which their references were deserialized. Every attempted read is included, including those of
+/// missing files replaced by a default value.
+pub fn deserialize_tracking<'de, D, T>(deserializer: D) -> Result<(T, Vec<PathBuf>), D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    let mut paths = vec![];
+    let value = deserialize(deserializer, |path: &Path, _: &io::Result<Vec<u8>>| {
+        paths.push(path.to_path_buf())
+    })?;
+    Ok((value, paths))
+}
+
+/// Reads the input from a reader and deserializes it in the specified format.
+///
+/// This is a convenience wrapper around [`deserialize`] for the common case of loading an entire
+/// configuration file. The listener will be called on every referenced file read along with the
+/// result of the read.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// use serde::Deserialize;
+/// # use serde::de::DeserializeSeed;
+/// # struct Json;
+/// # impl serde_file_value::Format for Json {
+/// #     type Error = serde_json::Error;
+/// #     fn deserialize<'de, S>(&self, input: &'de [u8], seed: S) -> Result<S::Value, Self::Error>
+/// #     where
+/// #         S: DeserializeSeed<'de>,
+/// #     {
+/// #         seed.deserialize(&mut serde_json::Deserializer::from_slice(input))
+/// #     }
+/// # }
+///
+/// #[derive(Deserialize)]
+/// struct Config {
+///     secret_value: String,
+/// }
+///
+/// let file = File::open("conf/config.json").unwrap();
+/// let config: Config = serde_file_value::deserialize_from_reader(file, Json, |_, _| ()).unwrap();
+/// ```
+pub fn deserialize_from_reader<R, F, L, T>(
+    mut reader: R,
+    format: F,
+    mut listener: L,
+) -> Result<T, F::Error>
+where
+    R: Read,
+    F: Format,
+    L: FnMut(&Path, &io::Result<Vec<u8>>),
+    T: DeserializeOwned,
+{
+    let mut input = vec![];
+    reader
+        .read_to_end(&mut input)
+        .map_err(|e| serde::de::Error::custom(format_args!("error reading input: {e}")))?;
+
+    let seed = Seed {
+        listener: &mut listener,
+        config_path: None,
+        _p: PhantomData,
+    };
+    format.deserialize(&input, seed)
+}
+
+struct Seed<'a, L, T> {
+    listener: &'a mut L,
+    config_path: Option<&'a Path>,
+    _p: PhantomData<T>,
+}
+
+impl<'de, L, T> DeserializeSeed<'de> for Seed<'_, L, T>
+where
+    L: FnMut(&Path, &io::Result<Vec<u8>>),
+    T: Deserialize<'de>,
+{
+    type Value = T;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<T, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let deserializer = Deserializer::new(deserializer, self.listener);
+        match self.config_path {
+            Some(config_path) => T::deserialize(deserializer.config_path(config_path)),
+            None => T::deserialize(deserializer),
+        }
+    }
+}
+
+/// Reads a configuration file and deserializes it in the format indicated by its extension.
+///
+/// This is a convenience wrapper around [`deserialize`] for the most common case of loading a
+/// configuration file from disk. The path is set as the [`Deserializer::config_path`], so relative
+/// file references are resolved against the directory containing the configuration file. The
+/// listener will be called on every referenced file read along with the result of the read.
+///
+/// The supported formats each require a Cargo feature, and this function is only available when at
+/// least one of them is enabled:
+///
+/// * `.json` - JSON, with the `json` feature.
+/// * `.yaml` and `.yml` - YAML, with the `yaml` feature.
+/// * `.toml` - TOML, with the `toml` feature.
+///
+/// Any other extension, or one whose feature is disabled, produces an error of kind
+/// [`io::ErrorKind::InvalidInput`]. A failure to deserialize the file, including one caused by a
+/// reference to another file, produces an error of kind [`io::ErrorKind::InvalidData`]. For more
+/// control over the deserializer, use [`Deserializer`] with the format's deserializer directly.
+///
+/// # Examples
+///
+/// ```no_run
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Config {
+///     secret_value: String,
+/// }
+///
+/// let config: Config = serde_file_value::deserialize_path("conf/config.json", |_, _| ()).unwrap();
+/// ```
+#[cfg(all(
+    feature = "fs",
+    any(feature = "json", feature = "toml", feature = "yaml")
+))]
+pub fn deserialize_path<P, L, T>(path: P, mut listener: L) -> io::Result<T>
+where
+    P: AsRef<Path>,
+    L: FnMut(&Path, &io::Result<Vec<u8>>),
+    T: DeserializeOwned,
+{
+    let path = path.as_ref();
+    let input = std::fs::read(path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("error reading file {}: {e}", path.display()),
+        )
+    })?;
+    let seed = Seed {
+        listener: &mut listener,
+        config_path: Some(path),
+        _p: PhantomData,
+    };
+
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let result = match extension {
+        #[cfg(feature = "json")]
+        "json" => format::Json
+            .deserialize(&input, seed)
+            .map_err(|e| e.to_string()),
+        #[cfg(feature = "yaml")]
+        "yaml" | "yml" => format::Yaml
+            .deserialize(&input, seed)
+            .map_err(|e| e.to_string()),
+        #[cfg(feature = "toml")]
+        "toml" => format::Toml
+            .deserialize(&input, seed)
+            .map_err(|e| e.to_string()),
+        _ => {
+            let feature = match extension {
+                "json" => "json",
+                "yaml" | "yml" => "yaml",
+                "toml" => "toml",
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("unsupported format of file {}", path.display()),
+                    ))
+                }
+            };
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "format of file {} requires the `{feature}` Cargo feature",
+                    path.display()
+                ),
+            ));
+        }
+    };
+    result.map_err(|e| invalid_data(path, e))
+}
+
+#[cfg(all(
+    feature = "fs",
+    any(feature = "json", feature = "toml", feature = "yaml")
+))]
+fn invalid_data(path: &Path, e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("error deserializing file {}: {e}", path.display()),
+    )
+}
+
+/// Returns the paths of all files referenced by the input without reading them.
+///
+/// See [`Deserializer::collect_references`] for details.
+pub fn collect_references<'de, D>(deserializer: D) -> Result<BTreeSet<PathBuf>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Deserializer::new(deserializer, &mut |_, _| ()).collect_references()
+}
+
+/// Returns the text of every reference in the input without resolving them.
+///
+/// See [`Deserializer::scan_references`] for details.
+pub fn scan_references<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Deserializer::new(deserializer, &mut |_, _| ()).scan_references()
+}
+
+/// Checks that all files referenced by the input exist and can be read, returning their paths.
+///
+/// See [`Deserializer::validate`] for details.
+pub fn validate<'de, D>(deserializer: D) -> Result<Vec<PathBuf>, Error<D::Error>>
+where
+    D: serde::Deserializer<'de>,
+{
+    Deserializer::new(deserializer, &mut |_, _| ()).validate()
+}
+
 #[cfg(test)]
 mod test {
-    use std::{fs, io, path::Path};
+    #[cfg(feature = "fs")]
+    use std::borrow::Cow;
+    use std::{cell::Cell, collections::HashMap, fs, io, path::Path, rc::Rc};
 
-    use serde::Deserialize;
+    use serde::{de, Deserialize};
     use tempfile::NamedTempFile;
 
     use super::*;
 
+    #[cfg(feature = "fs")]
+    #[derive(PartialEq, Debug)]
+    struct Bytes(Vec<u8>);
+
+    #[cfg(feature = "fs")]
+    impl<'de> Deserialize<'de> for Bytes {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: de::Deserializer<'de>,
+        {
+            struct Visitor;
+
+            impl<'de> de::Visitor<'de> for Visitor {
+                type Value = Bytes;
+
+                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    formatter.write_str("bytes")
+                }
+
+                fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+                where
+                    E: de::Error,
+                {
+                    Ok(Bytes(v.to_vec()))
+                }
+
+                fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+                where
+                    E: de::Error,
+                {
+                    Ok(Bytes(v))
+                }
+            }
+
+            deserializer.deserialize_byte_buf(Visitor)
+        }
+    }
+
+    #[cfg(feature = "fs")]
     #[derive(Deserialize, PartialEq, Debug)]
     struct Config {
         sub: Subconfig,
     }
 
+    #[cfg(feature = "fs")]
     #[derive(Deserialize, PartialEq, Debug)]
     struct Subconfig {
         file: Vec<String>,
         inline: String,
     }
 
+    #[cfg(feature = "fs")]
+    struct Json;
+
+    #[cfg(feature = "fs")]
+    impl Format for Json {
+        type Error = serde_json::Error;
+
+        fn deserialize<'de, S>(&self, input: &'de [u8], seed: S) -> Result<S::Value, Self::Error>
+        where
+            S: de::DeserializeSeed<'de>,
+        {
+            let mut deserializer = serde_json::Deserializer::from_slice(input);
+            let value = seed.deserialize(&mut deserializer)?;
+            deserializer.end()?;
+            Ok(value)
+        }
+    }
+
     #[test]
+    #[cfg(feature = "fs")]
     fn smoke() {
         let file = NamedTempFile::new().unwrap();
         fs::write(file.path(), "hunter2").unwrap();
@@ -135,4 +647,4316 @@ mod test {
         let expected = vec![(file.to_path_buf(), None)];
         assert_eq!(files, expected);
     }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn trim_trailing_newline() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2\r\n").unwrap();
+
+        let config = format!("\"${{file:{}}}\"", file.path().display());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut files = vec![];
+        let mut cb = |_: &Path, r: &io::Result<Vec<u8>>| files.push(r.as_ref().ok().cloned());
+        let deserializer =
+            Deserializer::new(&mut deserializer, &mut cb).trim_trailing_newline(true);
+
+        let value = String::deserialize(deserializer).unwrap();
+        assert_eq!(value, "hunter2");
+
+        assert_eq!(files, vec![Some(b"hunter2\r\n".to_vec())]);
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value: String = deserialize(&mut deserializer, |_, _| ()).unwrap();
+        assert_eq!(value, "hunter2\r\n");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn custom_delimiters() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let config = format!(
+            r#"["@@(file:{0})@@", "${{file:{0}}}"]"#,
+            file.path().display()
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| {};
+        let deserializer = Deserializer::with_delimiters(&mut deserializer, &mut cb, "@@(", ")@@");
+
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+        let expected = vec![
+            "hunter2".to_string(),
+            format!("${{file:{}}}", file.path().display()),
+        ];
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn escapes() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let config = format!(
+            r#"["\\${{file:{0}}}", "\\\\${{file:{0}}}", "\\\\\\${{file:{0}}}", "\\", "\\foo"]"#,
+            file.path().display()
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut files = vec![];
+        let value: Vec<String> = deserialize(&mut deserializer, |path, _| {
+            files.push(path.to_owned());
+        })
+        .unwrap();
+
+        let expected = vec![
+            format!("${{file:{}}}", file.path().display()),
+            "\\hunter2".to_string(),
+            format!("\\${{file:{}}}", file.path().display()),
+            "\\".to_string(),
+            "\\foo".to_string(),
+        ];
+        assert_eq!(value, expected);
+
+        assert_eq!(files, vec![file.path().to_owned()]);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn bytes() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Config {
+            key: Bytes,
+            name: String,
+        }
+
+        let key = NamedTempFile::new().unwrap();
+        fs::write(key.path(), [0xff, 0x00, 0x80]).unwrap();
+        let name = NamedTempFile::new().unwrap();
+        fs::write(name.path(), "hunter2").unwrap();
+
+        let config = format!(
+            r#"{{"key": "${{file:{}}}", "name": "${{file:{}}}"}}"#,
+            key.path().display(),
+            name.path().display(),
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let config: Config = deserialize(&mut deserializer, |_, _| ()).unwrap();
+
+        let expected = Config {
+            key: Bytes(vec![0xff, 0x00, 0x80]),
+            name: "hunter2".to_string(),
+        };
+        assert_eq!(config, expected);
+    }
+
+    #[test]
+    fn env() {
+        #[derive(Default)]
+        struct EnvListener {
+            vars: Vec<(String, Option<String>)>,
+        }
+
+        impl Listener for EnvListener {
+            fn file_read(&mut self, _: &Path, _: &io::Result<Vec<u8>>) {}
+
+            fn env_read(&mut self, name: &str, result: &Result<String, std::env::VarError>) {
+                self.vars
+                    .push((name.to_string(), result.as_ref().ok().cloned()));
+            }
+        }
+
+        std::env::set_var("SERDE_FILE_VALUE_TEST_ENV", "hunter2");
+
+        let config =
+            r#"["${env:SERDE_FILE_VALUE_TEST_ENV}", "${env:SERDE_FILE_VALUE_TEST_MISSING}"]"#;
+
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let mut listener = EnvListener::default();
+        let deserializer = Deserializer::with_listener(&mut deserializer, &mut listener);
+
+        let err = Vec::<String>::deserialize(deserializer).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("error reading environment variable SERDE_FILE_VALUE_TEST_MISSING: not set"));
+
+        let expected = vec![
+            (
+                "SERDE_FILE_VALUE_TEST_ENV".to_string(),
+                Some("hunter2".to_string()),
+            ),
+            ("SERDE_FILE_VALUE_TEST_MISSING".to_string(), None),
+        ];
+        assert_eq!(listener.vars, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn recursive() {
+        let dir = tempfile::tempdir().unwrap();
+        let secret = dir.path().join("secret");
+        fs::write(&secret, "hunter2").unwrap();
+        let manifest = dir.path().join("manifest");
+        fs::write(&manifest, format!("${{file:{}}}", secret.display())).unwrap();
+
+        let config = format!("\"${{file:{}}}\"", manifest.display());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut files = vec![];
+        let mut cb = |path: &Path, _: &io::Result<Vec<u8>>| files.push(path.to_owned());
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).recursive(true);
+
+        let value = String::deserialize(deserializer).unwrap();
+        assert_eq!(value, "hunter2");
+        assert_eq!(files, vec![manifest.clone(), secret]);
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value: String = deserialize(&mut deserializer, |_, _| ()).unwrap();
+        assert_eq!(
+            value,
+            format!("${{file:{}}}", dir.path().join("secret").display())
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn recursive_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        fs::write(&a, format!("${{file:{}}}", b.display())).unwrap();
+        fs::write(&b, format!("${{file:{}}}", a.display())).unwrap();
+
+        let config = format!("\"${{file:{}}}\"", a.display());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| {};
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).recursive(true);
+
+        let err = String::deserialize(deserializer).unwrap_err();
+        let expected = format!(
+            "reference cycle detected: ${{file:{0}}} -> ${{file:{1}}} -> ${{file:{0}}}",
+            a.display(),
+            b.display(),
+        );
+        assert!(err.to_string().contains(&expected), "{err}");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn inline() {
+        let a = NamedTempFile::new().unwrap();
+        fs::write(a.path(), "hunter2").unwrap();
+        let b = NamedTempFile::new().unwrap();
+        fs::write(b.path(), "swordfish").unwrap();
+
+        let config = format!(
+            r#"["Bearer ${{file:{0}}}", "${{file:{0}}}:${{file:{1}}} \\${{file:{1}}}", "${{foo}} ${{file:{0}"]"#,
+            a.path().display(),
+            b.path().display(),
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut files = vec![];
+        let mut cb = |path: &Path, _: &io::Result<Vec<u8>>| files.push(path.to_owned());
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).inline(true);
+
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+        let expected = vec![
+            "Bearer hunter2".to_string(),
+            format!("hunter2:swordfish ${{file:{}}}", b.path().display()),
+            format!("${{foo}} ${{file:{}", a.path().display()),
+        ];
+        assert_eq!(value, expected);
+
+        let expected = vec![
+            a.path().to_owned(),
+            a.path().to_owned(),
+            b.path().to_owned(),
+        ];
+        assert_eq!(files, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn default() {
+        let dir = tempfile::tempdir().unwrap();
+        let present = dir.path().join("present");
+        fs::write(&present, "hunter2").unwrap();
+        let missing = dir.path().join("missing");
+
+        let config = format!(
+            r#"["${{file:{}:-fallback}}", "${{file:{}:-fall:-back}}", "${{env:SERDE_FILE_VALUE_TEST_MISSING:-}}"]"#,
+            present.display(),
+            missing.display(),
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut files = vec![];
+        let value: Vec<String> = deserialize(&mut deserializer, |path, r| {
+            files.push((path.to_owned(), r.as_ref().map_err(|e| e.kind()).cloned()))
+        })
+        .unwrap();
+
+        let expected = vec![
+            "hunter2".to_string(),
+            "fall:-back".to_string(),
+            "".to_string(),
+        ];
+        assert_eq!(value, expected);
+
+        let expected = vec![
+            (present, Ok(b"hunter2".to_vec())),
+            (missing, Err(io::ErrorKind::NotFound)),
+        ];
+        assert_eq!(files, expected);
+    }
+
+    #[test]
+    fn collect_references() {
+        let config = r#"
+{
+    "sub": {
+        "file": [
+            "${file:/a}",
+            "${file:/b:-default}"
+        ],
+        "inline": "${file:/a}",
+        "other": [1, null, true, {"${file:/c}": "${foobar}"}]
+    }
+}
+        "#;
+
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let references = super::collect_references(&mut deserializer).unwrap();
+
+        let expected = ["/a", "/b"]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<BTreeSet<_>>();
+        assert_eq!(references, expected);
+
+        let files = [(PathBuf::from("/a"), b"hunter2".to_vec())]
+            .into_iter()
+            .collect();
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#"["${file:/a}"]"#);
+        let mut reads = vec![];
+        let mut cb = |path: &Path, _: &io::Result<Vec<u8>>| reads.push(path.to_owned());
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).files(files);
+
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+        assert_eq!(value, vec!["hunter2".to_string()]);
+        assert_eq!(reads, vec![PathBuf::from("/a")]);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn max_file_size() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let config = format!("\"${{file:{}}}\"", file.path().display());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| {};
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).max_file_size(7);
+        let value = String::deserialize(deserializer).unwrap();
+        assert_eq!(value, "hunter2");
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| {};
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).max_file_size(6);
+        let err = String::deserialize(deserializer).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("file exceeds size limit of 6 bytes"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "fs", unix))]
+    fn max_file_size_unknown_length() {
+        let config = "\"${file:/dev/zero}\"";
+
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| {};
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).max_file_size(1024);
+        let err = Bytes::deserialize(deserializer).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("file exceeds size limit of 1024 bytes"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn allowed_root() {
+        let root = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        let allowed = root.path().join("allowed");
+        fs::write(&allowed, "hunter2").unwrap();
+        let denied = other.path().join("denied");
+        fs::write(&denied, "swordfish").unwrap();
+
+        let escaped = root.path().join("..").join(other.path()).join("denied");
+        for (path, expected) in [
+            (&allowed, Ok("hunter2")),
+            (&denied, Err(())),
+            (&escaped, Err(())),
+        ] {
+            let config = format!("\"${{file:{}}}\"", path.display());
+
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let mut files = vec![];
+            let mut cb = |_: &Path, r: &io::Result<Vec<u8>>| {
+                files.push(r.as_ref().map_err(|e| e.kind()).cloned())
+            };
+            let deserializer =
+                Deserializer::new(&mut deserializer, &mut cb).allowed_root(root.path());
+
+            let value = String::deserialize(deserializer);
+            match expected {
+                Ok(expected) => {
+                    assert_eq!(value.unwrap(), expected);
+                    assert_eq!(files, vec![Ok(expected.as_bytes().to_vec())]);
+                }
+                Err(()) => {
+                    let err = value.unwrap_err();
+                    assert!(
+                        err.to_string().contains("is outside of the allowed roots"),
+                        "{err}"
+                    );
+                    assert_eq!(files, vec![Err(io::ErrorKind::PermissionDenied)]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn base_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("secrets")).unwrap();
+        let relative = dir.path().join("secrets").join("token");
+        fs::write(&relative, "hunter2").unwrap();
+        let absolute = NamedTempFile::new().unwrap();
+        fs::write(absolute.path(), "swordfish").unwrap();
+
+        let config = format!(
+            r#"["${{file:secrets/token}}", "${{file:{}}}"]"#,
+            absolute.path().display()
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut files = vec![];
+        let mut cb = |path: &Path, _: &io::Result<Vec<u8>>| files.push(path.to_owned());
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).base_dir(dir.path());
+
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+        assert_eq!(value, vec!["hunter2".to_string(), "swordfish".to_string()]);
+
+        assert_eq!(files, vec![relative, absolute.path().to_owned()]);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn cache() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let config = format!(
+            r#"["${{file:{0}}}", "${{file:{0}}}"]"#,
+            file.path().display()
+        );
+
+        for (cache, reads) in [(false, 2), (true, 1)] {
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let mut files = vec![];
+            let mut cb = |path: &Path, _: &io::Result<Vec<u8>>| files.push(path.to_owned());
+            let deserializer = Deserializer::new(&mut deserializer, &mut cb).cache(cache);
+
+            let value = Vec::<String>::deserialize(deserializer).unwrap();
+            assert_eq!(value, vec!["hunter2".to_string(), "hunter2".to_string()]);
+            assert_eq!(files.len(), reads);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn before_file_read() {
+        struct DenyListener {
+            denied: PathBuf,
+            files: Vec<PathBuf>,
+        }
+
+        impl Listener for DenyListener {
+            fn before_file_read(&mut self, path: &Path) -> Result<(), String> {
+                if path == self.denied {
+                    Err("access denied by policy".to_string())
+                } else {
+                    Ok(())
+                }
+            }
+
+            fn file_read(&mut self, path: &Path, _: &io::Result<Vec<u8>>) {
+                self.files.push(path.to_owned());
+            }
+        }
+
+        let allowed = NamedTempFile::new().unwrap();
+        fs::write(allowed.path(), "hunter2").unwrap();
+        let denied = NamedTempFile::new().unwrap();
+        fs::write(denied.path(), "swordfish").unwrap();
+
+        let config = format!(
+            r#"["${{file:{}}}", "${{file:{}}}"]"#,
+            allowed.path().display(),
+            denied.path().display(),
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut listener = DenyListener {
+            denied: denied.path().to_owned(),
+            files: vec![],
+        };
+        let deserializer = Deserializer::with_listener(&mut deserializer, &mut listener);
+
+        let err = Vec::<String>::deserialize(deserializer).unwrap_err();
+        let expected = format!(
+            "error reading file {}: access denied by policy",
+            denied.path().display()
+        );
+        assert!(err.to_string().contains(&expected), "{err}");
+
+        assert_eq!(listener.files, vec![allowed.path().to_owned()]);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn file_resolved() {
+        #[derive(Default)]
+        struct ValueListener {
+            events: Vec<(&'static str, Vec<u8>)>,
+        }
+
+        impl Listener for ValueListener {
+            fn file_read(&mut self, _: &Path, result: &io::Result<Vec<u8>>) {
+                self.events.push(("read", result.as_ref().unwrap().clone()));
+            }
+
+            fn file_resolved(&mut self, _: &Path, value: Resolved<'_>) {
+                match value {
+                    Resolved::String(value) => self.events.push(("string", value.into())),
+                    Resolved::Bytes(value) => self.events.push(("bytes", value.to_vec())),
+                }
+            }
+        }
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2\n").unwrap();
+
+        let config = format!(
+            r#"{{"key": "${{file:{0}}}", "name": "${{file:{0}}}"}}"#,
+            file.path().display()
+        );
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Config {
+            key: Bytes,
+            name: String,
+        }
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut listener = ValueListener::default();
+        let deserializer = Deserializer::with_listener(&mut deserializer, &mut listener)
+            .trim_trailing_newline(true);
+        let config = Config::deserialize(deserializer).unwrap();
+
+        let expected = Config {
+            key: Bytes(b"hunter2\n".to_vec()),
+            name: "hunter2".to_string(),
+        };
+        assert_eq!(config, expected);
+
+        let expected = vec![
+            ("read", b"hunter2\n".to_vec()),
+            ("bytes", b"hunter2\n".to_vec()),
+            ("read", b"hunter2\n".to_vec()),
+            ("string", b"hunter2".to_vec()),
+        ];
+        assert_eq!(listener.events, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn expand_keys() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let key = format!("${{file:{}}}", file.path().display());
+        let config = format!(r#"{{"{key}": "value"}}"#);
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut files = vec![];
+        let value: HashMap<String, String> = deserialize(&mut deserializer, |path, _| {
+            files.push(path.to_owned());
+        })
+        .unwrap();
+
+        let expected = [(key, "value".to_string())].into_iter().collect();
+        assert_eq!(value, expected);
+        assert_eq!(files, Vec::<PathBuf>::new());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| {};
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).expand_keys(true);
+        let value = HashMap::<String, String>::deserialize(deserializer).unwrap();
+
+        let expected = [("hunter2".to_string(), "value".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn struct_field_keys() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Config {
+            hunter2: Option<String>,
+        }
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let config = format!(r#"{{"${{file:{}}}": "value"}}"#, file.path().display());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value: Config = deserialize(&mut deserializer, |_, _| ()).unwrap();
+        assert_eq!(value, Config { hunter2: None });
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| {};
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).expand_keys(true);
+        let value = Config::deserialize(deserializer).unwrap();
+        assert_eq!(
+            value,
+            Config {
+                hunter2: Some("value".to_string())
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn deserialize_with_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("bogus");
+
+        let config = format!("\"${{file:{}}}\"", file.display());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let err = super::deserialize_with_error::<_, _, String>(&mut deserializer, |_, _| ())
+            .unwrap_err();
+
+        assert_eq!(err.path(), Some(&*file));
+        assert_eq!(err.io_error().unwrap().kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("error reading file"), "{err}");
+
+        let mut deserializer = serde_json::Deserializer::from_str("1");
+        let err = super::deserialize_with_error::<_, _, String>(&mut deserializer, |_, _| ())
+            .unwrap_err();
+
+        assert_eq!(err.path(), None);
+        assert!(err.io_error().is_none());
+
+        // a failed read which the target type recovers from isn't blamed for later errors
+        #[derive(Debug)]
+        struct Lenient;
+
+        impl<'de> Deserialize<'de> for Lenient {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                let _ = String::deserialize(deserializer);
+                Ok(Lenient)
+            }
+        }
+
+        let present = NamedTempFile::new().unwrap();
+        fs::write(present.path(), "hunter2").unwrap();
+        let config = format!(
+            r#"["${{file:{}}}", "${{file:{}}}", 1]"#,
+            file.display(),
+            present.path().display(),
+        );
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let err = super::deserialize_with_error::<_, _, (Lenient, String, String)>(
+            &mut deserializer,
+            |_, _| (),
+        )
+        .unwrap_err();
+
+        assert_eq!(err.path(), None);
+        assert!(err.io_error().is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn base64() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), [0xfb, 0xff, 0x00, b'\n']).unwrap();
+
+        let config = format!("\"${{file-base64:{}}}\"", file.path().display());
+
+        let mut reads = vec![];
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value: String = deserialize(&mut deserializer, |_, r| {
+            reads.push(r.as_ref().unwrap().clone())
+        })
+        .unwrap();
+        assert_eq!(value, "+/8ACg==");
+        assert_eq!(reads, [vec![0xfb, 0xff, 0x00, b'\n']]);
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+            .base64_alphabet(Base64Alphabet::UrlSafe)
+            .trim_trailing_newline(true);
+        let value = String::deserialize(deserializer).unwrap();
+        assert_eq!(value, "-_8ACg==");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn hex() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), [0x01, 0xab, 0xff, 0x00]).unwrap();
+
+        let config = format!("\"${{file-hex:{}}}\"", file.path().display());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value: String = deserialize(&mut deserializer, |_, _| ()).unwrap();
+        assert_eq!(value, "01abff00");
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).hex_uppercase(true);
+        let value = String::deserialize(deserializer).unwrap();
+        assert_eq!(value, "01ABFF00");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn utf8_lossy() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), b"hunter\xff2").unwrap();
+
+        let config = format!("\"${{file:{}}}\"", file.path().display());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let err = deserialize::<_, _, String>(&mut deserializer, |_, _| ()).unwrap_err();
+        assert!(err.to_string().contains("error parsing file"), "{err}");
+
+        let mut reads = vec![];
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, r: &io::Result<Vec<u8>>| reads.push(r.as_ref().unwrap().clone());
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).utf8_lossy(true);
+        let value = String::deserialize(deserializer).unwrap();
+        assert_eq!(value, "hunter\u{fffd}2");
+        assert_eq!(reads, [b"hunter\xff2".to_vec()]);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn trim() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Config {
+            key: Bytes,
+            name: String,
+        }
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), " \n hunter2 \n\n").unwrap();
+
+        let config = format!(
+            r#"{{"key": "${{file:{0}}}", "name": "${{file:{0}}}"}}"#,
+            file.path().display(),
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+            .trim(true)
+            .trim_trailing_newline(true);
+        let config = Config::deserialize(deserializer).unwrap();
+
+        let expected = Config {
+            key: Bytes(b" \n hunter2 \n\n".to_vec()),
+            name: "hunter2".to_string(),
+        };
+        assert_eq!(config, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn missing_as_none() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Config {
+            present: Option<String>,
+            missing: Option<String>,
+            defaulted: Option<String>,
+            literal: Option<Vec<u32>>,
+            null: Option<String>,
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let present = dir.path().join("present");
+        fs::write(&present, "hunter2").unwrap();
+        let missing = dir.path().join("missing");
+
+        let config = format!(
+            r#"{{
+                "present": "${{file:{0}}}",
+                "missing": "${{file:{1}}}",
+                "defaulted": "${{file:{1}:-fallback}}",
+                "literal": [1, 2],
+                "null": null
+            }}"#,
+            present.display(),
+            missing.display(),
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut reads = vec![];
+        let mut cb = |path: &Path, _: &io::Result<Vec<u8>>| reads.push(path.to_path_buf());
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).missing_as_none(true);
+        let config = Config::deserialize(deserializer).unwrap();
+
+        let expected = Config {
+            present: Some("hunter2".to_string()),
+            missing: None,
+            defaulted: Some("fallback".to_string()),
+            literal: Some(vec![1, 2]),
+            null: None,
+        };
+        assert_eq!(config, expected);
+        assert_eq!(reads, [present, missing.clone()]);
+
+        let config = format!("\"${{file:{}}}\"", missing.display());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).missing_as_none(true);
+        String::deserialize(deserializer).unwrap_err();
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn structured_files() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Config {
+            inner: Inner,
+            list: Vec<u32>,
+            name: String,
+        }
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Inner {
+            value: u32,
+            mode: Mode,
+            port: Option<u16>,
+        }
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        #[serde(rename_all = "lowercase")]
+        enum Mode {
+            Fast,
+            Slow { factor: u32 },
+        }
+
+        let inner = NamedTempFile::new().unwrap();
+        fs::write(
+            inner.path(),
+            r#"{"value": 1, "mode": {"slow": {"factor": 2}}, "port": null}"#,
+        )
+        .unwrap();
+        let list = NamedTempFile::new().unwrap();
+        fs::write(list.path(), "[1, 2, 3]").unwrap();
+        let name = NamedTempFile::new().unwrap();
+        fs::write(name.path(), r#""hunter2""#).unwrap();
+
+        let config = format!(
+            r#"{{"inner": "${{file-json:{}}}", "list": "${{file-json:{}}}", "name": "${{file-json:{}}}"}}"#,
+            inner.path().display(),
+            list.path().display(),
+            name.path().display(),
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).format("json", Json);
+        let config = Config::deserialize(deserializer).unwrap();
+
+        let expected = Config {
+            inner: Inner {
+                value: 1,
+                mode: Mode::Slow { factor: 2 },
+                port: None,
+            },
+            list: vec![1, 2, 3],
+            name: "hunter2".to_string(),
+        };
+        assert_eq!(config, expected);
+
+        let config = format!(r#""${{file-json:{}}}""#, list.path().display());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).format("json", Json);
+        let err = Inner::deserialize(deserializer).unwrap_err();
+        assert!(
+            err.to_string().contains("error deserializing file"),
+            "{err}"
+        );
+
+        // only registered formats are structured
+        let config = format!(r#""${{file-yaml:{}}}""#, list.path().display());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).format("json", Json);
+        let value = String::deserialize(deserializer).unwrap();
+        assert_eq!(value, config.trim_matches('"'));
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn deserialize_from_reader() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let config = format!(r#"{{"value": "${{file:{}}}"}}"#, file.path().display());
+
+        let mut reads = vec![];
+        let value: HashMap<String, String> =
+            super::deserialize_from_reader(config.as_bytes(), Json, |path: &Path, _: &_| {
+                reads.push(path.to_path_buf())
+            })
+            .unwrap();
+        assert_eq!(value["value"], "hunter2");
+        assert_eq!(reads, [file.path()]);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn deserialize_with_stats() {
+        let a = NamedTempFile::new().unwrap();
+        fs::write(a.path(), "hunter2").unwrap();
+        let b = NamedTempFile::new().unwrap();
+        fs::write(b.path(), "foo").unwrap();
+
+        let config = format!(
+            r#"["${{file:{0}}}", "${{file:{1}}}", "${{file:{0}}}", "${{file:/bogus:-bar}}"]"#,
+            a.path().display(),
+            b.path().display(),
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let (value, stats) =
+            super::deserialize_with_stats::<_, _, Vec<String>>(&mut deserializer, |_, _| ())
+                .unwrap();
+        assert_eq!(value, ["hunter2", "foo", "hunter2", "bar"]);
+        assert_eq!(stats.files_read(), 3);
+        assert_eq!(stats.total_bytes(), 17);
+        assert_eq!(stats.cache_hits(), 0);
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let (_, stats) = Deserializer::new(&mut deserializer, &mut cb)
+            .cache(true)
+            .deserialize_with_stats::<Vec<String>>()
+            .unwrap();
+        assert_eq!(stats.files_read(), 2);
+        assert_eq!(stats.total_bytes(), 10);
+        assert_eq!(stats.cache_hits(), 1);
+    }
+
+    #[test]
+    fn resolver() {
+        struct MapResolver(HashMap<&'static str, &'static str>);
+
+        impl Resolver for MapResolver {
+            fn supports_scheme(&self, scheme: &str) -> bool {
+                scheme == "map"
+            }
+
+            fn resolve(&mut self, scheme: &str, arg: &str) -> io::Result<Vec<u8>> {
+                assert_eq!(scheme, "map");
+                match self.0.get(arg) {
+                    Some(value) => Ok(value.as_bytes().to_vec()),
+                    None => Err(io::Error::new(io::ErrorKind::NotFound, "not found")),
+                }
+            }
+        }
+
+        let resolver = MapResolver(HashMap::from([("a", "hunter2")]));
+
+        let config = r#"["${map:a}", "${map:b:-foo}", "${other:a}"]"#;
+
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).resolver(resolver);
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+        assert_eq!(value, ["hunter2", "foo", "${other:a}"]);
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#""${map:b}""#);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer =
+            Deserializer::new(&mut deserializer, &mut cb).resolver(MapResolver(HashMap::new()));
+        let err = String::deserialize(deserializer).unwrap_err();
+        assert!(
+            err.to_string().contains("error resolving map reference b"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn encoding() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), b"caf\xe9").unwrap();
+
+        let config = format!(
+            r#"["${{file:{0};encoding=latin1}}", "${{file:{0};encoding=ISO-8859-1:-foo}}"]"#,
+            file.path().display()
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value: Vec<String> = deserialize(&mut deserializer, |_, _| ()).unwrap();
+        assert_eq!(value, ["caf\u{e9}", "caf\u{e9}"]);
+
+        let config = format!(r#""${{file:{};encoding=utf-8}}""#, file.path().display());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let err = deserialize::<_, _, String>(&mut deserializer, |_, _| ()).unwrap_err();
+        assert!(err.to_string().contains("error parsing file"), "{err}");
+
+        let config = format!(r#""${{file:{};encoding=ebcdic}}""#, file.path().display());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let err = deserialize::<_, _, String>(&mut deserializer, |_, _| ()).unwrap_err();
+        assert!(
+            err.to_string().contains("unknown encoding `ebcdic`"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn semicolon_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a;b");
+        fs::write(&file, "hunter2").unwrap();
+
+        let config = format!(r#""${{file:{}}}""#, file.display());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value: String = deserialize(&mut deserializer, |_, _| ()).unwrap();
+        assert_eq!(value, "hunter2");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn file_metadata() {
+        #[derive(Default)]
+        struct MetadataListener {
+            reads: Vec<u64>,
+            metadata: Vec<(PathBuf, u64)>,
+        }
+
+        impl Listener for MetadataListener {
+            fn file_read(&mut self, _: &Path, result: &io::Result<Vec<u8>>) {
+                self.reads.push(result.as_ref().unwrap().len() as u64);
+            }
+
+            fn file_metadata(&mut self, path: &Path, metadata: &fs::Metadata) {
+                assert!(metadata.modified().is_ok());
+                self.metadata.push((path.to_path_buf(), metadata.len()));
+            }
+        }
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let config = format!(
+            r#"["${{file:{}}}", "${{file:/preloaded}}"]"#,
+            file.path().display()
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut listener = MetadataListener::default();
+        let deserializer = Deserializer::with_listener(&mut deserializer, &mut listener).files(
+            HashMap::from([(PathBuf::from("/preloaded"), b"foo".to_vec())]),
+        );
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+        assert_eq!(value, ["hunter2", "foo"]);
+
+        assert_eq!(listener.reads, [7, 3]);
+        assert_eq!(listener.metadata, [(file.path().to_path_buf(), 7)]);
+    }
+
+    #[test]
+    fn dry_run() {
+        #[derive(Default)]
+        struct DryRunListener {
+            referenced: Vec<PathBuf>,
+        }
+
+        impl Listener for DryRunListener {
+            fn file_read(&mut self, path: &Path, _: &io::Result<Vec<u8>>) {
+                panic!("unexpected read of {}", path.display());
+            }
+
+            fn file_referenced(&mut self, path: &Path) {
+                self.referenced.push(path.to_path_buf());
+            }
+        }
+
+        let config = r#"{"a": "${file:/a}", "b": "foo ${file:/b} bar", "c": "baz"}"#;
+
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let mut listener = DryRunListener::default();
+        let deserializer = Deserializer::with_listener(&mut deserializer, &mut listener)
+            .inline(true)
+            .recursive(true)
+            .dry_run(true);
+        let value = HashMap::<String, String>::deserialize(deserializer).unwrap();
+
+        assert_eq!(value["a"], "${file:/a}");
+        assert_eq!(value["b"], "foo ${file:/b} bar");
+        assert_eq!(value["c"], "baz");
+        let mut referenced = listener.referenced;
+        referenced.sort();
+        assert_eq!(referenced, [Path::new("/a"), Path::new("/b")]);
+
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let mut listener = DryRunListener::default();
+        let deserializer = Deserializer::with_listener(&mut deserializer, &mut listener)
+            .dry_run(true)
+            .dry_run_placeholder("<redacted>");
+        let value = HashMap::<String, String>::deserialize(deserializer).unwrap();
+
+        assert_eq!(value["a"], "<redacted>");
+        assert_eq!(value["b"], "foo ${file:/b} bar");
+    }
+
+    #[test]
+    fn windows_path_parsing() {
+        let paths = [
+            r"C:\secrets\token",
+            "C:/secrets/token",
+            r"\\server\share\token",
+            r"C:\secrets\token ",
+        ];
+        let config = paths
+            .iter()
+            .map(|path| format!("${{file:{path}}}"))
+            .collect::<Vec<_>>();
+        let config = serde_json::to_string(&config).unwrap();
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let references = super::collect_references(&mut deserializer).unwrap();
+
+        let expected = paths.iter().map(PathBuf::from).collect::<BTreeSet<_>>();
+        assert_eq!(references, expected);
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn windows_paths() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let backslash = file.path().display().to_string();
+        assert!(backslash.contains('\\'), "{backslash}");
+        let forward_slash = backslash.replace('\\', "/");
+        let (drive, rest) = backslash.split_once(':').unwrap();
+        let unc = format!(r"\\localhost\{drive}${rest}");
+        let verbatim = format!(r"\\?\{backslash}");
+
+        for path in [&backslash, &forward_slash, &unc, &verbatim] {
+            let config = serde_json::to_string(&format!("${{file:{path}}}")).unwrap();
+
+            let mut reads = vec![];
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let value = deserialize::<_, _, String>(&mut deserializer, |path, _| {
+                reads.push(path.to_path_buf())
+            });
+            if path == &unc && value.is_err() {
+                // administrative shares may be disabled
+                continue;
+            }
+
+            assert_eq!(value.unwrap(), "hunter2", "{path}");
+            assert_eq!(reads, [PathBuf::from(path)]);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn max_total_bytes() {
+        let a = NamedTempFile::new().unwrap();
+        fs::write(a.path(), "hunter2").unwrap();
+        let b = NamedTempFile::new().unwrap();
+        fs::write(b.path(), "foo").unwrap();
+
+        let config = format!(
+            r#"["${{file:{0}}}", "${{file:{1}}}", "${{file:{0}}}"]"#,
+            a.path().display(),
+            b.path().display(),
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+            .max_total_bytes(10)
+            .cache(true);
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+        assert_eq!(value, ["hunter2", "foo", "hunter2"]);
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).max_total_bytes(16);
+        let err = Vec::<String>::deserialize(deserializer).unwrap_err();
+        assert!(
+            err.to_string().contains("exceeds limit of 16 bytes"),
+            "{err}"
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+            .max_total_bytes(9)
+            .max_file_size(100);
+        let err = Vec::<String>::deserialize(deserializer).unwrap_err();
+        assert!(
+            err.to_string().contains("exceeds limit of 9 bytes"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn prefetch() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = (0..10)
+            .map(|i| {
+                let path = dir.path().join(i.to_string());
+                fs::write(&path, i.to_string()).unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+
+        let config = paths
+            .iter()
+            .chain([&dir.path().join("missing"), &paths[0]])
+            .map(|path| format!("${{file:{}:-missing}}", path.display()))
+            .collect::<Vec<_>>();
+        let config = serde_json::to_string(&config).unwrap();
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let references = super::collect_references(&mut deserializer).unwrap();
+        assert_eq!(references.len(), 11);
+
+        // once the first file has been referenced, all files are deleted, so later references can
+        // only succeed if they were prefetched
+        let mut reads = 0;
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| {
+            if reads == 0 {
+                for path in &paths {
+                    fs::remove_file(path).unwrap();
+                }
+            }
+            reads += 1;
+        };
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+            .prefetch(references)
+            .prefetch_threads(4);
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+
+        let mut expected = (0..10).map(|i| i.to_string()).collect::<Vec<_>>();
+        expected.extend(["missing".to_string(), "missing".to_string()]);
+        assert_eq!(value, expected);
+        assert_eq!(reads, 12);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn not_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("bogus");
+
+        let config = format!(r#""${{file:{}}}""#, file.display());
+
+        let mut kinds = vec![];
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let err = super::deserialize_with_error::<_, _, String>(&mut deserializer, |_, r| {
+            kinds.push(r.as_ref().unwrap_err().kind())
+        })
+        .unwrap_err();
+        assert_eq!(kinds, [io::ErrorKind::NotFound]);
+        assert_eq!(err.io_error().unwrap().kind(), io::ErrorKind::NotFound);
+
+        let config = format!(r#""${{file:{}:-}}""#, file.display());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value: String = deserialize(&mut deserializer, |_, _| ()).unwrap();
+        assert_eq!(value, "");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn other_io_error_kind() {
+        let root = tempfile::tempdir().unwrap();
+        let file = NamedTempFile::new().unwrap();
+
+        let config = format!(r#""${{file:{}:-default}}""#, file.path().display());
+
+        let mut kinds = vec![];
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, r: &io::Result<Vec<u8>>| kinds.push(r.as_ref().unwrap_err().kind());
+        let err = Deserializer::new(&mut deserializer, &mut cb)
+            .allowed_root(root.path())
+            .deserialize_with_error::<String>()
+            .unwrap_err();
+        assert_eq!(kinds, [io::ErrorKind::PermissionDenied]);
+        assert_eq!(err.path(), Some(file.path()));
+        assert_eq!(
+            err.io_error().unwrap().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+    }
+
+    #[test]
+    fn filesystem() {
+        let filesystem = HashMap::from([
+            (PathBuf::from("/secrets/a"), b"hunter2".to_vec()),
+            (PathBuf::from("/other/b"), b"foo".to_vec()),
+        ]);
+
+        let config = r#"["${file:/secrets/a}", "${file:/secrets/missing:-bar}"]"#;
+
+        let mut reads = vec![];
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let mut cb = |path: &Path, _: &io::Result<Vec<u8>>| reads.push(path.to_path_buf());
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+            .filesystem(filesystem.clone())
+            .allowed_root("/secrets");
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+        assert_eq!(value, ["hunter2", "bar"]);
+        assert_eq!(reads, ["/secrets/a", "/secrets/missing"].map(PathBuf::from));
+
+        for path in ["/other/b", "/secrets/../other/b"] {
+            let config = format!(r#""${{file:{path}}}""#);
+
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+            let err = Deserializer::new(&mut deserializer, &mut cb)
+                .filesystem(filesystem.clone())
+                .allowed_root("/secrets")
+                .deserialize_with_error::<String>()
+                .unwrap_err();
+            assert_eq!(
+                err.io_error().unwrap().kind(),
+                io::ErrorKind::PermissionDenied
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "fs"))]
+    fn fs_disabled() {
+        let config = r#"["${file:/a}", "${file:/b}"]"#;
+
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let err = Deserializer::new(&mut deserializer, &mut cb)
+            .files(HashMap::from([(PathBuf::from("/a"), b"hunter2".to_vec())]))
+            .deserialize_with_error::<Vec<String>>()
+            .unwrap_err();
+        assert_eq!(err.path(), Some(Path::new("/b")));
+        assert_eq!(err.io_error().unwrap().kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn schemes() {
+        let config = r#"["${upper:abc}", "${env:x}", "${upper:b:-foo}", "${other:a}", "${a b:c}"]"#;
+
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+            .scheme("upper", |arg| match arg {
+                "b" => Err(io::Error::new(io::ErrorKind::NotFound, "not found")),
+                _ => Ok(arg.to_uppercase().into_bytes()),
+            })
+            .scheme("env", |arg| Ok(format!("env {arg}").into_bytes()));
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+        assert_eq!(value, ["ABC", "env x", "foo", "${other:a}", "${a b:c}"]);
+
+        let mut deserializer =
+            serde_json::Deserializer::from_str(r#"["${a b:c}", "\\${other:a}"]"#);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).deny_unknown_schemes(true);
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+        assert_eq!(value, ["${a b:c}", "\\${other:a}"]);
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#""${other:a}""#);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).deny_unknown_schemes(true);
+        let err = String::deserialize(deserializer).unwrap_err();
+        assert!(
+            err.to_string().contains("unknown reference scheme `other`"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn read_timeout() {
+        struct SlowFilesystem;
+
+        impl Filesystem for SlowFilesystem {
+            fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+                if path == Path::new("/slow") {
+                    std::thread::sleep(std::time::Duration::from_secs(5));
+                }
+                Ok(b"hunter2".to_vec())
+            }
+        }
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#""${file:/fast}""#);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let value = Deserializer::new(&mut deserializer, &mut cb)
+            .filesystem(SlowFilesystem)
+            .read_timeout(std::time::Duration::from_secs(5))
+            .deserialize_with_error::<String>()
+            .unwrap();
+        assert_eq!(value, "hunter2");
+
+        let mut kinds = vec![];
+        let mut deserializer = serde_json::Deserializer::from_str(r#""${file:/slow}""#);
+        let mut cb =
+            |_: &Path, r: &io::Result<Vec<u8>>| kinds.push(r.as_ref().map_err(|e| e.kind()).err());
+        let err = Deserializer::new(&mut deserializer, &mut cb)
+            .filesystem(SlowFilesystem)
+            .read_timeout(std::time::Duration::from_millis(10))
+            .deserialize_with_error::<String>()
+            .unwrap_err();
+        assert_eq!(err.io_error().unwrap().kind(), io::ErrorKind::TimedOut);
+        assert_eq!(kinds, [Some(io::ErrorKind::TimedOut)]);
+    }
+
+    #[test]
+    fn error_location() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Config {
+            sub: Sub,
+        }
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Sub {
+            name: String,
+            file: Vec<String>,
+        }
+
+        let mut deserializer =
+            serde_json::Deserializer::from_str(r#"{"sub": {"name": "a", "file": []}}"#);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let value = Config::deserialize(Deserializer::new(&mut deserializer, &mut cb)).unwrap();
+        assert_eq!(
+            value,
+            Config {
+                sub: Sub {
+                    name: "a".to_string(),
+                    file: vec![],
+                },
+            }
+        );
+
+        let config = r#"{"sub": {"name": "a", "file": ["b", "${file:/does/not/exist}"]}}"#;
+
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let err = Config::deserialize(Deserializer::new(&mut deserializer, &mut cb)).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("sub.file[1]: error reading file /does/not/exist"),
+            "{err}"
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#""${file:/does/not/exist}""#);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let err = String::deserialize(Deserializer::new(&mut deserializer, &mut cb)).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("error reading file /does/not/exist"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn borrowed_str() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Config<'a> {
+            #[serde(borrow)]
+            a: Cow<'a, str>,
+            #[serde(borrow)]
+            b: Cow<'a, str>,
+        }
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let config = format!(
+            r#"{{"a": "foo", "b": "${{file:{}}}"}}"#,
+            file.path().display()
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let value = Config::deserialize(Deserializer::new(&mut deserializer, &mut cb)).unwrap();
+        assert_eq!(
+            value,
+            Config {
+                a: Cow::Borrowed("foo"),
+                b: Cow::Owned("hunter2".to_string()),
+            }
+        );
+        assert!(matches!(value.a, Cow::Borrowed(_)));
+        assert!(matches!(value.b, Cow::Owned(_)));
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#""foo""#);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let value = <&str>::deserialize(Deserializer::new(&mut deserializer, &mut cb)).unwrap();
+        assert_eq!(value, "foo");
+
+        let config = format!(r#""${{file:{}}}""#, file.path().display());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let err = <&str>::deserialize(Deserializer::new(&mut deserializer, &mut cb)).unwrap_err();
+        assert!(err.to_string().contains("invalid type"), "{err}");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn deserialize_tracking() {
+        let a = NamedTempFile::new().unwrap();
+        fs::write(a.path(), "foo").unwrap();
+        let b = NamedTempFile::new().unwrap();
+        fs::write(b.path(), "bar").unwrap();
+
+        let config = format!(
+            r#"["${{file:{}}}", "${{file:{}}}", "${{file:/does/not/exist:-baz}}"]"#,
+            b.path().display(),
+            a.path().display(),
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let (value, paths) =
+            super::deserialize_tracking::<_, Vec<String>>(&mut deserializer).unwrap();
+        assert_eq!(value, ["bar", "foo", "baz"]);
+        assert_eq!(paths, [b.path(), a.path(), Path::new("/does/not/exist")]);
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#""${file:/does/not/exist}""#);
+        super::deserialize_tracking::<_, String>(&mut deserializer).unwrap_err();
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn selections() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "foo\r\nbar\nbaz\n").unwrap();
+        let path = file.path().display();
+
+        let config = format!(
+            r#"["${{file:{path}#L1}}", "${{file:{path}#L3}}", "${{file:{path}#5-8}}", "${{file:{path}#0-0}}"]"#
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let value =
+            Vec::<String>::deserialize(Deserializer::new(&mut deserializer, &mut cb)).unwrap();
+        assert_eq!(value, ["foo", "baz", "bar", ""]);
+
+        for (selection, message) in [
+            ("L4", "line 4 is out of range"),
+            ("L0", "invalid line 0"),
+            ("5-14", "byte range 5-14 is out of range"),
+            ("8-5", "invalid byte range 8-5"),
+        ] {
+            let config = format!(r#""${{file:{path}#{selection}}}""#);
+
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+            let err =
+                String::deserialize(Deserializer::new(&mut deserializer, &mut cb)).unwrap_err();
+            assert!(err.to_string().contains(message), "{err}");
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a#b");
+        fs::write(&file, "hunter2").unwrap();
+        let config = format!(r#""${{file:{}}}""#, file.display());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let value = String::deserialize(Deserializer::new(&mut deserializer, &mut cb)).unwrap();
+        assert_eq!(value, "hunter2");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn trim_byte_reads() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Config {
+            string: String,
+            bytes: Bytes,
+            hex: String,
+        }
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), " hunter2\n").unwrap();
+
+        let config = format!(
+            r#"{{"string": "${{file:{0}}}", "bytes": "${{file:{0}}}", "hex": "${{file-hex:{0}}}"}}"#,
+            file.path().display()
+        );
+
+        for trim in [false, true] {
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+            let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+                .trim_trailing_newline(true)
+                .trim(trim);
+            let value = Config::deserialize(deserializer).unwrap();
+            assert_eq!(
+                value,
+                Config {
+                    string: if trim { "hunter2" } else { " hunter2" }.to_string(),
+                    bytes: Bytes(b" hunter2\n".to_vec()),
+                    hex: "2068756e746572320a".to_string(),
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_reference() {
+        #[derive(Default)]
+        struct MalformedListener {
+            malformed: Vec<(String, String)>,
+        }
+
+        impl Listener for MalformedListener {
+            fn file_read(&mut self, path: &Path, _: &io::Result<Vec<u8>>) {
+                panic!("unexpected read of {}", path.display());
+            }
+
+            fn malformed_reference(&mut self, value: &str, reason: &str) {
+                self.malformed.push((value.to_string(), reason.to_string()));
+            }
+        }
+
+        let config = r#"["${file/a}", "${file:/a", "${env}", "${filename}", "${environment}", "\\${file/a}", "${other}", "foo"]"#;
+
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let mut listener = MalformedListener::default();
+        let deserializer = Deserializer::with_listener(&mut deserializer, &mut listener);
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+        assert_eq!(
+            value,
+            [
+                "${file/a}",
+                "${file:/a",
+                "${env}",
+                "${filename}",
+                "${environment}",
+                "\\${file/a}",
+                "${other}",
+                "foo"
+            ]
+        );
+        assert_eq!(
+            listener.malformed,
+            [
+                ("${file/a}", "missing `:` after the `file` scheme"),
+                ("${file:/a", "missing closing `}`"),
+                ("${env}", "missing `:` after the `env` scheme"),
+            ]
+            .map(|(value, reason)| (value.to_string(), reason.to_string()))
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#""a ${file:/a} b ${file:/b""#);
+        let mut listener = MalformedListener::default();
+        let deserializer = Deserializer::with_listener(&mut deserializer, &mut listener)
+            .inline(true)
+            .dry_run(true);
+        String::deserialize(deserializer).unwrap();
+        assert_eq!(
+            listener.malformed,
+            [("${file:/b".to_string(), "missing closing `}`".to_string())]
+        );
+    }
+
+    #[test]
+    fn strict() {
+        for value in ["${file:/x", "${file/x}"] {
+            let config = format!(r#""{value}""#);
+
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+            let deserializer = Deserializer::new(&mut deserializer, &mut cb);
+            assert_eq!(String::deserialize(deserializer).unwrap(), value);
+
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+            let deserializer = Deserializer::new(&mut deserializer, &mut cb).strict(true);
+            let err = String::deserialize(deserializer).unwrap_err();
+            assert!(
+                err.to_string()
+                    .starts_with(&format!("malformed reference {value}: ")),
+                "{err}"
+            );
+        }
+
+        let config = r#"["${other}", "\\${file/x}", "${environment}", "${filename}"]"#;
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).strict(true);
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+        assert_eq!(
+            value,
+            ["${other}", "\\${file/x}", "${environment}", "${filename}"]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn error_source() {
+        let mut deserializer = serde_json::Deserializer::from_str(r#""${file:/does/not/exist}""#);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let err = Deserializer::new(&mut deserializer, &mut cb)
+            .deserialize_with_error::<String>()
+            .unwrap_err();
+        let source = std::error::Error::source(&err)
+            .unwrap()
+            .downcast_ref::<io::Error>()
+            .unwrap();
+        assert_eq!(source.kind(), io::ErrorKind::NotFound);
+
+        let mut deserializer = serde_json::Deserializer::from_str("1");
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let err = Deserializer::new(&mut deserializer, &mut cb)
+            .deserialize_with_error::<String>()
+            .unwrap_err();
+        assert!(std::error::Error::source(&err).is_none());
+    }
+
+    #[test]
+    fn ignored_values() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Config {
+            a: String,
+            #[serde(skip)]
+            b: String,
+        }
+
+        let input = [
+            ("a", "foo"),
+            ("b", "${file:/must/not/read}"),
+            ("c", "${file:/must/not/read}"),
+        ];
+
+        let deserializer =
+            de::value::MapDeserializer::<_, de::value::Error>::new(input.into_iter());
+        let mut cb =
+            |path: &Path, _: &io::Result<Vec<u8>>| panic!("unexpected read of {}", path.display());
+        let value = Config::deserialize(Deserializer::new(deserializer, &mut cb)).unwrap();
+        assert_eq!(
+            value,
+            Config {
+                a: "foo".to_string(),
+                b: String::new(),
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn byte_sequences() {
+        let a = NamedTempFile::new().unwrap();
+        fs::write(a.path(), [0x30, 0x82, 0xff, b'\n']).unwrap();
+        let b = NamedTempFile::new().unwrap();
+        fs::write(b.path(), [0x30, 0x00]).unwrap();
+
+        let config = format!(
+            r#"["${{file:{}}}", "${{file:{}}}", [1, 2]]"#,
+            a.path().display(),
+            b.path().display(),
+        );
+
+        let mut reads = vec![];
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |path: &Path, _: &io::Result<Vec<u8>>| reads.push(path.to_path_buf());
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+            .byte_sequences(true)
+            .trim_trailing_newline(true);
+        let value = Vec::<Vec<u8>>::deserialize(deserializer).unwrap();
+        assert_eq!(
+            value,
+            [vec![0x30, 0x82, 0xff, b'\n'], vec![0x30, 0x00], vec![1, 2]]
+        );
+        assert_eq!(reads, [a.path(), b.path()]);
+
+        let config = format!(r#"["${{file:{}}}", [1, 2]]"#, b.path().display());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).byte_sequences(true);
+        let value = Vec::<[u8; 2]>::deserialize(deserializer).unwrap();
+        assert_eq!(value, [[0x30, 0x00], [1, 2]]);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn validate() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        let config = format!(
+            r#"{{"a": "${{file:{}}}", "b": "${{file:/in/memory}}", "c": "foo"}}"#,
+            file.path().display()
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb =
+            |path: &Path, _: &io::Result<Vec<u8>>| panic!("unexpected read of {}", path.display());
+        let paths = Deserializer::new(&mut deserializer, &mut cb)
+            .files(HashMap::from([(PathBuf::from("/in/memory"), vec![])]))
+            .validate()
+            .unwrap();
+        let mut expected = vec![file.path().to_path_buf(), PathBuf::from("/in/memory")];
+        expected.sort();
+        assert_eq!(paths, expected);
+
+        for (path, kind) in [
+            (Path::new("/does/not/exist"), io::ErrorKind::NotFound),
+            (dir.path(), io::ErrorKind::Other),
+        ] {
+            let config = format!(r#"["${{file:{}:-default}}"]"#, path.display());
+
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let err = super::validate(&mut deserializer).unwrap_err();
+            assert_eq!(err.path(), Some(path));
+            assert_eq!(err.io_error().unwrap().kind(), kind);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn error_on_empty() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), " \n").unwrap();
+
+        let config = format!(r#""${{file:{}}}""#, file.path().display());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).error_on_empty(true);
+        let value = String::deserialize(deserializer).unwrap();
+        assert_eq!(value, " \n");
+
+        let mut reads = vec![];
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, r: &io::Result<Vec<u8>>| reads.push(r.as_ref().unwrap().clone());
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+            .error_on_empty(true)
+            .trim(true);
+        let err = String::deserialize(deserializer).unwrap_err();
+        assert!(err.to_string().contains("is empty"), "{err}");
+        assert_eq!(reads, [b" \n".to_vec()]);
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#""${file:/does/not/exist:-}""#);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).error_on_empty(true);
+        let value = String::deserialize(deserializer).unwrap();
+        assert_eq!(value, "");
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn gzip() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(b"hunter2\n").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), &compressed).unwrap();
+
+        let config = format!(r#""${{file-gz:{}}}""#, file.path().display());
+
+        let mut reads = vec![];
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, r: &io::Result<Vec<u8>>| reads.push(r.as_ref().unwrap().clone());
+        let deserializer =
+            Deserializer::new(&mut deserializer, &mut cb).trim_trailing_newline(true);
+        let value = String::deserialize(deserializer).unwrap();
+        assert_eq!(value, "hunter2");
+        assert_eq!(reads, [compressed]);
+
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&[b'a'; 1000]).unwrap();
+        fs::write(file.path(), encoder.finish().unwrap()).unwrap();
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).max_file_size(100);
+        let err = String::deserialize(deserializer).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("file exceeds size limit of 100 bytes"),
+            "{err}"
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).max_total_bytes(500);
+        let err = String::deserialize(deserializer).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("total size of referenced files exceeds limit of 500 bytes"),
+            "{err}"
+        );
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+        let config = format!(r#""${{file-gz:{}}}""#, file.path().display());
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let err = String::deserialize(Deserializer::new(&mut deserializer, &mut cb)).unwrap_err();
+        assert!(
+            err.to_string().contains("error decompressing file"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn transform() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "-----BEGIN-----\nhunter2\n-----END-----\n").unwrap();
+
+        let config = format!(
+            r#"["${{file:{0}}}", "${{file:/does/not/exist:-default}}"]"#,
+            file.path().display()
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+            .trim(true)
+            .transform(|_, value| {
+                value
+                    .strip_prefix("-----BEGIN-----\n")
+                    .and_then(|v| v.strip_suffix("\n-----END-----"))
+                    .map(|v| v.to_string())
+                    .ok_or_else(|| "missing PEM markers".to_string())
+            });
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+        assert_eq!(value, ["hunter2", "default"]);
+
+        let config = format!(r#""${{file:{}}}""#, file.path().display());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+            .transform(|_, _| Err("decryption failed".to_string()));
+        let err = String::deserialize(deserializer).unwrap_err();
+        assert!(
+            err.to_string().contains(&format!(
+                "error transforming file {}: decryption failed",
+                file.path().display()
+            )),
+            "{err}"
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "fs", unix))]
+    fn symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+
+        fs::write(root.path().join("secret"), "inside").unwrap();
+        fs::write(outside.path().join("secret"), "outside").unwrap();
+        symlink(root.path().join("secret"), root.path().join("inner")).unwrap();
+        symlink(root.path().join("inner"), root.path().join("chain")).unwrap();
+        symlink(outside.path().join("secret"), root.path().join("outer")).unwrap();
+
+        let read = |name: &str, policy: SymlinkPolicy, allowed_root: bool| {
+            let config = format!(r#""${{file:{}}}""#, root.path().join(name).display());
+
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+            let mut deserializer = Deserializer::new(&mut deserializer, &mut cb).symlinks(policy);
+            if allowed_root {
+                deserializer = deserializer.allowed_root(root.path());
+            }
+            deserializer
+                .deserialize_with_error::<String>()
+                .map_err(|e| e.io_error().unwrap().kind())
+        };
+
+        for name in ["secret", "inner", "chain"] {
+            assert_eq!(read(name, SymlinkPolicy::Follow, false).unwrap(), "inside");
+        }
+        assert_eq!(
+            read("outer", SymlinkPolicy::Follow, false).unwrap(),
+            "outside"
+        );
+
+        assert_eq!(
+            read("secret", SymlinkPolicy::Deny, false).unwrap(),
+            "inside"
+        );
+        for name in ["inner", "chain", "outer"] {
+            assert_eq!(
+                read(name, SymlinkPolicy::Deny, false),
+                Err(io::ErrorKind::PermissionDenied)
+            );
+        }
+
+        for name in ["inner", "chain"] {
+            assert_eq!(
+                read(name, SymlinkPolicy::WithinAllowedRoots, true).unwrap(),
+                "inside"
+            );
+            assert_eq!(
+                read(name, SymlinkPolicy::WithinAllowedRoots, false),
+                Err(io::ErrorKind::PermissionDenied)
+            );
+        }
+        assert_eq!(
+            read("outer", SymlinkPolicy::WithinAllowedRoots, true),
+            Err(io::ErrorKind::PermissionDenied)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn config_path() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("secrets")).unwrap();
+        fs::write(dir.path().join("secrets/a"), "foo").unwrap();
+        fs::write(dir.path().join("b"), "bar").unwrap();
+        let config_path = dir.path().join("config.json");
+
+        let config = format!(
+            r#"["${{file:secrets/a}}", "${{file:{}}}"]"#,
+            dir.path().join("b").display()
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).config_path(&config_path);
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+        assert_eq!(value, ["foo", "bar"]);
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#""${file:a}""#);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+            .config_path(&config_path)
+            .base_dir("secrets");
+        let value = String::deserialize(deserializer).unwrap();
+        assert_eq!(value, "foo");
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#""${file:b}""#);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+            .config_path("/does/not/exist/config.json")
+            .base_dir(dir.path());
+        let value = String::deserialize(deserializer).unwrap();
+        assert_eq!(value, "bar");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn allowed_extensions() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.pem", "b.key", "c.conf", "d"] {
+            fs::write(dir.path().join(name), name).unwrap();
+        }
+
+        let read = |name: &str, allowed_extensions: &[&str]| {
+            let config = format!(r#""${{file:{}}}""#, dir.path().join(name).display());
+
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+            Deserializer::new(&mut deserializer, &mut cb)
+                .allowed_extensions(allowed_extensions)
+                .deserialize_with_error::<String>()
+                .map_err(|e| e.io_error().unwrap().kind())
+        };
+
+        assert_eq!(read("a.pem", &["pem", ".key"]).unwrap(), "a.pem");
+        assert_eq!(read("b.key", &["pem", ".key"]).unwrap(), "b.key");
+        assert_eq!(
+            read("c.conf", &["pem", ".key"]),
+            Err(io::ErrorKind::PermissionDenied)
+        );
+        assert_eq!(
+            read("d", &["pem", ".key"]),
+            Err(io::ErrorKind::PermissionDenied)
+        );
+        assert_eq!(read("d", &["pem", ""]).unwrap(), "d");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn file_accessed() {
+        #[derive(Default)]
+        struct AccessListener {
+            reads: usize,
+            accesses: Vec<(Access, Vec<u8>)>,
+        }
+
+        impl Listener for AccessListener {
+            fn file_read(&mut self, _: &Path, _: &io::Result<Vec<u8>>) {
+                self.reads += 1;
+            }
+
+            fn file_accessed(&mut self, _: &Path, access: Access, result: &io::Result<Vec<u8>>) {
+                self.accesses
+                    .push((access, result.as_ref().unwrap().clone()));
+            }
+        }
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let config = format!(
+            r#"["${{file:{0}}}", "${{file:{0}}}"]"#,
+            file.path().display()
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut listener = AccessListener::default();
+        let deserializer =
+            Deserializer::with_listener(&mut deserializer, &mut listener).cache(true);
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+        assert_eq!(value, ["hunter2", "hunter2"]);
+        assert_eq!(listener.reads, 1);
+        assert_eq!(
+            listener.accesses,
+            [
+                (Access::Read, b"hunter2".to_vec()),
+                (Access::CacheHit, b"hunter2".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn enum_payloads() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        enum Credential {
+            Token(String),
+            Basic(String, String),
+            Key { path: String, passphrase: String },
+        }
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let config = format!(
+            r#"[
+                {{"Token": "${{file:{0}}}"}},
+                {{"Basic": ["user", "${{file:{0}}}"]}},
+                {{"Key": {{"path": "/key", "passphrase": "${{file:{0}}}"}}}}
+            ]"#,
+            file.path().display()
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let value =
+            Vec::<Credential>::deserialize(Deserializer::new(&mut deserializer, &mut cb)).unwrap();
+        assert_eq!(
+            value,
+            [
+                Credential::Token("hunter2".to_string()),
+                Credential::Basic("user".to_string(), "hunter2".to_string()),
+                Credential::Key {
+                    path: "/key".to_string(),
+                    passphrase: "hunter2".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn file_lines() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Config {
+            allowlist: Vec<String>,
+            empty: Vec<String>,
+            fallback: Vec<String>,
+        }
+
+        let allowlist = NamedTempFile::new().unwrap();
+        fs::write(allowlist.path(), "10.0.0.1\r\n\n10.0.0.2\n").unwrap();
+        let empty = NamedTempFile::new().unwrap();
+
+        let config = format!(
+            r#"{{"allowlist": "${{file-lines:{}}}", "empty": "${{file-lines:{}}}", "fallback": "${{file-lines:/nonexistent:-127.0.0.1}}"}}"#,
+            allowlist.path().display(),
+            empty.path().display(),
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let value =
+            Config::deserialize(Deserializer::new(&mut deserializer, &mut cb).file_lines(true))
+                .unwrap();
+        assert_eq!(
+            value,
+            Config {
+                allowlist: vec![
+                    "10.0.0.1".to_string(),
+                    String::new(),
+                    "10.0.0.2".to_string()
+                ],
+                empty: vec![],
+                fallback: vec!["127.0.0.1".to_string()],
+            }
+        );
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Pair(String, String);
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Credentials {
+            tuple: (String, String),
+            array: [String; 2],
+            pair: Pair,
+        }
+
+        let credentials = NamedTempFile::new().unwrap();
+        fs::write(credentials.path(), "admin\nhunter2\n").unwrap();
+        let config = format!(
+            r#"{{"tuple": "${{file-lines:{0}}}", "array": "${{file-lines:{0}}}", "pair": "${{file-lines:{0}}}"}}"#,
+            credentials.path().display(),
+        );
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value = Credentials::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb).file_lines(true),
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            Credentials {
+                tuple: ("admin".to_string(), "hunter2".to_string()),
+                array: ["admin".to_string(), "hunter2".to_string()],
+                pair: Pair("admin".to_string(), "hunter2".to_string()),
+            }
+        );
+
+        let config = format!(r#""hosts: ${{file-lines:{}}}""#, allowlist.path().display());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let err = String::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb)
+                .file_lines(true)
+                .inline(true),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("must make up the entire value"));
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn deserialize_with_graph() {
+        let b = NamedTempFile::new().unwrap();
+        fs::write(b.path(), "hunter2").unwrap();
+        let a = NamedTempFile::new().unwrap();
+        fs::write(a.path(), format!("${{file:{}}}", b.path().display())).unwrap();
+        let c = NamedTempFile::new().unwrap();
+        fs::write(c.path(), "foo").unwrap();
+
+        let config = format!(
+            r#"["${{file:{}}}", "${{file:{}}}"]"#,
+            a.path().display(),
+            c.path().display(),
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let (value, graph) = Deserializer::new(&mut deserializer, &mut cb)
+            .recursive(true)
+            .deserialize_with_graph::<Vec<String>>()
+            .unwrap();
+        assert_eq!(value, ["hunter2", "foo"]);
+
+        let mut roots = vec![a.path(), c.path()];
+        roots.sort();
+        assert_eq!(graph.roots().collect::<Vec<_>>(), roots);
+        assert_eq!(graph.dependencies(a.path()).collect::<Vec<_>>(), [b.path()]);
+        assert_eq!(graph.dependencies(b.path()).count(), 0);
+        assert_eq!(graph.files().count(), 3);
+
+        let serialized = serde_json::to_value(&graph).unwrap();
+        assert_eq!(
+            serialized,
+            serde_json::json!({
+                "roots": roots,
+                "edges": {a.path().to_str().unwrap(): [b.path()]},
+            })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn secret_sources() {
+        std::env::set_var("SERDE_FILE_VALUE_TEST_SECRET_A", "from-env");
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("SERDE_FILE_VALUE_TEST_SECRET_A"),
+            "from-file-a",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("SERDE_FILE_VALUE_TEST_SECRET_B"),
+            "from-file-b",
+        )
+        .unwrap();
+
+        let config = r#"[
+            "${secret:SERDE_FILE_VALUE_TEST_SECRET_A}",
+            "${secret:SERDE_FILE_VALUE_TEST_SECRET_B}",
+            "${secret:SERDE_FILE_VALUE_TEST_SECRET_C:-fallback}"
+        ]"#;
+
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let mut reads = vec![];
+        let mut cb = |path: &Path, result: &io::Result<Vec<u8>>| {
+            reads.push((path.to_path_buf(), result.is_ok()))
+        };
+        let value = Vec::<String>::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb)
+                .secret_source(SecretSource::Env)
+                .secret_source(SecretSource::Dir(dir.path().to_path_buf())),
+        )
+        .unwrap();
+        assert_eq!(value, ["from-env", "from-file-b", "fallback"]);
+        assert_eq!(
+            reads,
+            [
+                (dir.path().join("SERDE_FILE_VALUE_TEST_SECRET_B"), true),
+                (dir.path().join("SERDE_FILE_VALUE_TEST_SECRET_C"), false),
+            ]
+        );
+
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        for config in [
+            r#""${secret:SERDE_FILE_VALUE_TEST_SECRET_C}""#,
+            r#""${secret:../SERDE_FILE_VALUE_TEST_SECRET_B}""#,
+        ] {
+            let mut deserializer = serde_json::Deserializer::from_str(config);
+            String::deserialize(
+                Deserializer::new(&mut deserializer, &mut cb)
+                    .secret_source(SecretSource::Dir(dir.path().to_path_buf())),
+            )
+            .unwrap_err();
+        }
+
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let value =
+            Vec::<String>::deserialize(Deserializer::new(&mut deserializer, &mut cb)).unwrap();
+        assert_eq!(value[0], "${secret:SERDE_FILE_VALUE_TEST_SECRET_A}");
+    }
+
+    #[test]
+    #[cfg(all(feature = "fs", unix))]
+    fn warn_insecure_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        struct PermissionsListener(Vec<(PathBuf, u32)>);
+
+        impl Listener for PermissionsListener {
+            fn file_read(&mut self, _: &Path, _: &io::Result<Vec<u8>>) {}
+
+            fn insecure_permissions(&mut self, path: &Path, mode: u32) {
+                self.0.push((path.to_path_buf(), mode & 0o777));
+            }
+        }
+
+        let private = NamedTempFile::new().unwrap();
+        fs::write(private.path(), "hunter2").unwrap();
+        fs::set_permissions(private.path(), fs::Permissions::from_mode(0o600)).unwrap();
+        let public = NamedTempFile::new().unwrap();
+        fs::write(public.path(), "hunter3").unwrap();
+        fs::set_permissions(public.path(), fs::Permissions::from_mode(0o644)).unwrap();
+
+        let config = format!(
+            r#"["${{file:{}}}", "${{file:{}}}"]"#,
+            private.path().display(),
+            public.path().display(),
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut listener = PermissionsListener(vec![]);
+        let value = Vec::<String>::deserialize(
+            Deserializer::with_listener(&mut deserializer, &mut listener)
+                .warn_insecure_permissions(true),
+        )
+        .unwrap();
+        assert_eq!(value, ["hunter2", "hunter3"]);
+        assert_eq!(listener.0, [(public.path().to_path_buf(), 0o644)]);
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut listener = PermissionsListener(vec![]);
+        Vec::<String>::deserialize(Deserializer::with_listener(
+            &mut deserializer,
+            &mut listener,
+        ))
+        .unwrap();
+        assert_eq!(listener.0, []);
+    }
+
+    #[test]
+    fn serde_path_to_error() {
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Config {
+            database: Database,
+        }
+
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Database {
+            users: Vec<User>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct User {
+            password: String,
+        }
+
+        let config =
+            r#"{"database": {"users": [{"password": "hunter2"}, {"password": "${file:/bogus}"}]}}"#;
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let err = serde_path_to_error::deserialize::<_, Config>(
+            Deserializer::new(&mut deserializer, &mut cb).error_locations(false),
+        )
+        .unwrap_err();
+        assert_eq!(err.path().to_string(), "database.users[1].password");
+        assert!(err
+            .to_string()
+            .starts_with("database.users[1].password: error reading file /bogus: "));
+
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let mut track = serde_path_to_error::Track::new();
+        let err = Config::deserialize(
+            Deserializer::new(
+                serde_path_to_error::Deserializer::new(&mut deserializer, &mut track),
+                &mut cb,
+            )
+            .error_locations(false),
+        )
+        .unwrap_err();
+        assert_eq!(track.path().to_string(), "database.users[1].password");
+        assert!(err.to_string().starts_with("error reading file /bogus: "));
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn base64_decode() {
+        use base64::Engine;
+
+        let data = (0..100).collect::<Vec<u8>>();
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&data);
+        let wrapped = encoded
+            .as_bytes()
+            .chunks(64)
+            .map(|line| format!("{}\r\n", std::str::from_utf8(line).unwrap()))
+            .collect::<String>();
+        assert!(wrapped.lines().count() > 1);
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), format!("  {wrapped}\n")).unwrap();
+
+        let config = format!("\"${{file-base64-decode:{}}}\"", file.path().display());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value: Bytes = deserialize(&mut deserializer, |_, _| ()).unwrap();
+        assert_eq!(value.0, data);
+
+        let text = NamedTempFile::new().unwrap();
+        fs::write(text.path(), "aHVudGVy\nMg==\n").unwrap();
+        let invalid = NamedTempFile::new().unwrap();
+        fs::write(invalid.path(), "not base64!").unwrap();
+
+        let config = format!("\"${{file-base64-decode:{}}}\"", text.path().display());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value: String = deserialize(&mut deserializer, |_, _| ()).unwrap();
+        assert_eq!(value, "hunter2");
+
+        let config = format!("\"${{file-base64-decode:{}}}\"", invalid.path().display());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let err = deserialize::<_, _, String>(&mut deserializer, |_, _| ()).unwrap_err();
+        assert!(err.to_string().contains("error decoding base64 file"));
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn double_braces() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("secret"), "hunter2").unwrap();
+        fs::write(dir.path().join("a}b"), "hunter3").unwrap();
+
+        let read = |value: &str, inline: bool| {
+            let config = serde_json::to_string(value).unwrap();
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+            String::deserialize(
+                Deserializer::new(&mut deserializer, &mut cb)
+                    .double_braces()
+                    .inline(inline)
+                    .strict(true),
+            )
+        };
+
+        let secret = dir.path().join("secret");
+        let stray = dir.path().join("a}b");
+        assert_eq!(
+            read(&format!("{{{{file:{}}}}}", secret.display()), false).unwrap(),
+            "hunter2"
+        );
+        assert_eq!(
+            read(&format!("{{{{file:{}}}}}", stray.display()), false).unwrap(),
+            "hunter3"
+        );
+        assert_eq!(
+            read(&format!("{{{{file:{}}}}}", stray.display()), true).unwrap(),
+            "hunter3"
+        );
+        assert_eq!(
+            read(&format!("a}} {{{{file:{}}}}} }}b", secret.display()), true).unwrap(),
+            "a} hunter2 }b"
+        );
+        assert_eq!(
+            read(&format!("${{file:{}}}", secret.display()), true).unwrap(),
+            format!("${{file:{}}}", secret.display())
+        );
+        assert_eq!(read("{{file:/bogus:-a}b}}", false).unwrap(), "a}b");
+
+        let err = read(&format!("{{{{file:{}}}", secret.display()), false).unwrap_err();
+        assert!(err.to_string().contains("missing closing `}}`"), "{err}");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn dynamic_value() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let config = format!(
+            r#"{{"password": "${{file:{0}}}", "list": [1, "${{file:{0}}}"], "other": "plain"}}"#,
+            file.path().display()
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value: serde_json::Value = deserialize(&mut deserializer, |_, _| ()).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "password": "hunter2",
+                "list": [1, "hunter2"],
+                "other": "plain",
+            })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn path_env_vars() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("api_token"), "hunter2").unwrap();
+        fs::write(dir.path().join("$literal"), "hunter3").unwrap();
+        std::env::set_var("SERDE_FILE_VALUE_TEST_DIR", dir.path());
+        std::env::set_var("SERDE_FILE_VALUE_TEST_SERVICE", "api");
+
+        let read = |value: &str, path_env_vars: bool| {
+            let config = serde_json::to_string(value).unwrap();
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+            String::deserialize(
+                Deserializer::new(&mut deserializer, &mut cb).path_env_vars(path_env_vars),
+            )
+        };
+
+        assert_eq!(
+            read(
+                "${file:$SERDE_FILE_VALUE_TEST_DIR/${SERDE_FILE_VALUE_TEST_SERVICE}_token}",
+                true
+            )
+            .unwrap(),
+            "hunter2"
+        );
+        assert_eq!(
+            read("${file:$SERDE_FILE_VALUE_TEST_DIR/$$literal}", true).unwrap(),
+            "hunter3"
+        );
+        assert!(read("${file:$SERDE_FILE_VALUE_TEST_DIR/api_token}", false).is_err());
+
+        let err = read("${file:$SERDE_FILE_VALUE_TEST_UNSET/token:-default}", true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "error expanding variables in path $SERDE_FILE_VALUE_TEST_UNSET/token: environment \
+             variable SERDE_FILE_VALUE_TEST_UNSET is not set at line 1 column 53",
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "sha256")]
+    fn sha256() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let digest = "f52fbd32b2b3b86ff88ef6c490628285f482af15ddcb29541f94bcf526a3f6c7";
+        let read = |digest: &str| {
+            let config = format!(r#""${{file:{};sha256={digest}}}""#, file.path().display());
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            deserialize::<_, _, String>(&mut deserializer, |_, _| ())
+        };
+
+        assert_eq!(read(digest).unwrap(), "hunter2");
+        assert_eq!(read(&digest.to_uppercase()).unwrap(), "hunter2");
+
+        let err = read(&digest.replace('f', "0")).unwrap_err();
+        assert!(err.to_string().contains("sha256 digest mismatch"), "{err}");
+
+        let err = read("abcd").unwrap_err();
+        assert!(
+            err.to_string().contains("invalid sha256 digest `abcd`"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn directory() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let config = format!(r#""${{file:{}}}""#, dir.path().display());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut results = vec![];
+        let err = deserialize::<_, _, String>(&mut deserializer, |_, r| {
+            results.push(r.as_ref().map_err(|e| e.to_string()).cloned())
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "error reading file {}: expected a file but found a directory at line 1 column {}",
+                dir.path().display(),
+                config.len(),
+            )
+        );
+        assert_eq!(
+            results,
+            [Err("expected a file but found a directory".to_string())]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn case_insensitive_schemes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("Secret"), "hunter2").unwrap();
+        std::env::set_var("SERDE_FILE_VALUE_TEST_CASE", "hunter3");
+
+        let path = dir.path().join("Secret");
+        let config = format!(
+            r#"["${{FILE:{0}}}", "${{File:{0}}}", "${{ENV:SERDE_FILE_VALUE_TEST_CASE}}", "${{VAULT:db}}"]"#,
+            path.display()
+        );
+
+        let read = |config: &str, case_insensitive_schemes: bool| {
+            let mut deserializer = serde_json::Deserializer::from_str(config);
+            let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+            Vec::<String>::deserialize(
+                Deserializer::new(&mut deserializer, &mut cb)
+                    .scheme("vault", |arg| Ok(format!("vault-{arg}").into_bytes()))
+                    .case_insensitive_schemes(case_insensitive_schemes),
+            )
+        };
+
+        assert_eq!(
+            read(&config, true).unwrap(),
+            ["hunter2", "hunter2", "hunter3", "vault-db"]
+        );
+        assert_eq!(
+            read(&config, false).unwrap(),
+            [
+                format!("${{FILE:{}}}", path.display()),
+                format!("${{File:{}}}", path.display()),
+                "${ENV:SERDE_FILE_VALUE_TEST_CASE}".to_string(),
+                "${VAULT:db}".to_string(),
+            ]
+        );
+
+        let config = format!(r#"["${{FILE:{}}}"]"#, dir.path().join("SECRET").display());
+        read(&config, true).unwrap_err();
+
+        // names differing only in case replace each other
+        let read = |config: &str, case_insensitive_schemes: bool| {
+            let mut deserializer = serde_json::Deserializer::from_str(config);
+            let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+            Vec::<String>::deserialize(
+                Deserializer::new(&mut deserializer, &mut cb)
+                    .scheme("vault", |_| Ok(b"lower".to_vec()))
+                    .scheme("Vault", |_| Ok(b"mixed".to_vec()))
+                    .case_insensitive_schemes(case_insensitive_schemes),
+            )
+            .unwrap()
+        };
+        let config = r#"["${vault:db}", "${Vault:db}", "${VAULT:db}"]"#;
+        assert_eq!(read(config, true), ["mixed", "mixed", "mixed"]);
+        assert_eq!(read(config, false), ["${vault:db}", "mixed", "${VAULT:db}"]);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn path_resolved() {
+        struct AuditListener(Vec<(String, PathBuf)>);
+
+        impl Listener for AuditListener {
+            fn file_read(&mut self, _: &Path, _: &io::Result<Vec<u8>>) {}
+
+            fn path_resolved(&mut self, reference: &str, path: &Path) {
+                self.0.push((reference.to_string(), path.to_path_buf()));
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("token"), "hunter2").unwrap();
+
+        let config = r#"["${file:token;encoding=latin1}", "a ${file:missing:-b} c"]"#;
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let mut listener = AuditListener(vec![]);
+        let value = Vec::<String>::deserialize(
+            Deserializer::with_listener(&mut deserializer, &mut listener)
+                .base_dir(dir.path())
+                .inline(true),
+        )
+        .unwrap();
+        assert_eq!(value, ["hunter2", "a b c"]);
+        assert_eq!(
+            listener.0,
+            [
+                (
+                    "${file:token;encoding=latin1}".to_string(),
+                    dir.path().join("token")
+                ),
+                ("${file:missing:-b}".to_string(), dir.path().join("missing")),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "fs", unix))]
+    fn fd_references() {
+        use std::io::{Read, Seek};
+        use std::os::unix::io::AsRawFd;
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+        let mut handle = fs::File::open(file.path()).unwrap();
+        let fd = handle.as_raw_fd();
+
+        let config = format!(r#""${{fd:{fd}}}""#);
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut paths = vec![];
+        let mut cb = |path: &Path, _: &io::Result<Vec<u8>>| paths.push(path.to_path_buf());
+        let value =
+            String::deserialize(Deserializer::new(&mut deserializer, &mut cb).fd_references(true))
+                .unwrap();
+        assert_eq!(value, "hunter2");
+        assert_eq!(paths, [PathBuf::from(format!("/dev/fd/{fd}"))]);
+
+        // the descriptor remains open
+        handle.rewind().unwrap();
+        let mut contents = String::new();
+        handle.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hunter2");
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let value = String::deserialize(Deserializer::new(&mut deserializer, &mut cb)).unwrap();
+        assert_eq!(value, config.trim_matches('"'));
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#""${fd:stdin}""#);
+        let err =
+            String::deserialize(Deserializer::new(&mut deserializer, &mut cb).fd_references(true))
+                .unwrap_err();
+        assert!(err.to_string().contains("invalid file descriptor"), "{err}");
+
+        // a descriptor which isn't open is never used
+        let mut deserializer = serde_json::Deserializer::from_str(r#""${fd:99999}""#);
+        let err =
+            String::deserialize(Deserializer::new(&mut deserializer, &mut cb).fd_references(true))
+                .unwrap_err();
+        assert!(err.to_string().contains("/dev/fd/99999"), "{err}");
+
+        // the limits of other reads apply
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let err = String::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb)
+                .fd_references(true)
+                .max_total_bytes(3),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("total size"), "{err}");
+    }
+
+    #[test]
+    #[cfg(all(feature = "fs", target_os = "linux"))]
+    fn fd_references_access_checks() {
+        use std::os::unix::io::AsRawFd;
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+        let handle = fs::File::open(file.path()).unwrap();
+        let fd = handle.as_raw_fd();
+        let config = format!(r#""${{fd:{fd}}}""#);
+
+        // a descriptor's path is a symbolic link without an extension, but is still subject to the
+        // allowed roots
+        let read = |deserializer: &mut serde_json::Deserializer<_>, allowed_root: &Path| {
+            let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+            String::deserialize(
+                Deserializer::new(deserializer, &mut cb)
+                    .fd_references(true)
+                    .symlinks(SymlinkPolicy::Deny)
+                    .allowed_extensions(&["pem"])
+                    .allowed_root(allowed_root),
+            )
+        };
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value = read(&mut deserializer, file.path().parent().unwrap()).unwrap();
+        assert_eq!(value, "hunter2");
+
+        let root = tempfile::tempdir().unwrap();
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let err = read(&mut deserializer, root.path()).unwrap_err();
+        assert!(
+            err.to_string().contains("is outside of the allowed roots"),
+            "{err}"
+        );
+
+        // the exemption only applies when descriptor references are enabled
+        let config = format!(r#""${{file:/dev/fd/{fd}}}""#);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let err = String::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb).symlinks(SymlinkPolicy::Deny),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("is a symbolic link"), "{err}");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn line_limit() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "one\ntwo\nthree\nfour\n").unwrap();
+
+        let read = |params: &str| {
+            let config = format!(r#""${{file:{}{params}}}""#, file.path().display());
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            deserialize::<_, _, String>(&mut deserializer, |_, _| ())
+        };
+
+        assert_eq!(read(";lines=2").unwrap(), "one\ntwo\n");
+        assert_eq!(read(";lines=10").unwrap(), "one\ntwo\nthree\nfour\n");
+        assert_eq!(read(";lines=0").unwrap(), "");
+        assert_eq!(read("#L2;lines=2").unwrap(), "two");
+        assert_eq!(read("#4-7;lines=2").unwrap(), "two");
+        read("#L3;lines=2").unwrap_err();
+        read(";lines=x").unwrap_err();
+
+        let config = format!(r#""${{file:{};lines=1}}""#, file.path().display());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let value = String::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb)
+                .max_file_size(5)
+                .trim_trailing_newline(true),
+        )
+        .unwrap();
+        assert_eq!(value, "one");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn deserialize_in_place() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let config = format!(r#"["${{file:{}}}", "plain"]"#, file.path().display());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let mut value = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        Vec::<String>::deserialize_in_place(
+            Deserializer::new(&mut deserializer, &mut cb),
+            &mut value,
+        )
+        .unwrap();
+        assert_eq!(value, ["hunter2", "plain"]);
+
+        let config = format!(r#""${{file:{}}}""#, file.path().display());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut value = "previous".to_string();
+        String::deserialize_in_place(Deserializer::new(&mut deserializer, &mut cb), &mut value)
+            .unwrap();
+        assert_eq!(value, "hunter2");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn candidate_separator() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        let present = dir.path().join("present");
+        fs::write(&present, "hunter2").unwrap();
+
+        let read = |arg: String| {
+            let config = format!(r#""${{file:{arg}}}""#);
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let mut paths = vec![];
+            let mut cb = |path: &Path, _: &io::Result<Vec<u8>>| paths.push(path.to_path_buf());
+            let value = String::deserialize(
+                Deserializer::new(&mut deserializer, &mut cb).candidate_separator("|"),
+            );
+            value.map(|value| (value, paths))
+        };
+
+        let (value, paths) = read(format!("{}|{}", missing.display(), present.display())).unwrap();
+        assert_eq!(value, "hunter2");
+        assert_eq!(paths, [missing.clone(), present.clone()]);
+
+        let (value, paths) = read(format!("{}|{}", present.display(), missing.display())).unwrap();
+        assert_eq!(value, "hunter2");
+        assert_eq!(paths, std::slice::from_ref(&present));
+
+        let (value, _) = read(format!(
+            "{}|{}:-fallback",
+            missing.display(),
+            missing.display()
+        ))
+        .unwrap();
+        assert_eq!(value, "fallback");
+
+        let err = read(format!("{}|{}", missing.display(), dir.path().display())).unwrap_err();
+        assert!(err.to_string().starts_with("error reading files "), "{err}");
+        assert!(
+            err.to_string().contains(&missing.display().to_string()),
+            "{err}"
+        );
+
+        let err = read(missing.display().to_string()).unwrap_err();
+        assert!(err.to_string().starts_with("error reading file "), "{err}");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn file_value() {
+        #[derive(Deserialize)]
+        struct Config {
+            plain: FileValue<String>,
+            password: FileValue<String>,
+            nested: FileValue<Nested>,
+            missing: FileValue<String>,
+        }
+
+        #[derive(Deserialize)]
+        struct Nested {
+            inner: FileValue<String>,
+            other: String,
+        }
+
+        let first = NamedTempFile::new().unwrap();
+        fs::write(first.path(), "hunter2").unwrap();
+        let second = NamedTempFile::new().unwrap();
+        fs::write(second.path(), "swordfish").unwrap();
+
+        let config = format!(
+            r#"{{
+                "plain": "admin",
+                "password": "${{file:{}}}",
+                "nested": {{"inner": "${{file:{}}}", "other": "${{file:{}}}"}},
+                "missing": "${{file:/does/not/exist:-default}}"
+            }}"#,
+            second.path().display(),
+            first.path().display(),
+            second.path().display(),
+        );
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let config = deserialize::<_, _, Config>(&mut deserializer, |_, _| ()).unwrap();
+
+        assert_eq!(*config.plain, "admin");
+        assert_eq!(config.plain.path(), None);
+        assert_eq!(*config.password, "swordfish");
+        assert_eq!(config.password.path(), Some(second.path()));
+        assert_eq!(*config.nested.inner, "hunter2");
+        assert_eq!(config.nested.inner.path(), Some(first.path()));
+        assert_eq!(config.nested.other, "swordfish");
+        assert_eq!(config.nested.path(), Some(first.path()));
+        assert_eq!(*config.missing, "default");
+        assert_eq!(config.missing.path(), None);
+
+        let config = format!(r#""${{file:{}}}""#, first.path().display());
+        let value: FileValue<String> = serde_json::from_str(&config).unwrap();
+        assert_eq!(value.path(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn flatten() {
+        #[derive(Deserialize)]
+        struct Config {
+            name: String,
+            #[serde(flatten)]
+            credentials: Credentials,
+            #[serde(flatten)]
+            extra: HashMap<String, String>,
+        }
+
+        #[derive(Deserialize)]
+        struct Credentials {
+            password: String,
+            token: Vec<u8>,
+        }
+
+        let password = NamedTempFile::new().unwrap();
+        fs::write(password.path(), "hunter2").unwrap();
+        let extra = NamedTempFile::new().unwrap();
+        fs::write(extra.path(), "swordfish").unwrap();
+
+        let config = format!(
+            r#"{{
+                "name": "admin",
+                "password": "${{file:{}}}",
+                "token": [1, 2],
+                "api_key": "${{file:{}}}"
+            }}"#,
+            password.path().display(),
+            extra.path().display(),
+        );
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let config = deserialize::<_, _, Config>(&mut deserializer, |_, _| ()).unwrap();
+
+        assert_eq!(config.name, "admin");
+        assert_eq!(config.credentials.password, "hunter2");
+        assert_eq!(config.credentials.token, [1, 2]);
+        assert_eq!(config.extra.len(), 1);
+        assert_eq!(config.extra["api_key"], "swordfish");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn file_value_options() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2\n").unwrap();
+
+        let options = FileValueOptions::new()
+            .trim_trailing_newline(true)
+            .inline(true);
+        let strict = options.clone().max_file_size(1);
+
+        let config = format!(r#""password=${{file:{}}}""#, file.path().display());
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        for _ in 0..2 {
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let value = String::deserialize(options.wrap(&mut deserializer, &mut cb)).unwrap();
+            assert_eq!(value, "password=hunter2");
+        }
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        String::deserialize(strict.wrap(&mut deserializer, &mut cb)).unwrap_err();
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value =
+            String::deserialize(FileValueOptions::default().wrap(&mut deserializer, &mut cb))
+                .unwrap();
+        assert_eq!(value, config.trim_matches('"'));
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    fn tracing() {
+        use std::{
+            fmt::Debug,
+            sync::{Arc, Mutex},
+        };
+        use tracing::{
+            field::{Field, Visit},
+            span, Event, Metadata, Subscriber,
+        };
+
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+
+        impl Visit for Recorder {
+            fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                self.0.lock().unwrap().push(format!("{field}={value:?}"));
+            }
+        }
+
+        struct Collector(Arc<Mutex<Vec<String>>>);
+
+        impl Subscriber for Collector {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("span={}", attrs.metadata().name()));
+                span::Id::from_u64(1)
+            }
+
+            fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                event.record(&mut Recorder(self.0.clone()));
+            }
+
+            fn enter(&self, _: &span::Id) {}
+
+            fn exit(&self, _: &span::Id) {}
+        }
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let config = format!(
+            r#"["${{file:{}}}", "${{file:/does/not/exist}}"]"#,
+            file.path().display()
+        );
+        let records = Arc::new(Mutex::new(vec![]));
+        tracing::subscriber::with_default(Collector(records.clone()), || {
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            deserialize::<_, _, Vec<String>>(&mut deserializer, |_, _| ()).unwrap_err();
+        });
+
+        let records = records.lock().unwrap();
+        assert_eq!(records[0], "span=serde_file_value::deserialize");
+        assert!(records.contains(&format!("path={}", file.path().display())));
+        assert!(records.contains(&"bytes=7".to_string()));
+        assert!(records.contains(&"path=/does/not/exist".to_string()));
+        assert!(records.iter().any(|r| r.starts_with("error=")));
+        assert!(!records.iter().any(|r| r.contains("hunter2")));
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn typed_scalars() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Config {
+            port: u16,
+            debug: bool,
+            ratio: f64,
+            offset: Option<i32>,
+            separator: char,
+            plain: u32,
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        for (name, contents) in [
+            ("port", "8080\n"),
+            ("debug", "true"),
+            ("ratio", " 0.5 "),
+            ("offset", "-3"),
+            ("separator", ","),
+            ("invalid", "80a0"),
+        ] {
+            fs::write(dir.path().join(name), contents).unwrap();
+        }
+        let dir = dir.path().display();
+
+        let config = format!(
+            r#"{{
+                "port": "${{file:{dir}/port}}",
+                "debug": "${{file:{dir}/debug}}",
+                "ratio": "${{file:{dir}/ratio}}",
+                "offset": "${{file:{dir}/offset}}",
+                "separator": "${{file:{dir}/separator}}",
+                "plain": 5
+            }}"#
+        );
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let value =
+            Config::deserialize(Deserializer::new(&mut deserializer, &mut cb).typed_scalars(true))
+                .unwrap();
+        assert_eq!(
+            value,
+            Config {
+                port: 8080,
+                debug: true,
+                ratio: 0.5,
+                offset: Some(-3),
+                separator: ',',
+                plain: 5,
+            }
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        Config::deserialize(Deserializer::new(&mut deserializer, &mut cb)).unwrap_err();
+
+        let config = format!(r#""${{file:{dir}/invalid}}""#);
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let err =
+            u16::deserialize(Deserializer::new(&mut deserializer, &mut cb).typed_scalars(true))
+                .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("error parsing referenced value as u16: invalid digit"),
+            "{err}"
+        );
+        assert!(!err.to_string().contains("80a0"), "{err}");
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#""8080""#);
+        u16::deserialize(Deserializer::new(&mut deserializer, &mut cb).typed_scalars(true))
+            .unwrap_err();
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn json_pointer() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(
+            file.path(),
+            r#"{"db": {"password": "hunter2", "port": 5432}, "a/b": [true, null], "~": "tilde"}"#,
+        )
+        .unwrap();
+
+        let read = |pointer: &str| {
+            let config = format!(r#""${{file:{}#{pointer}}}""#, file.path().display());
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            deserialize::<_, _, String>(&mut deserializer, |_, _| ())
+        };
+
+        assert_eq!(read("/db/password").unwrap(), "hunter2");
+        assert_eq!(read("/db/port").unwrap(), "5432");
+        assert_eq!(read("/a~1b/0").unwrap(), "true");
+        assert_eq!(read("/~0").unwrap(), "tilde");
+        assert_eq!(read("/db/password;encoding=latin1").unwrap(), "hunter2");
+
+        let err = read("/db/user").unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("JSON pointer /db/user not found in file"),
+            "{err}"
+        );
+        let err = read("/db").unwrap_err();
+        assert!(
+            err.to_string().contains("does not refer to a string"),
+            "{err}"
+        );
+        read("/a~1b/1").unwrap_err();
+
+        let invalid = NamedTempFile::new().unwrap();
+        fs::write(invalid.path(), "hunter2").unwrap();
+        let config = format!(r#""${{file:{}#/password}}""#, invalid.path().display());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let err = deserialize::<_, _, String>(&mut deserializer, |_, _| ()).unwrap_err();
+        assert!(err.to_string().contains("as JSON"), "{err}");
+        assert!(!err.to_string().contains("hunter2"), "{err}");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn strip_bom() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), b"\xef\xbb\xbfhunter2\n").unwrap();
+
+        let config = format!(r#""${{file:{}}}""#, file.path().display());
+        let mut read = vec![];
+        let mut cb = |_: &Path, r: &io::Result<Vec<u8>>| read.push(r.as_ref().unwrap().clone());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value = String::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb)
+                .strip_bom(true)
+                .trim(true),
+        )
+        .unwrap();
+        assert_eq!(value, "hunter2");
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value = String::deserialize(Deserializer::new(&mut deserializer, &mut cb)).unwrap();
+        assert_eq!(value, "\u{feff}hunter2\n");
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value =
+            Bytes::deserialize(Deserializer::new(&mut deserializer, &mut cb).strip_bom(true))
+                .unwrap();
+        assert_eq!(value.0, b"\xef\xbb\xbfhunter2\n");
+
+        assert_eq!(read, [b"\xef\xbb\xbfhunter2\n"; 3]);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn path_redaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret-infrastructure");
+
+        let config = format!(r#""${{file:{}}}""#, path.display());
+        let read = |path_redaction| {
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let mut paths = vec![];
+            let mut cb = |path: &Path, _: &io::Result<Vec<u8>>| paths.push(path.to_path_buf());
+            let err = String::deserialize(
+                Deserializer::new(&mut deserializer, &mut cb).path_redaction(path_redaction),
+            )
+            .unwrap_err();
+            assert_eq!(paths, std::slice::from_ref(&path));
+            err.to_string()
+        };
+
+        let err = read(PathRedaction::Disabled);
+        assert!(err.contains("secret-infrastructure"), "{err}");
+
+        let err = read(PathRedaction::Hidden);
+        assert!(err.starts_with("error reading file <redacted>: "), "{err}");
+
+        let err = read(PathRedaction::Hashed);
+        assert!(err.starts_with("error reading file <path:"), "{err}");
+        assert!(!err.contains("secret-infrastructure"), "{err}");
+        assert_eq!(err, read(PathRedaction::Hashed));
+
+        fs::write(&path, "hunter2").unwrap();
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let err = Deserializer::new(&mut deserializer, &mut cb)
+            .allowed_root(dir.path().join("root"))
+            .path_redaction(PathRedaction::Hidden)
+            .deserialize_with_error::<String>()
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("is outside of the allowed roots"),
+            "{err}"
+        );
+        assert!(!err.to_string().contains("secret-infrastructure"), "{err}");
+        assert_eq!(err.path(), Some(&*path));
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn path_redaction_references() {
+        fn check(err: impl std::fmt::Display, message: &str) {
+            let err = err.to_string();
+            assert!(err.starts_with(message), "{err}");
+            assert!(!err.contains("secret-infrastructure"), "{err}");
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("secret-infrastructure-a");
+        let b = dir.path().join("secret-infrastructure-b");
+        fs::write(&a, format!("${{file:{}}}", b.display())).unwrap();
+        fs::write(&b, format!("${{file:{}}}", a.display())).unwrap();
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+
+        let cycle = format!("\"${{file:{}}}\"", a.display());
+        let mut deserializer = serde_json::Deserializer::from_str(&cycle);
+        let err = String::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb)
+                .recursive(true)
+                .path_redaction(PathRedaction::Hidden),
+        )
+        .unwrap_err();
+        check(
+            err,
+            "reference cycle detected: <redacted> -> <redacted> -> <redacted>",
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&cycle);
+        let err = String::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb)
+                .recursive(true)
+                .recursion_limit(1)
+                .path_redaction(PathRedaction::Hidden),
+        )
+        .unwrap_err();
+        check(
+            err,
+            "recursion limit of 1 exceeded: <redacted> -> <redacted>",
+        );
+
+        let config = r#""${file:/secret-infrastructure/$SERDE_FILE_VALUE_TEST_UNSET/token}""#;
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let err = String::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb)
+                .path_env_vars(true)
+                .path_redaction(PathRedaction::Hidden),
+        )
+        .unwrap_err();
+        check(err, "error expanding variables in path <redacted>: ");
+
+        let config = r#""${file:/secret-infrastructure/token""#;
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let err = String::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb)
+                .strict(true)
+                .path_redaction(PathRedaction::Hidden),
+        )
+        .unwrap_err();
+        check(err, "malformed reference <redacted>: ");
+
+        let config = r#""${fd:/secret-infrastructure/token}""#;
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let err = String::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb)
+                .fd_references(true)
+                .path_redaction(PathRedaction::Hidden),
+        )
+        .unwrap_err();
+        check(
+            err,
+            "invalid file descriptor `<redacted>` in reference <redacted>",
+        );
+
+        for (scheme, message) in [
+            ("file-json", "structured file reference"),
+            ("file-lines", "file lines reference"),
+            ("file-dotenv", "dotenv file reference"),
+        ] {
+            let config = format!(r#""x ${{{scheme}:/secret-infrastructure/token}}""#);
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let err = String::deserialize(
+                Deserializer::new(&mut deserializer, &mut cb)
+                    .inline(true)
+                    .format("json", Json)
+                    .file_lines(true)
+                    .dotenv_files(true)
+                    .path_redaction(PathRedaction::Hidden),
+            )
+            .unwrap_err();
+            check(
+                err,
+                &format!("{message} <redacted> must make up the entire value"),
+            );
+        }
+
+        let mut deserializer = serde_json::Deserializer::from_str(&cycle);
+        let err = String::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb)
+                .recursive(true)
+                .path_redaction(PathRedaction::Hashed),
+        )
+        .unwrap_err();
+        check(err, "reference cycle detected: <reference:");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn tuples() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        enum Credentials {
+            Basic(String, String),
+        }
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Pair(String, String);
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Config {
+            array: [String; 3],
+            tuple: (String, String),
+            tuple_struct: Pair,
+            variant: Credentials,
+        }
+
+        let first = NamedTempFile::new().unwrap();
+        fs::write(first.path(), "hunter2").unwrap();
+        let second = NamedTempFile::new().unwrap();
+        fs::write(second.path(), "swordfish").unwrap();
+        let first = format!("${{file:{}}}", first.path().display());
+        let second = format!("${{file:{}}}", second.path().display());
+
+        let input = format!(
+            r#"{{
+                "array": ["{first}", "plain", "{second}"],
+                "tuple": ["{second}", "{first}"],
+                "tuple_struct": ["{first}", "{second}"],
+                "variant": {{"Basic": ["admin", "{first}"]}}
+            }}"#
+        );
+        let mut deserializer = serde_json::Deserializer::from_str(&input);
+        let config = deserialize::<_, _, Config>(&mut deserializer, |_, _| ()).unwrap();
+
+        let expected = Config {
+            array: [
+                "hunter2".to_string(),
+                "plain".to_string(),
+                "swordfish".to_string(),
+            ],
+            tuple: ("swordfish".to_string(), "hunter2".to_string()),
+            tuple_struct: Pair("hunter2".to_string(), "swordfish".to_string()),
+            variant: Credentials::Basic("admin".to_string(), "hunter2".to_string()),
+        };
+        assert_eq!(config, expected);
+
+        // tuples are deserialized through `deserialize_any` when structured formats are registered
+        let mut deserializer = serde_json::Deserializer::from_str(&input);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let config =
+            Config::deserialize(Deserializer::new(&mut deserializer, &mut cb).format("json", Json))
+                .unwrap();
+        assert_eq!(config, expected);
+    }
+
+    #[test]
+    fn enabled() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Config {
+            password: String,
+            escaped: String,
+            key: Option<String>,
+        }
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let password = format!("${{file:{}}}", file.path().display());
+        let config = format!(
+            r#"{{
+                "password": "{password}",
+                "escaped": "\\${{env:HOME}}",
+                "key": "${{file:/does/not/exist}}"
+            }}"#
+        );
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut calls = 0;
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| calls += 1;
+        let value = Config::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb)
+                .enabled(false)
+                .missing_as_none(true)
+                .prefetch([file.path().to_path_buf()]),
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            Config {
+                password,
+                escaped: r"\${env:HOME}".to_string(),
+                key: Some("${file:/does/not/exist}".to_string()),
+            }
+        );
+        assert_eq!(calls, 0);
+    }
+
+    #[test]
+    fn scan_references() {
+        let config = r#"{
+            "password": "${file:/does/not/exist}",
+            "nested": [{"host": "${env:MISSING_HOST}", "port": "${file:/port:-80}"}],
+            "url": "https://${file:/host}/",
+            "${file:/key}": "escaped \\${file:/escaped}",
+            "again": "${file:/does/not/exist}",
+            "unknown": "${other:value}"
+        }"#;
+
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let references = crate::scan_references(&mut deserializer).unwrap();
+        assert_eq!(
+            references,
+            [
+                "${file:/does/not/exist}",
+                "${env:MISSING_HOST}",
+                "${file:/port:-80}",
+                "${file:/does/not/exist}",
+            ]
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| panic!("file read");
+        let references = Deserializer::new(&mut deserializer, &mut cb)
+            .expand_keys(true)
+            .inline(true)
+            .scan_references()
+            .unwrap();
+        assert_eq!(references[3], "${file:/host}");
+        assert_eq!(references[4], "${file:/key}");
+        assert_eq!(references.len(), 6);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn dotenv_files() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Config {
+            env: HashMap<String, String>,
+            database: Database,
+            defaults: HashMap<String, String>,
+        }
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Database {
+            #[serde(rename = "DB_URL")]
+            url: String,
+        }
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(
+            file.path(),
+            r#"# credentials
+export USER = admin
+
+TOKEN=abc=def
+SINGLE='it''s \n literal'
+DOUBLE="line\none \"quoted\" \d"
+PLAIN=a # not a comment
+DB_URL=postgres://db
+"#,
+        )
+        .unwrap();
+
+        let config = format!(
+            r#"{{
+                "env": "${{file-dotenv:{path}}}",
+                "database": "${{file-dotenv:{path}}}",
+                "defaults": "${{file-dotenv:/does/not/exist:-A=1}}"
+            }}"#,
+            path = file.path().display(),
+        );
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let value = Config::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb)
+                .dotenv_files(true)
+                .cache(true),
+        )
+        .unwrap();
+
+        let env = [
+            ("USER", "admin"),
+            ("TOKEN", "abc=def"),
+            ("SINGLE", r"it''s \n literal"),
+            ("DOUBLE", "line\none \"quoted\" \\d"),
+            ("PLAIN", "a # not a comment"),
+            ("DB_URL", "postgres://db"),
+        ];
+        assert_eq!(
+            value,
+            Config {
+                env: env
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+                database: Database {
+                    url: "postgres://db".to_string(),
+                },
+                defaults: HashMap::from([("A".to_string(), "1".to_string())]),
+            }
+        );
+
+        for contents in ["KEY", "=value", "KEY=\"unterminated", "KEY='a' b"] {
+            fs::write(file.path(), contents).unwrap();
+            let config = format!(r#""${{file-dotenv:{}}}""#, file.path().display());
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let err = HashMap::<String, String>::deserialize(
+                Deserializer::new(&mut deserializer, &mut cb).dotenv_files(true),
+            )
+            .unwrap_err();
+            assert!(
+                err.to_string().starts_with("error parsing dotenv file"),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn shared_options() {
+        use std::{sync::Arc, thread};
+
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<FileValueOptions>();
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2\n").unwrap();
+
+        let options = Arc::new(FileValueOptions::new().trim_trailing_newline(true));
+        let config = format!(r#""${{file:{}}}""#, file.path().display());
+
+        let handles = (0..4)
+            .map(|_| {
+                let options = options.clone();
+                let config = config.clone();
+                thread::spawn(move || {
+                    let mut deserializer = serde_json::Deserializer::from_str(&config);
+                    let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+                    String::deserialize(Deserializer::with_options(
+                        &mut deserializer,
+                        &mut cb,
+                        options,
+                    ))
+                    .unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), "hunter2");
+        }
+
+        // configuring a copy leaves the shared options unchanged
+        let strict = (*options).clone().max_file_size(1);
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        String::deserialize(strict.wrap(&mut deserializer, &mut cb)).unwrap_err();
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value = String::deserialize(options.wrap(&mut deserializer, &mut cb)).unwrap();
+        assert_eq!(value, "hunter2");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn stdin_file() {
+        use std::{
+            env,
+            io::Write,
+            process::{Command, Stdio},
+        };
+
+        // standard input is read in a child process running this test, so that its contents can
+        // be controlled
+        if env::var_os("SERDE_FILE_VALUE_STDIN_TEST").is_some() {
+            // the rejected read consumes only the start of the first line
+            let mut deserializer = serde_json::Deserializer::from_str(r#""${file:-}""#);
+            let err = String::deserialize(
+                Deserializer::new(&mut deserializer, &mut |_, _| {})
+                    .stdin_file(true)
+                    .max_total_bytes(4),
+            )
+            .unwrap_err();
+            assert!(err.to_string().contains("total size"), "{err}");
+
+            let mut paths = vec![];
+            let mut deserializer = serde_json::Deserializer::from_str(r#""${file:-#L2}""#);
+            let value = String::deserialize(
+                Deserializer::new(&mut deserializer, &mut |path: &Path, _: &_| {
+                    paths.push(path.to_path_buf())
+                })
+                .stdin_file(true),
+            )
+            .unwrap();
+            assert_eq!(value, "swordfish");
+            assert_eq!(paths, [Path::new("-")]);
+
+            let mut deserializer =
+                serde_json::Deserializer::from_str(r#"["${file:-}", "${file:-}"]"#);
+            let err = Vec::<String>::deserialize(
+                Deserializer::new(&mut deserializer, &mut |_, _| {}).stdin_file(true),
+            )
+            .unwrap_err();
+            assert!(err.to_string().contains("only be referenced once"), "{err}");
+            return;
+        }
+
+        let mut child = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "test::stdin_file", "--test-threads=1"])
+            .env("SERDE_FILE_VALUE_STDIN_TEST", "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(b"hunter2\nswordfish\n")
+            .unwrap();
+        assert!(child.wait().unwrap().success());
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#""${file:-}""#);
+        let references = Deserializer::new(&mut deserializer, &mut |_, _| {})
+            .stdin_file(true)
+            .collect_references()
+            .unwrap();
+        assert!(references.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn join_lines() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), " abc123\r\ndef456\nghi789 \n").unwrap();
+
+        let config = format!(
+            r#"["${{file:{0}}}", "${{file:{0}}}"]"#,
+            file.path().display()
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb).join_lines(true);
+        let value = <(String, Bytes)>::deserialize(deserializer).unwrap();
+        assert_eq!(value.0, " abc123def456ghi789 ");
+        assert_eq!(value.1, Bytes(b" abc123\r\ndef456\nghi789 \n".to_vec()));
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+            .join_lines(true)
+            .trim(true);
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+        assert_eq!(value, ["abc123def456ghi789", "abc123def456ghi789"]);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn serde_bytes() {
+        #[derive(Deserialize)]
+        struct Config {
+            key: serde_bytes::ByteBuf,
+            #[serde(with = "serde_bytes")]
+            cert: Vec<u8>,
+        }
+
+        let contents = b"\x00\xff\xfe\r\n\x80binary";
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), contents).unwrap();
+
+        let config = format!(
+            r#"{{"key": "${{file:{0}}}", "cert": "${{file:{0}}}"}}"#,
+            file.path().display(),
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let config =
+            Config::deserialize(Deserializer::new(&mut deserializer, &mut cb).trim(true)).unwrap();
+        assert_eq!(config.key, contents);
+        assert_eq!(config.cert, contents);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn utf8_error_handler() {
+        let valid = NamedTempFile::new().unwrap();
+        fs::write(valid.path(), "hunter2").unwrap();
+        let invalid = NamedTempFile::new().unwrap();
+        fs::write(invalid.path(), b" caf\xe9\n").unwrap();
+
+        let config = format!(
+            r#"["${{file:{}}}", "${{file:{}}}"]"#,
+            valid.path().display(),
+            invalid.path().display(),
+        );
+
+        let mut paths = vec![];
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+            .trim(true)
+            .utf8_error_handler(move |path, contents| {
+                paths.push(path.to_path_buf());
+                assert_eq!(paths.len(), 1);
+                Ok(contents.iter().map(|&b| b as char).collect())
+            });
+        let value = Vec::<String>::deserialize(deserializer).unwrap();
+        assert_eq!(value, ["hunter2", "café"]);
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+            .utf8_error_handler(|_, _| Err("unknown encoding".to_string()));
+        let err = Vec::<String>::deserialize(deserializer).unwrap_err();
+        assert!(err.to_string().contains("unknown encoding"), "{err}");
+    }
+
+    #[test]
+    #[cfg(all(feature = "fs", feature = "json", feature = "toml", feature = "yaml"))]
+    fn deserialize_path() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Config {
+            name: String,
+            password: String,
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("secrets")).unwrap();
+        fs::write(dir.path().join("secrets/password"), "hunter2").unwrap();
+
+        let expected = Config {
+            name: "admin".to_string(),
+            password: "hunter2".to_string(),
+        };
+        let configs = [
+            (
+                "config.json",
+                r#"{"name": "admin", "password": "${file:secrets/password}"}"#,
+            ),
+            (
+                "config.yml",
+                "name: admin\npassword: ${file:secrets/password}\n",
+            ),
+            (
+                "config.toml",
+                "name = \"admin\"\npassword = \"${file:secrets/password}\"\n",
+            ),
+        ];
+        for (name, contents) in configs {
+            let path = dir.path().join(name);
+            fs::write(&path, contents).unwrap();
+
+            let mut paths = vec![];
+            let config = super::deserialize_path::<_, _, Config>(&path, |path, _| {
+                paths.push(path.to_path_buf())
+            })
+            .unwrap();
+            assert_eq!(config, expected);
+            assert_eq!(paths, [dir.path().join("secrets/password")]);
+        }
+
+        let path = dir.path().join("config.ini");
+        fs::write(&path, "name = admin").unwrap();
+        let err = super::deserialize_path::<_, _, Config>(&path, |_, _| ()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let path = dir.path().join("invalid.json");
+        fs::write(&path, r#"{"name": "admin", "password": "${file:missing}"}"#).unwrap();
+        let err = super::deserialize_path::<_, _, Config>(&path, |_, _| ()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("invalid.json"), "{err}");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn optional_reference() {
+        let dir = tempfile::tempdir().unwrap();
+        let present = dir.path().join("present");
+        fs::write(&present, "hunter2").unwrap();
+        let missing = dir.path().join("missing");
+
+        let config = format!(
+            r#"["${{file?:{0}}}", "${{file?:{1}}}", "${{file?:{1}:-fallback}}"]"#,
+            present.display(),
+            missing.display(),
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value: Vec<String> = deserialize(&mut deserializer, |_, _| ()).unwrap();
+        assert_eq!(value, ["hunter2", "", "fallback"]);
+
+        // errors other than a missing file are still reported
+        let config = format!(r#""${{file?:{}}}""#, dir.path().display());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let err = deserialize::<_, _, String>(&mut deserializer, |_, _| ()).unwrap_err();
+        assert!(err.to_string().contains("error reading file"), "{err}");
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn recommended_root() {
+        struct RootListener(Vec<(PathBuf, PathBuf)>);
+
+        impl Listener for RootListener {
+            fn file_read(&mut self, _: &Path, _: &io::Result<Vec<u8>>) {}
+
+            fn outside_recommended_root(&mut self, path: &Path, root: &Path) {
+                self.0.push((path.to_path_buf(), root.to_path_buf()));
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("secrets");
+        fs::create_dir(&root).unwrap();
+        let inside = root.join("inside");
+        fs::write(&inside, "hunter2").unwrap();
+        fs::write(dir.path().join("outside"), "hunter3").unwrap();
+
+        // an escape via `..` is detected
+        let escaped = root.join("../outside");
+        let config = format!(
+            r#"["${{file:{}}}", "${{file:{}}}"]"#,
+            inside.display(),
+            escaped.display(),
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut listener = RootListener(vec![]);
+        let value = Vec::<String>::deserialize(
+            Deserializer::with_listener(&mut deserializer, &mut listener).recommended_root(&root),
+        )
+        .unwrap();
+        assert_eq!(value, ["hunter2", "hunter3"]);
+        assert_eq!(listener.0, [(escaped, root)]);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn read_retries() {
+        use std::{thread, time::Duration};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rotated");
+        let config = format!(r#""${{file:{}}}""#, path.display());
+
+        let writer = thread::spawn({
+            let path = path.clone();
+            move || {
+                thread::sleep(Duration::from_millis(50));
+                let staged = path.with_extension("tmp");
+                fs::write(&staged, "hunter2").unwrap();
+                fs::rename(&staged, &path).unwrap();
+            }
+        });
+
+        let mut results = vec![];
+        let mut cb = |_: &Path, r: &io::Result<Vec<u8>>| results.push(r.is_ok());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value = String::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb)
+                .read_retries(8)
+                .read_retry_backoff(Duration::from_millis(5)),
+        )
+        .unwrap();
+        writer.join().unwrap();
+        assert_eq!(value, "hunter2");
+        assert_eq!(results, [true]);
+
+        let missing = dir.path().join("missing");
+        let config = format!(r#""${{file:{}}}""#, missing.display());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let err = String::deserialize(
+            Deserializer::new(&mut deserializer, &mut |_, _| {})
+                .read_retries(2)
+                .read_retry_backoff(Duration::from_millis(1)),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("error reading file"), "{err}");
+
+        // missing files which have a fallback are not waited for
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+        let config = format!(
+            r#"["${{file:{0}:-default}}", "${{file?:{0}}}", "${{file:{0},{1}}}"]"#,
+            missing.display(),
+            file.path().display(),
+        );
+        let start = std::time::Instant::now();
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let value = Vec::<String>::deserialize(
+            Deserializer::new(&mut deserializer, &mut |_, _| {})
+                .candidate_separator(",")
+                .read_retries(8)
+                .read_retry_backoff(Duration::from_secs(1)),
+        )
+        .unwrap();
+        assert_eq!(value, ["default", "", "hunter2"]);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn any_reads() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "hunter2").unwrap();
+
+        let configs = [
+            (
+                r#"["plain", "${env:SERDE_FILE_VALUE_TEST_MISSING:-}"]"#.to_string(),
+                false,
+            ),
+            (format!(r#"["${{file:{}}}"]"#, file.path().display()), true),
+            (r#"["${file:/bogus:-bar}"]"#.to_string(), true),
+        ];
+        for (config, any_reads) in configs {
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let (_, stats) =
+                super::deserialize_with_stats::<_, _, Vec<String>>(&mut deserializer, |_, _| ())
+                    .unwrap();
+            assert_eq!(stats.any_reads(), any_reads, "{config}");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn unit_suffixes() {
+        let dir = tempfile::tempdir().unwrap();
+        let read = |contents: &str, unit_suffixes: bool| {
+            let path = dir.path().join("value");
+            fs::write(&path, contents).unwrap();
+            let config = format!(r#""${{file:{}}}""#, path.display());
+            let mut deserializer = serde_json::Deserializer::from_str(&config);
+            let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+            i32::deserialize(
+                Deserializer::new(&mut deserializer, &mut cb)
+                    .typed_scalars(true)
+                    .unit_suffixes(unit_suffixes),
+            )
+            .map_err(|e| e.to_string())
+        };
+
+        assert_eq!(read("256MiB\n", true), Ok(256 << 20));
+        assert_eq!(read("2 kB", true), Ok(2000));
+        assert_eq!(read("30 s", true), Ok(30));
+        assert_eq!(read("-2h", true), Ok(-7200));
+        assert_eq!(read("1024", true), Ok(1024));
+
+        let err = read("256MiB", false).unwrap_err();
+        assert!(err.contains("invalid digit"), "{err}");
+        let err = read("2GiB", true).unwrap_err();
+        assert!(err.contains("i32: value out of range"), "{err}");
+        let err = read("5ms", true).unwrap_err();
+        assert!(err.contains("i32: unknown unit suffix"), "{err}");
+        let err = read("1.5KiB", true).unwrap_err();
+        assert!(err.contains("i32:"), "{err}");
+    }
+
+    #[test]
+    fn resolver_chain() {
+        struct LayerResolver {
+            scheme: &'static str,
+            values: HashMap<&'static str, io::Result<&'static str>>,
+        }
+
+        impl Resolver for LayerResolver {
+            fn supports_scheme(&self, scheme: &str) -> bool {
+                scheme == self.scheme
+            }
+
+            fn resolve(&mut self, _: &str, arg: &str) -> io::Result<Vec<u8>> {
+                match self.values.remove(arg) {
+                    Some(Ok(value)) => Ok(value.as_bytes().to_vec()),
+                    Some(Err(e)) => Err(e),
+                    None => Err(io::Error::new(io::ErrorKind::NotFound, "not found")),
+                }
+            }
+        }
+
+        let denied = || Err(io::Error::new(io::ErrorKind::PermissionDenied, "denied"));
+        let deserialize = |config: &str| {
+            let local = LayerResolver {
+                scheme: "vault",
+                values: HashMap::from([("a", Ok("local")), ("c", denied())]),
+            };
+            let unrelated = LayerResolver {
+                scheme: "other",
+                values: HashMap::from([("b", Ok("unrelated"))]),
+            };
+            let remote = LayerResolver {
+                scheme: "vault",
+                values: HashMap::from([
+                    ("a", Ok("remote")),
+                    ("b", Ok("remote")),
+                    ("c", Ok("remote")),
+                ]),
+            };
+
+            let mut deserializer = serde_json::Deserializer::from_str(config);
+            let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+            let deserializer = Deserializer::new(&mut deserializer, &mut cb)
+                .resolver(local)
+                .resolver(unrelated)
+                .resolver(remote);
+            Vec::<String>::deserialize(deserializer).map_err(|e| e.to_string())
+        };
+
+        let value = deserialize(r#"["${vault:a}", "${vault:b}", "${vault:d:-default}"]"#);
+        assert_eq!(value.unwrap(), ["local", "remote", "default"]);
+
+        let err = deserialize(r#"["${vault:c:-default}"]"#).unwrap_err();
+        assert!(
+            err.contains("error resolving vault reference c: denied"),
+            "{err}"
+        );
+
+        let err = deserialize(r#"["${vault:d}"]"#).unwrap_err();
+        assert!(
+            err.contains("error resolving vault reference d: not found"),
+            "{err}"
+        );
+
+        // supports the scheme for a limited number of calls, so that it can stop supporting it
+        // between the reference being parsed and resolved
+        struct FickleResolver(Rc<Cell<usize>>);
+
+        impl Resolver for FickleResolver {
+            fn supports_scheme(&self, _: &str) -> bool {
+                let remaining = self.0.get();
+                self.0.set(remaining.saturating_sub(1));
+                remaining > 0
+            }
+
+            fn resolve(&mut self, _: &str, _: &str) -> io::Result<Vec<u8>> {
+                Err(io::Error::new(io::ErrorKind::NotFound, "not found"))
+            }
+        }
+
+        let read = |config: &str, remaining: &Rc<Cell<usize>>| {
+            let mut deserializer = serde_json::Deserializer::from_str(config);
+            let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+            String::deserialize(
+                Deserializer::new(&mut deserializer, &mut cb)
+                    .resolver(FickleResolver(remaining.clone())),
+            )
+            .map_err(|e| e.to_string())
+        };
+
+        // count the calls made while parsing and resolving the reference
+        let remaining = Rc::new(Cell::new(usize::MAX));
+        let err = read(r#""${fickle:a}""#, &remaining).unwrap_err();
+        assert!(
+            err.contains("error resolving fickle reference a: not found"),
+            "{err}"
+        );
+        let calls = usize::MAX - remaining.get();
+
+        remaining.set(calls - 1);
+        let err = read(r#""${fickle:a}""#, &remaining).unwrap_err();
+        assert!(
+            err.contains(
+                "error resolving fickle reference a: no resolver supports scheme `fickle`"
+            ),
+            "{err}"
+        );
+        remaining.set(calls - 1);
+        assert_eq!(
+            read(r#""${fickle:a:-default}""#, &remaining).unwrap(),
+            "default"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "test-util")]
+    fn mock_filesystem() {
+        let filesystem = MockFilesystem::new();
+        filesystem.insert("/secrets/a", "hunter2");
+        filesystem.insert_error("/secrets/b", io::ErrorKind::PermissionDenied);
+
+        let config = r#"["${file:/secrets/a}", "${file:/secrets/missing:-default}"]"#;
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let value = Vec::<String>::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb).filesystem(filesystem.clone()),
+        )
+        .unwrap();
+        assert_eq!(value, ["hunter2", "default"]);
+        assert_eq!(
+            filesystem.reads(),
+            [Path::new("/secrets/a"), Path::new("/secrets/missing")]
+        );
+
+        filesystem.clear_reads();
+        let mut errors = vec![];
+        let mut cb = |_: &Path, r: &io::Result<Vec<u8>>| {
+            errors.extend(r.as_ref().err().map(|e| e.kind()));
+        };
+        let mut deserializer = serde_json::Deserializer::from_str(r#""${file:/secrets/b:-x}""#);
+        String::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb).filesystem(filesystem.clone()),
+        )
+        .unwrap_err();
+        assert_eq!(errors, [io::ErrorKind::PermissionDenied]);
+        assert_eq!(filesystem.reads(), [Path::new("/secrets/b")]);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn path_redaction_read_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret-infrastructure");
+        fs::write(&path, "hunter2").unwrap();
+
+        // the read happens on a helper thread, which must use the same redaction policy
+        let config = format!(r#""${{file:{}}}""#, path.display());
+        let mut deserializer = serde_json::Deserializer::from_str(&config);
+        let mut cb = |_: &Path, _: &io::Result<Vec<u8>>| ();
+        let err = String::deserialize(
+            Deserializer::new(&mut deserializer, &mut cb)
+                .read_timeout(std::time::Duration::from_secs(10))
+                .allowed_root(dir.path().join("root"))
+                .path_redaction(PathRedaction::Hidden),
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("<redacted> is outside of the allowed roots"),
+            "{err}"
+        );
+        assert!(!err.to_string().contains("secret-infrastructure"), "{err}");
+    }
+
+    #[test]
+    fn prefetch_before_file_read() {
+        use std::sync::{Arc, Mutex};
+
+        struct RecordingFilesystem(Arc<Mutex<Vec<PathBuf>>>);
+
+        impl Filesystem for RecordingFilesystem {
+            fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+                self.0.lock().unwrap().push(path.to_path_buf());
+                Ok(b"hunter2".to_vec())
+            }
+        }
+
+        struct DenyListener;
+
+        impl Listener for DenyListener {
+            fn before_file_read(&mut self, path: &Path) -> Result<(), String> {
+                if path == Path::new("/denied") {
+                    Err("access denied by policy".to_string())
+                } else {
+                    Ok(())
+                }
+            }
+
+            fn file_read(&mut self, _: &Path, _: &io::Result<Vec<u8>>) {}
+        }
+
+        let reads = Arc::new(Mutex::new(vec![]));
+        let config = r#"["${file:/allowed}", "${file:/denied}"]"#;
+        let mut deserializer = serde_json::Deserializer::from_str(config);
+        let err = Deserializer::with_listener(&mut deserializer, &mut DenyListener)
+            .filesystem(RecordingFilesystem(reads.clone()))
+            .prefetch([PathBuf::from("/allowed"), PathBuf::from("/denied")])
+            .deserialize_with_error::<Vec<String>>()
+            .unwrap_err();
+        assert!(err.to_string().contains("access denied by policy"), "{err}");
+        assert_eq!(*reads.lock().unwrap(), [PathBuf::from("/allowed")]);
+    }
 }
@@ -0,0 +1,209 @@
+use std::{env, fs::Metadata, io, path::Path};
+
+/// A listener notified of the values read by a [`Deserializer`](crate::Deserializer).
+///
+/// This is implemented for all `FnMut(&Path, &io::Result<Vec<u8>>)` closures, which are notified of
+/// file reads only. Implementing it on a type instead allows the listener to own any state it
+/// needs, such as a logger or metrics handle, and to observe events other than file reads.
+///
+/// # Examples
+///
+/// ```
+/// use std::{io, path::{Path, PathBuf}};
+///
+/// use serde::Deserialize;
+/// use serde_file_value::{Deserializer, Listener};
+///
+/// #[derive(Default)]
+/// struct Metrics {
+///     reads: usize,
+///     failures: Vec<PathBuf>,
+/// }
+///
+/// impl Listener for Metrics {
+///     fn file_read(&mut self, path: &Path, result: &io::Result<Vec<u8>>) {
+///         self.reads += 1;
+///         if result.is_err() {
+///             self.failures.push(path.to_path_buf());
+///         }
+///     }
+/// }
+///
+/// # if !cfg!(feature = "fs") { return; }
+/// let config = r#"["${file:/does/not/exist:-default}"]"#;
+///
+/// let mut metrics = Metrics::default();
+/// let mut deserializer = serde_json::Deserializer::from_str(config);
+/// let deserializer = Deserializer::with_listener(&mut deserializer, &mut metrics);
+/// let value = Vec::<String>::deserialize(deserializer).unwrap();
+///
+/// assert_eq!(value, ["default"]);
+/// assert_eq!(metrics.reads, 1);
+/// assert_eq!(metrics.failures, [Path::new("/does/not/exist")]);
+/// ```
+pub trait Listener {
+    /// Called before every referenced file read.
+    ///
+    /// If an error is returned, the file will not be read and deserialization will fail with the
+    /// provided message. [`Listener::file_read`] is not called for rejected reads.
+    ///
+    /// The default implementation allows all reads.
+    fn before_file_read(&mut self, path: &Path) -> Result<(), String> {
+        let _ = path;
+        Ok(())
+    }
+
+    /// Called on every file reference with the text of the reference and the path of the file it
+    /// refers to.
+    ///
+    /// The `reference` is the reference exactly as it appeared in the input, like
+    /// `${file:token;encoding=latin1:-default}`, including its delimiters, parameters, selection,
+    /// and default value. The `path` is the file which will be read after any processing of the
+    /// reference's path, such as resolution against
+    /// [`Deserializer::base_dir`](crate::Deserializer::base_dir) and expansion of
+    /// [environment variables](crate::Deserializer::path_env_vars). It is the same path passed to
+    /// the other methods for the reference, such as [`Listener::file_read`], and is called before
+    /// them. In [inline](crate::Deserializer::inline) values, `reference` is only the portion of
+    /// the value making up the reference.
+    ///
+    /// This is called for all file references, including those served from the cache and those
+    /// in dry-run mode.
+    ///
+    /// The default implementation does nothing.
+    fn path_resolved(&mut self, reference: &str, path: &Path) {
+        let _ = (reference, path);
+    }
+
+    /// Called on every file reference in dry-run mode instead of reading the file.
+    ///
+    /// See [`Deserializer::dry_run`](crate::Deserializer::dry_run) for details.
+    ///
+    /// The default implementation does nothing.
+    fn file_referenced(&mut self, path: &Path) {
+        let _ = path;
+    }
+
+    /// Called on every referenced file read along with the result of the read.
+    fn file_read(&mut self, path: &Path, result: &io::Result<Vec<u8>>);
+
+    /// Called on every access to the contents of a referenced file, indicating whether the file was
+    /// read or its contents were taken from the cache.
+    ///
+    /// Unlike [`Listener::file_read`], which is only called when a file is actually read, this is
+    /// also called for cache hits, with the cached contents. For reads, it is called after
+    /// [`Listener::file_read`] with the same result. This can be used to measure the effectiveness
+    /// of [`Deserializer::cache`](crate::Deserializer::cache).
+    ///
+    /// The default implementation does nothing.
+    fn file_accessed(&mut self, path: &Path, access: Access, result: &io::Result<Vec<u8>>) {
+        let _ = (path, access, result);
+    }
+
+    /// Called after every successful file read with the metadata of the file.
+    ///
+    /// The metadata is taken from the same handle used to read the file, so it is consistent with
+    /// the contents passed to [`Listener::file_read`], which is called first. It is not called for
+    /// files which were not read from the filesystem, such as cache hits and files provided via
+    /// [`Deserializer::files`](crate::Deserializer::files).
+    ///
+    /// This can be used to fingerprint referenced files, for example by their modification time and
+    /// size, to detect when they change.
+    ///
+    /// The default implementation does nothing.
+    fn file_metadata(&mut self, path: &Path, metadata: &Metadata) {
+        let _ = (path, metadata);
+    }
+
+    /// Called after a file is read if it is readable by its group or by other users.
+    ///
+    /// This is only called when
+    /// [`Deserializer::warn_insecure_permissions`](crate::Deserializer::warn_insecure_permissions)
+    /// is enabled, and only on Unix platforms. The `mode` contains the file's permission bits. It
+    /// is called after [`Listener::file_metadata`], and the read proceeds as normal.
+    ///
+    /// The default implementation does nothing.
+    fn insecure_permissions(&mut self, path: &Path, mode: u32) {
+        let _ = (path, mode);
+    }
+
+    /// Called after a file is read if it is located outside of the recommended root.
+    ///
+    /// This is only called when
+    /// [`Deserializer::recommended_root`](crate::Deserializer::recommended_root) is set. The `root`
+    /// is the recommended root as configured. It is called after [`Listener::file_metadata`], and
+    /// the read proceeds as normal.
+    ///
+    /// The default implementation does nothing.
+    fn outside_recommended_root(&mut self, path: &Path, root: &Path) {
+        let _ = (path, root);
+    }
+
+    /// Called on every successfully resolved file reference along with its final value.
+    ///
+    /// Unlike [`Listener::file_read`], the value reflects all processing performed on the file's
+    /// contents, such as UTF-8 decoding and trimming. This is called after [`Listener::file_read`]
+    /// for the same reference, and is also called for references which did not trigger a read,
+    /// such as cache hits and default values.
+    ///
+    /// For a reference which makes up the entire value, this is exactly what is passed to the
+    /// target type. In [inline](crate::Deserializer::inline) or
+    /// [recursive](crate::Deserializer::recursive) mode, it is instead the intermediate value of
+    /// the individual file, before it is combined with the surrounding text or its own references
+    /// are resolved.
+    ///
+    /// The default implementation does nothing.
+    fn file_resolved(&mut self, path: &Path, value: Resolved<'_>) {
+        let _ = (path, value);
+    }
+
+    /// Called on every value which looks like a reference but could not be parsed as one.
+    ///
+    /// A value is considered to be a malformed reference if it starts with the opening delimiter
+    /// followed by the full name of a supported scheme, like `${file/path}` (missing the `:`) or
+    /// `${file:/path` (missing the closing delimiter). Values where the name only starts with a
+    /// supported scheme, like `${filename}`, are not reported. Unknown schemes can be rejected with
+    /// [`Deserializer::deny_unknown_schemes`](crate::Deserializer::deny_unknown_schemes).
+    /// The value is passed through unchanged, so this is purely advisory. `reason` is a
+    /// human-readable description of the problem.
+    ///
+    /// The default implementation does nothing.
+    fn malformed_reference(&mut self, value: &str, reason: &str) {
+        let _ = (value, reason);
+    }
+
+    /// Called on every referenced environment variable lookup along with the result of the lookup.
+    ///
+    /// The default implementation does nothing.
+    fn env_read(&mut self, name: &str, result: &Result<String, env::VarError>) {
+        let _ = (name, result);
+    }
+}
+
+/// How the contents of a referenced file were obtained.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Access {
+    /// The file was read.
+    Read,
+    /// The contents were taken from the cache enabled by
+    /// [`Deserializer::cache`](crate::Deserializer::cache).
+    CacheHit,
+}
+
+/// The final value of a resolved reference.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Resolved<'a> {
+    /// A value passed to the target type as a string.
+    String(&'a str),
+    /// A value passed to the target type as bytes.
+    Bytes(&'a [u8]),
+}
+
+impl<F> Listener for F
+where
+    F: FnMut(&Path, &io::Result<Vec<u8>>),
+{
+    fn file_read(&mut self, path: &Path, result: &io::Result<Vec<u8>>) {
+        self(path, result)
+    }
+}
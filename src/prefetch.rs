@@ -0,0 +1,44 @@
+use std::{
+    collections::HashMap,
+    fs::Metadata,
+    io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::expand::{self, Options};
+
+/// The results of prefetched file reads.
+pub(crate) type Prefetched = HashMap<PathBuf, io::Result<(Vec<u8>, Option<Metadata>)>>;
+
+/// Reads the files in parallel on the specified number of threads.
+pub(crate) fn prefetch(options: &Arc<Options>, paths: &[PathBuf], threads: usize) -> Prefetched {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(HashMap::new());
+
+    let worker = || {
+        while let Some(path) = paths.get(next.fetch_add(1, Ordering::Relaxed)) {
+            if options.files.contains_key(path) {
+                continue;
+            }
+
+            // whether a missing file should be waited for isn't known until it is referenced
+            let result = expand::read_checked(options, path, None, None, false);
+            results.lock().unwrap().insert(path.clone(), result);
+        }
+    };
+
+    let threads = threads.clamp(1, paths.len().max(1));
+    thread::scope(|s| {
+        for _ in 1..threads {
+            s.spawn(worker);
+        }
+        worker();
+    });
+
+    results.into_inner().unwrap()
+}
@@ -0,0 +1,128 @@
+use std::{collections::HashMap, io, path::Path};
+
+/// A resolver for custom reference schemes.
+///
+/// A resolver installed with [`Deserializer::resolver`](crate::Deserializer::resolver) is consulted
+/// for references like `${vault:secret/db}` whose scheme is not handled by the deserializer itself.
+/// The built-in `file` and `env` schemes are always handled by the deserializer.
+///
+/// The resolved value is treated like the contents of a file: values deserialized as bytes receive
+/// it directly, and all other values receive it decoded as UTF-8. A `NotFound` error causes the
+/// reference's default value to be used, if it has one.
+///
+/// # Chaining
+///
+/// Multiple resolvers can be installed to layer several sources under a single scheme, like local
+/// overrides in front of a remote secret store. A reference is resolved by trying each resolver
+/// which supports its scheme in the order they were installed, where the result of
+/// [`Resolver::resolve`] has one of three outcomes:
+///
+/// * `Ok` - the reference is resolved to the value, and no further resolvers are tried.
+/// * An error of kind [`io::ErrorKind::NotFound`] - the resolver does not have a value for the
+///   reference, and the next resolver is tried.
+/// * Any other error - resolution fails with the error, and no further resolvers are tried. The
+///   reference's default value is not used.
+///
+/// A resolver whose [`Resolver::supports_scheme`] returns `false` is skipped without being called.
+/// If every supporting resolver returns a `NotFound` error, the reference's default value is used
+/// if it has one, and otherwise resolution fails with the error returned by the last resolver.
+pub trait Resolver {
+    /// Determines if the resolver handles references with the specified scheme.
+    ///
+    /// References with unsupported schemes are passed through unchanged. This is called both
+    /// when a reference is parsed and when it is resolved, and if no resolver supports the scheme
+    /// by the time the reference is resolved, resolution fails with a
+    /// [`NotFound`](io::ErrorKind::NotFound) error.
+    ///
+    /// The default implementation supports all schemes.
+    fn supports_scheme(&self, scheme: &str) -> bool {
+        let _ = scheme;
+        true
+    }
+
+    /// Resolves a reference.
+    fn resolve(&mut self, scheme: &str, arg: &str) -> io::Result<Vec<u8>>;
+}
+
+/// A handler for a single reference scheme registered with
+/// [`Deserializer::scheme`](crate::Deserializer::scheme).
+pub(crate) type SchemeHandler = Box<dyn FnMut(&str) -> io::Result<Vec<u8>>>;
+
+/// A hook registered with [`Deserializer::transform`](crate::Deserializer::transform).
+pub(crate) type Transform = Box<dyn FnMut(&Path, String) -> Result<String, String>>;
+
+/// A handler registered with
+/// [`Deserializer::utf8_error_handler`](crate::Deserializer::utf8_error_handler).
+pub(crate) type Utf8ErrorHandler = Box<dyn FnMut(&Path, Vec<u8>) -> Result<String, String>>;
+
+/// The custom handlers of a deserializer.
+#[derive(Default)]
+pub(crate) struct Resolvers {
+    pub(crate) schemes: HashMap<String, SchemeHandler>,
+    pub(crate) chain: Vec<Box<dyn Resolver>>,
+    pub(crate) transform: Option<Transform>,
+    pub(crate) utf8_error_handler: Option<Utf8ErrorHandler>,
+}
+
+impl Resolvers {
+    /// Determines if a registered handler supports the scheme.
+    pub(crate) fn is_registered(&self, scheme: &str, ignore_case: bool) -> bool {
+        if ignore_case {
+            self.schemes.keys().any(|s| s.eq_ignore_ascii_case(scheme))
+        } else {
+            self.schemes.contains_key(scheme)
+        }
+    }
+
+    /// Determines if any resolver supports the scheme.
+    pub(crate) fn resolver_supports(&self, scheme: &str) -> bool {
+        self.chain.iter().any(|r| r.supports_scheme(scheme))
+    }
+
+    pub(crate) fn resolve(
+        &mut self,
+        scheme: &str,
+        arg: &str,
+        ignore_case: bool,
+    ) -> io::Result<Vec<u8>> {
+        let handler = if ignore_case {
+            self.schemes
+                .iter_mut()
+                .find(|(s, _)| s.eq_ignore_ascii_case(scheme))
+                .map(|(_, handler)| handler)
+        } else {
+            self.schemes.get_mut(scheme)
+        };
+        if let Some(handler) = handler {
+            return handler(arg);
+        }
+
+        let mut result = None;
+        for resolver in &mut self.chain {
+            if !resolver.supports_scheme(scheme) {
+                continue;
+            }
+            match resolver.resolve(scheme, arg) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => result = Some(Err(e)),
+                r => return r,
+            }
+        }
+        // a resolver may no longer support the scheme it supported when the reference was parsed
+        result.unwrap_or_else(|| {
+            Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no resolver supports scheme `{scheme}`"),
+            ))
+        })
+    }
+}
+
+/// Determines if a string is a valid scheme name.
+///
+/// Scheme names consist of ASCII letters, digits, `+`, `-`, `.`, and `_`.
+pub(crate) fn is_valid_scheme(scheme: &str) -> bool {
+    !scheme.is_empty()
+        && scheme
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'-' | b'.' | b'_'))
+}
@@ -0,0 +1,40 @@
+/// Statistics about the files read during deserialization.
+///
+/// Returned by
+/// [`Deserializer::deserialize_with_stats`](crate::Deserializer::deserialize_with_stats).
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub(crate) files_read: usize,
+    pub(crate) total_bytes: u64,
+    pub(crate) cache_hits: usize,
+    pub(crate) any_reads: bool,
+}
+
+impl Stats {
+    /// Returns the number of files successfully read.
+    ///
+    /// References served from the cache are not included.
+    pub fn files_read(&self) -> usize {
+        self.files_read
+    }
+
+    /// Returns the total size in bytes of the files successfully read.
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    /// Returns the number of references served from the cache rather than read.
+    pub fn cache_hits(&self) -> usize {
+        self.cache_hits
+    }
+
+    /// Returns `true` if any file was accessed.
+    ///
+    /// Unlike [`Stats::files_read`], this includes failed reads, such as those of missing files
+    /// replaced by a default value, and references served from the cache. It can be used to
+    /// determine whether the input referenced any files at all, for example to decide whether
+    /// they need to be watched for changes.
+    pub fn any_reads(&self) -> bool {
+        self.any_reads
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use crate::Filesystem;
+
+/// An in-memory [`Filesystem`] for tests which records every attempted read.
+///
+/// Files are served from memory, and reads of specific paths can be made to fail with a simulated
+/// IO error. Every read is recorded, whether or not it succeeds, so tests can check which files a
+/// configuration referenced without writing them to disk. Clones share the same files and log,
+/// so a clone can be installed with [`Deserializer::filesystem`](crate::Deserializer::filesystem)
+/// while the original is kept to inspect the reads afterwards.
+///
+/// This requires the `test-util` Cargo feature.
+///
+/// # Examples
+///
+/// ```
+/// use std::{io, path::Path};
+///
+/// use serde::Deserialize;
+/// use serde_file_value::{Deserializer, MockFilesystem};
+///
+/// #[derive(Deserialize)]
+/// struct Config {
+///     password: String,
+///     token: Option<String>,
+/// }
+///
+/// let filesystem = MockFilesystem::new();
+/// filesystem.insert("/secrets/password", "hunter2");
+/// filesystem.insert_error("/secrets/token", io::ErrorKind::PermissionDenied);
+///
+/// let config = r#"{"password": "${file:/secrets/password}", "token": "${file:/secrets/token}"}"#;
+/// let mut deserializer = serde_json::Deserializer::from_str(config);
+/// let mut listener = |_: &Path, _: &io::Result<Vec<u8>>| {};
+/// let result = Config::deserialize(
+///     Deserializer::new(&mut deserializer, &mut listener).filesystem(filesystem.clone()),
+/// );
+///
+/// assert!(result.is_err());
+/// assert_eq!(filesystem.reads(), [Path::new("/secrets/password"), Path::new("/secrets/token")]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockFilesystem {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    files: HashMap<PathBuf, Result<Vec<u8>, io::ErrorKind>>,
+    reads: Vec<PathBuf>,
+}
+
+impl MockFilesystem {
+    /// Creates a new filesystem with no files.
+    pub fn new() -> Self {
+        MockFilesystem::default()
+    }
+
+    /// Adds a file with the specified contents, replacing any existing file or error.
+    pub fn insert<P, C>(&self, path: P, contents: C)
+    where
+        P: AsRef<Path>,
+        C: AsRef<[u8]>,
+    {
+        self.lock()
+            .files
+            .insert(path.as_ref().to_path_buf(), Ok(contents.as_ref().to_vec()));
+    }
+
+    /// Makes reads of a path fail with an error of the specified kind, replacing any existing file.
+    pub fn insert_error<P>(&self, path: P, kind: io::ErrorKind)
+    where
+        P: AsRef<Path>,
+    {
+        self.lock()
+            .files
+            .insert(path.as_ref().to_path_buf(), Err(kind));
+    }
+
+    /// Removes a file or error.
+    pub fn remove<P>(&self, path: P)
+    where
+        P: AsRef<Path>,
+    {
+        self.lock().files.remove(path.as_ref());
+    }
+
+    /// Returns the paths of every attempted read in the order they occurred, including those which
+    /// failed.
+    pub fn reads(&self) -> Vec<PathBuf> {
+        self.lock().reads.clone()
+    }
+
+    /// Clears the log of attempted reads.
+    pub fn clear_reads(&self) {
+        self.lock().reads.clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Filesystem for MockFilesystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let mut inner = self.lock();
+        inner.reads.push(path.to_path_buf());
+        match inner.files.get(path) {
+            Some(Ok(contents)) => Ok(contents.clone()),
+            Some(Err(kind)) => Err(io::Error::new(*kind, "simulated error")),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "file not found")),
+        }
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        match self.lock().files.get(path) {
+            Some(Ok(_)) => Ok(true),
+            Some(Err(io::ErrorKind::NotFound)) | None => Ok(false),
+            Some(Err(kind)) => Err(io::Error::new(*kind, "simulated error")),
+        }
+    }
+}